@@ -2,9 +2,17 @@ use clap::{Parser, Subcommand};
 use ink_tool::AppResult;
 use std::path::PathBuf;
 
+pub(crate) mod check;
 pub(crate) mod fmt;
+pub(crate) mod graph;
+pub(crate) mod l10n;
 pub(crate) mod lsp;
+pub(crate) mod rename;
+pub(crate) mod stats;
+pub(crate) mod symbols;
+pub(crate) mod tags;
 pub(crate) mod test;
+pub(crate) mod watch;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -41,8 +49,16 @@ impl Default for LogLevel {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
+    Check(check::CheckOpt),
     Fmt(fmt::FmtOpt),
+    Graph(graph::GraphOpt),
+    L10n(l10n::L10nOpt),
     Lsp(lsp::LspOpt),
+    Refs(symbols::RefsOpt),
+    Rename(rename::RenameOpt),
+    Stats(stats::StatsOpt),
+    Symbols(symbols::SymbolsOpt),
+    Tags(tags::TagsOpt),
     Test(test::TestOpt),
 }
 
@@ -50,8 +66,16 @@ pub(crate) fn main() -> AppResult<()> {
     let args = Cli::parse();
     setup_logging(&args)?;
     match args.command {
+        Commands::Check(opt) => check::check(opt),
         Commands::Fmt(opt) => fmt::fmt(opt),
+        Commands::Graph(opt) => graph::graph(opt),
+        Commands::L10n(opt) => l10n::l10n(opt),
         Commands::Lsp(opt) => lsp::lsp(opt),
+        Commands::Refs(opt) => symbols::refs(opt),
+        Commands::Rename(opt) => rename::rename(opt),
+        Commands::Stats(opt) => stats::stats(opt),
+        Commands::Symbols(opt) => symbols::symbols(opt),
+        Commands::Tags(opt) => tags::tags(opt),
         Commands::Test(opt) => test::test(opt),
     }
 }
@@ -0,0 +1,402 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::IsTerminal as _,
+    path::{Path, PathBuf},
+};
+
+use annotate_snippets::{AnnotationKind, Level, Renderer, Snippet};
+use clap::Args;
+use ink_tool::{
+    lsp::{lints::Rule, load_workspace, uri_to_path, State},
+    AppResult,
+};
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, TextEdit, Uri};
+use serde_json::json;
+
+use super::watch::watch;
+
+#[derive(Args, Debug)]
+/// Report the problems in a project, the same ones the language server shows
+pub(crate) struct CheckOpt {
+    /// The project directory
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// How to print the results
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+
+    /// Apply the fixes that are safe to make without looking (removing unused temps and
+    /// duplicate INCLUDEs, qualifying ambiguous LIST items, writing empty fallback choices
+    /// as `* ->`) first, then report what is left
+    #[arg(long)]
+    fix: bool,
+
+    /// Keep running, and check the stories of the files that change again whenever they do
+    #[arg(long, conflicts_with = "fix")]
+    watch: bool,
+}
+
+/// Fixes can make new ones possible, but if they keep doing that, something's wrong.
+const MAX_FIX_ROUNDS: usize = 10;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// Source snippets, like rustc
+    #[default]
+    Human,
+    /// A JSON array with one object per diagnostic
+    Json,
+    /// SARIF 2.1.0, for code scanning tools
+    Sarif,
+    /// GitHub Actions workflow commands, to annotate pull requests
+    Github,
+}
+
+/// The diagnostics of one file.
+struct FileReport {
+    uri: Uri,
+    /// Relative to the current directory, if possible
+    path: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+pub(crate) fn check(opt: CheckOpt) -> AppResult<()> {
+    let cwd = std::env::current_dir()?;
+    if opt.watch {
+        return watch(std::slice::from_ref(&opt.root), |state, changed| {
+            let mut diagnostics = state.diagnostics();
+            if let Some(changed) = changed {
+                let affected = state.affected_files(changed);
+                diagnostics.retain(|(uri, _)| affected.contains(uri));
+            }
+            report(state, diagnostics, opt.format, &cwd)
+        });
+    }
+    if opt.fix {
+        fix(&opt.root, &cwd)?;
+    }
+    let state = load_workspace(&opt.root)?;
+    report(&state, state.diagnostics(), opt.format, &cwd)
+}
+
+/// Print the diagnostics, and fail if there are errors among them.
+fn report(
+    state: &State,
+    diagnostics: Vec<(Uri, Vec<Diagnostic>)>,
+    format: Format,
+    cwd: &Path,
+) -> AppResult<()> {
+    let reports = diagnostics
+        .into_iter()
+        .filter(|(_, diagnostics)| !diagnostics.is_empty())
+        .map(|(uri, diagnostics)| FileReport {
+            path: display_path(&uri, cwd),
+            uri,
+            diagnostics,
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        Format::Human => print_human(state, &reports, cwd)?,
+        Format::Json => print_json(&reports, cwd)?,
+        Format::Sarif => print_sarif(&reports, cwd)?,
+        Format::Github => print_github(&reports),
+    }
+
+    let count = |severity| {
+        reports
+            .iter()
+            .flat_map(|it| &it.diagnostics)
+            .filter(|it| it.severity == Some(severity))
+            .count()
+    };
+    let errors = count(DiagnosticSeverity::ERROR);
+    if format == Format::Human {
+        let warnings = count(DiagnosticSeverity::WARNING);
+        eprintln!("{errors} error(s), {warnings} warning(s)");
+    }
+
+    if errors > 0 {
+        Err(format!("Found {errors} error(s)").into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Apply the safe fixes, analyse again, and repeat until there's nothing left to fix.
+fn fix(root: &Path, cwd: &Path) -> AppResult<()> {
+    let mut fixed = BTreeMap::<Rule, usize>::new();
+    let mut files = BTreeSet::new();
+    for round in 1.. {
+        let state = load_workspace(root)?;
+        let fixes = state.safe_fixes();
+        if fixes.is_empty() {
+            break;
+        }
+        if round > MAX_FIX_ROUNDS {
+            return Err(format!("Still finding fixes after {MAX_FIX_ROUNDS} rounds").into());
+        }
+
+        let mut edits = HashMap::<Uri, Vec<TextEdit>>::new();
+        for (rule, action) in fixes {
+            *fixed.entry(rule).or_default() += 1;
+            for (uri, changes) in action.edit.and_then(|it| it.changes).into_iter().flatten() {
+                edits.entry(uri).or_default().extend(changes);
+            }
+        }
+        for (uri, mut edits) in edits {
+            let mut text = state.text(&uri)?;
+            // Back to front, so the ranges stay valid.
+            edits.sort_by_key(|it| Reverse(it.range.start));
+            for edit in edits {
+                text.replace_range(state.byte_range(&uri, edit.range)?, &edit.new_text);
+            }
+            std::fs::write(uri_to_path(&uri), text)?;
+            files.insert(display_path(&uri, cwd));
+        }
+    }
+
+    for (rule, count) in &fixed {
+        eprintln!("Fixed {count} × {rule}");
+    }
+    for file in &files {
+        eprintln!("Changed {file}");
+    }
+    eprintln!(
+        "Applied {} fix(es) to {} file(s)",
+        fixed.values().sum::<usize>(),
+        files.len()
+    );
+    Ok(())
+}
+
+fn print_human(state: &State, reports: &[FileReport], cwd: &Path) -> AppResult<()> {
+    let renderer = if std::io::stdout().is_terminal() {
+        Renderer::styled()
+    } else {
+        Renderer::plain()
+    };
+
+    for report in reports {
+        let text = state.text(&report.uri)?;
+        for diag in &report.diagnostics {
+            let span = state.byte_range(&report.uri, diag.range)?;
+            let mut snippet = Snippet::source(&text)
+                .path(&report.path)
+                .fold(true)
+                .annotation(AnnotationKind::Primary.span(span));
+            let mut notes = Vec::new();
+            for related in diag.related_information.iter().flatten() {
+                if related.location.uri == report.uri {
+                    let span = state.byte_range(&report.uri, related.location.range)?;
+                    snippet = snippet
+                        .annotation(AnnotationKind::Context.span(span).label(&related.message));
+                } else {
+                    let path = display_path(&related.location.uri, cwd);
+                    let (line, column) = one_based(related.location.range.start);
+                    notes.push(format!("{path}:{line}:{column}: {}", related.message));
+                }
+            }
+
+            let code = code(diag);
+            let mut title = level(diag.severity).primary_title(&diag.message);
+            if let Some(code) = &code {
+                title = title.id(code.as_str());
+            }
+            let mut group = title.element(snippet);
+            for note in &notes {
+                group = group.element(Level::NOTE.message(note));
+            }
+            println!("{}\n", renderer.render(&[group]));
+        }
+    }
+    Ok(())
+}
+
+fn print_json(reports: &[FileReport], cwd: &Path) -> AppResult<()> {
+    let items = reports
+        .iter()
+        .flat_map(|report| {
+            report.diagnostics.iter().map(move |diag| {
+                let related = diag
+                    .related_information
+                    .iter()
+                    .flatten()
+                    .map(|it| {
+                        json!({
+                            "path": display_path(&it.location.uri, cwd),
+                            "range": json_range(it.location.range),
+                            "message": it.message,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "path": report.path,
+                    "range": json_range(diag.range),
+                    "severity": severity_name(diag.severity),
+                    "code": code(diag),
+                    "message": diag.message,
+                    "related": related,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    println!("{}", serde_json::to_string_pretty(&items)?);
+    Ok(())
+}
+
+pub(crate) fn json_range(range: lsp_types::Range) -> serde_json::Value {
+    let (start_line, start_column) = one_based(range.start);
+    let (end_line, end_column) = one_based(range.end);
+    json!({
+        "start": { "line": start_line, "column": start_column },
+        "end": { "line": end_line, "column": end_column },
+    })
+}
+
+fn print_sarif(reports: &[FileReport], cwd: &Path) -> AppResult<()> {
+    let location = |uri: &Uri, range: lsp_types::Range| {
+        let (start_line, start_column) = one_based(range.start);
+        let (end_line, end_column) = one_based(range.end);
+        json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": display_path(uri, cwd) },
+                "region": {
+                    "startLine": start_line,
+                    "startColumn": start_column,
+                    "endLine": end_line,
+                    "endColumn": end_column,
+                },
+            },
+        })
+    };
+
+    let rules = Rule::ALL
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id(),
+                "shortDescription": { "text": rule.description() },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let results = reports
+        .iter()
+        .flat_map(|report| {
+            report.diagnostics.iter().map(move |diag| {
+                let related = diag
+                    .related_information
+                    .iter()
+                    .flatten()
+                    .map(|it| {
+                        let mut location = location(&it.location.uri, it.location.range);
+                        location["message"] = json!({ "text": it.message });
+                        location
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "ruleId": code(diag),
+                    "level": match diag.severity {
+                        Some(DiagnosticSeverity::ERROR) => "error",
+                        Some(DiagnosticSeverity::WARNING) => "warning",
+                        _ => "note",
+                    },
+                    "message": { "text": diag.message },
+                    "locations": [location(&report.uri, diag.range)],
+                    "relatedLocations": related,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    });
+    println!("{}", serde_json::to_string_pretty(&sarif)?);
+    Ok(())
+}
+
+/// See <https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions>
+fn print_github(reports: &[FileReport]) {
+    for report in reports {
+        for diag in &report.diagnostics {
+            let command = match diag.severity {
+                Some(DiagnosticSeverity::ERROR) => "error",
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                _ => "notice",
+            };
+            let (line, col) = one_based(diag.range.start);
+            let (end_line, end_col) = one_based(diag.range.end);
+            let mut properties = format!(
+                "file={},line={line},col={col},endLine={end_line},endColumn={end_col}",
+                escape_property(&report.path)
+            );
+            if let Some(code) = code(diag) {
+                properties.push_str(&format!(",title={}", escape_property(&code)));
+            }
+            println!("::{command} {properties}::{}", escape_data(&diag.message));
+        }
+    }
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn level(severity: Option<DiagnosticSeverity>) -> Level<'static> {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => Level::ERROR,
+        Some(DiagnosticSeverity::WARNING) => Level::WARNING,
+        Some(DiagnosticSeverity::HINT) => Level::HELP,
+        _ => Level::INFO,
+    }
+}
+
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "info",
+    }
+}
+
+fn code(diag: &Diagnostic) -> Option<String> {
+    match diag.code.as_ref()? {
+        NumberOrString::String(it) => Some(it.clone()),
+        NumberOrString::Number(it) => Some(it.to_string()),
+    }
+}
+
+pub(crate) fn one_based(position: Position) -> (u32, u32) {
+    (position.line + 1, position.character + 1)
+}
+
+/// The file path of a `file://` URI, relative to `cwd` if it is below it.
+pub(crate) fn display_path(uri: &Uri, cwd: &Path) -> String {
+    let path = uri_to_path(uri);
+    path.strip_prefix(cwd)
+        .unwrap_or(&path)
+        .display()
+        .to_string()
+}
@@ -4,10 +4,21 @@ use std::{
 };
 
 use clap::Args;
-use ink_tool::AppResult;
+use ink_tool::{
+    config::Config,
+    fmt::{Formatter, Rules},
+    lsp::uri_to_path,
+    AppResult,
+};
+use similar::TextDiff;
+
+use super::watch::watch;
 
 #[derive(Args, Debug)]
 /// Format ink files or STDIN
+///
+/// Settings are read from the nearest `ink-fmt.toml` (or the `[fmt]` section of `ink-tool.toml`).
+/// Extra formatting rules can be given with --rules.
 pub(crate) struct FmtOpt {
     /// The file(s) to format.
     ///
@@ -16,6 +27,10 @@ pub(crate) struct FmtOpt {
     /// If omitted, take read from STDIN and output to STDOUT.
     ///
     /// Can be a single file or a directory. If directory format all Ink files found recursively.
+    ///
+    /// A Markdown file (`.md`) has the ```ink code blocks in it formatted, except ones marked
+    /// `input` or `no-fmt` (as in ```ink no-fmt). That only happens when it's given on its own:
+    /// Markdown files in a directory are left alone (and aren't checked with --check either).
     input: Option<PathBuf>,
 
     /// Where to output the formatted result. Overwrites any existing files.
@@ -28,64 +43,407 @@ pub(crate) struct FmtOpt {
     /// You wouldn't normally use this option. It's mainly for comparing the input to the output, i.e. for debugging.
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Don't write anything, just list the files that aren't formatted.
+    ///
+    /// Exits with an error if there are any.
+    #[arg(long, conflicts_with_all = ["output", "diff"])]
+    check: bool,
+
+    /// Don't write anything, print a unified diff of what formatting would change.
+    ///
+    /// Exits with an error if anything would change.
+    #[arg(long, conflicts_with = "output")]
+    diff: bool,
+
+    /// A query file with extra formatting rules, layered on top of the built-in ones.
+    ///
+    /// Takes precedence over the `rules` setting in the config file.
+    #[arg(long, value_name = "PATH")]
+    rules: Option<PathBuf>,
+
+    /// Don't write anything, list which rules decided each space and line break instead.
+    ///
+    /// For finding out why something is formatted the way it is. Needs a single file (or STDIN).
+    #[arg(long, conflicts_with_all = ["output", "check", "diff"])]
+    explain: bool,
+
+    /// Don't write anything, check that formatting works instead: Formatting a second time
+    /// must not change anything, and the formatted files must parse to the same syntax tree
+    /// (apart from whitespace).
+    ///
+    /// Exits with an error if any file fails the check.
+    #[arg(long, conflicts_with_all = ["output", "check", "diff", "explain"])]
+    verify: bool,
+
+    /// Keep running, and format the ink files again whenever they change.
+    ///
+    /// Needs a file or directory. Only the files that changed are formatted (or checked,
+    /// with --check and --diff).
+    #[arg(long, requires = "input", conflicts_with_all = ["explain", "verify"])]
+    watch: bool,
 }
 
 pub(crate) fn fmt(opt: FmtOpt) -> AppResult<()> {
+    let dry_run = opt.check || opt.diff;
+    let config_dir = match &opt.input {
+        Some(path) if path.is_dir() => path.clone(),
+        Some(path) => path
+            .parent()
+            .filter(|it| !it.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    let config = Config::discover_fmt(&config_dir)?;
+    let rules = match opt.rules.as_ref().or(config.rules.as_ref()) {
+        Some(path) => Rules::read(path)?,
+        None => Rules::default(),
+    };
+    if opt.explain {
+        let source = match &opt.input {
+            None => {
+                let mut source = String::new();
+                std::io::stdin().lock().read_to_string(&mut source)?;
+                source
+            }
+            Some(path) if path.is_dir() => return Err("--explain needs a single file".into()),
+            Some(path) => std::fs::read_to_string(path)?,
+        };
+        print!("{}", ink_tool::fmt::explain(source, &config, &rules));
+        return Ok(());
+    }
+    let formatter = Formatter::new(&config, &rules);
+    if opt.verify {
+        return verify(opt.input.as_deref(), &formatter);
+    }
+    if opt.watch {
+        return fmt_watch(&opt, &config_dir, &formatter);
+    }
+    let mut unformatted = 0;
+    let mut failed = 0;
     match &opt.input {
         None => {
             let mut source = String::new();
             std::io::stdin().lock().read_to_string(&mut source)?;
-            let formatted = ink_tool::fmt::format(source);
-            std::io::stdout().lock().write_all(formatted.as_bytes())?
+            let formatted = format("<stdin>", &source, &formatter);
+            if dry_run {
+                if let Some(report) = compare("<stdin>", &source, &formatted, &opt) {
+                    print!("{report}");
+                    unformatted += 1;
+                }
+            } else {
+                std::io::stdout().lock().write_all(formatted.as_bytes())?
+            }
         }
         Some(indir) if indir.is_dir() => {
-            let walk = walkdir::WalkDir::new(indir).follow_links(true);
-            let entries: Result<Vec<_>, _> = walk
-                .into_iter()
-                .filter_entry(|it| {
-                    it.path().is_dir() || it.path().extension().is_some_and(|ext| ext == "ink")
-                })
-                .collect();
-
-            match entries {
-                Ok(entries) => {
-                    for entry in entries {
-                        if entry.path().is_dir() {
-                            continue;
-                        }
-                        let inpath = entry.into_path();
-                        let outpath = match &opt.output {
-                            Some(prefix) => {
-                                if !prefix.exists() {
-                                    std::fs::create_dir(prefix)?;
-                                }
-                                let unique_part = inpath
-                                    .strip_prefix(indir)
-                                    .expect("indir should be a prefix of inpath");
-                                prefix.join(unique_part)
-                            }
-                            None => inpath.clone(),
-                        };
-                        fmt_single(&inpath, &outpath)?;
-                    }
-                }
-                Err(e) => Err(e.into_io_error().expect("what else could it be?"))?,
+            let inpaths = ink_files(indir)?;
+            if let Some(prefix) = opt.output.as_ref().filter(|it| !it.exists()) {
+                std::fs::create_dir(prefix)?;
             }
+
+            (unformatted, failed) = fmt_files(inpaths, &formatter, &opt);
         }
         Some(inpath) => {
-            if let Some(outpath) = &opt.output {
-                fmt_single(&inpath, &outpath)?;
+            if dry_run {
+                if let Some(report) = check_single(&inpath, &formatter, &opt)? {
+                    print!("{report}");
+                    unformatted += 1;
+                }
+            } else if let Some(outpath) = &opt.output {
+                fmt_single(&inpath, &outpath, &formatter)?;
             } else {
-                fmt_single(&inpath, &inpath)?;
+                fmt_single(&inpath, &inpath, &formatter)?;
+            }
+        }
+    }
+
+    outcome(unformatted, failed)
+}
+
+fn outcome(unformatted: usize, failed: usize) -> AppResult<()> {
+    if failed > 0 {
+        Err(format!("{failed} file(s) couldn't be formatted").into())
+    } else if unformatted > 0 {
+        Err(format!("{unformatted} file(s) need formatting").into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Format (or check) the files of a directory or a single file, the way `opt` asks for it.
+/// Returns how many aren't formatted and how many couldn't be read or written.
+fn fmt_files(inpaths: Vec<PathBuf>, formatter: &Formatter, opt: &FmtOpt) -> (usize, usize) {
+    let dry_run = opt.check || opt.diff;
+    let results = formatter.parallel(inpaths.clone(), |formatter, inpath| {
+        if dry_run {
+            check_single(&inpath, formatter, opt)
+        } else {
+            let indir = opt.input.as_deref().filter(|it| it.is_dir());
+            let outpath = output_path(&inpath, indir, opt.output.as_deref());
+            fmt_single(&inpath, &outpath, formatter).map(|()| None)
+        }
+    });
+    let (mut unformatted, mut failed) = (0, 0);
+    // Reported in order, not in whatever order the threads finished in.
+    for (inpath, result) in inpaths.iter().zip(results) {
+        match result.unwrap_or_else(|panic| Err(std::io::Error::other(panic))) {
+            Ok(Some(report)) => {
+                print!("{report}");
+                unformatted += 1;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("{}: {err}", inpath.display());
+                failed += 1;
             }
         }
     }
-    Ok(())
+    (unformatted, failed)
+}
+
+/// Where the formatted `inpath` goes: mirrored into the `output` directory if we're formatting
+/// the directory `indir`, to `output` itself if we're formatting a single file, and back into
+/// `inpath` if there's no `output`.
+fn output_path(inpath: &Path, indir: Option<&Path>, output: Option<&Path>) -> PathBuf {
+    match (output, indir) {
+        (Some(prefix), Some(indir)) => {
+            let unique_part = inpath
+                .strip_prefix(indir)
+                .expect("indir should be a prefix of inpath");
+            prefix.join(unique_part)
+        }
+        (Some(outpath), None) => outpath.to_path_buf(),
+        (None, _) => inpath.to_path_buf(),
+    }
+}
+
+/// `--watch`: Format everything once, then the files that change.
+fn fmt_watch(opt: &FmtOpt, root: &Path, formatter: &Formatter) -> AppResult<()> {
+    let input = opt.input.as_deref().expect("--watch requires an input");
+    let absolute_input = std::path::absolute(input)?;
+    let absolute_output = opt.output.as_deref().map(std::path::absolute).transpose()?;
+    if let Some(prefix) = opt
+        .output
+        .as_ref()
+        .filter(|it| input.is_dir() && !it.exists())
+    {
+        std::fs::create_dir(prefix)?;
+    }
+    watch(&[root.to_path_buf()], |_, changed| {
+        let inpaths = match changed {
+            None if input.is_dir() => ink_files(input)?,
+            None => vec![input.to_path_buf()],
+            Some(changed) => watched_inputs(
+                changed.iter().map(uri_to_path).filter(|it| it.exists()),
+                input,
+                &absolute_input,
+                absolute_output.as_deref(),
+            ),
+        };
+        let (unformatted, failed) = fmt_files(inpaths, formatter, opt);
+        outcome(unformatted, failed)
+    })
 }
 
-fn fmt_single(input: &Path, output: &Path) -> std::io::Result<()> {
+/// The files to format when the (absolute) `changed` paths have changed: the ones in `input`,
+/// as paths like `input` itself, so they can be mirrored in --output.
+fn watched_inputs(
+    changed: impl IntoIterator<Item = PathBuf>,
+    input: &Path,
+    absolute_input: &Path,
+    absolute_output: Option<&Path>,
+) -> Vec<PathBuf> {
+    changed
+        .into_iter()
+        // What we write ourselves doesn't need formatting again.
+        .filter(|it| !absolute_output.is_some_and(|out| it.starts_with(out)))
+        .filter_map(|it| {
+            let rest = it.strip_prefix(absolute_input).ok()?;
+            if rest.as_os_str().is_empty() {
+                Some(input.to_path_buf())
+            } else {
+                Some(input.join(rest))
+            }
+        })
+        .collect()
+}
+
+/// All the ink files in `indir` and its subdirectories.
+pub(crate) fn ink_files(indir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let walk = walkdir::WalkDir::new(indir).follow_links(true);
+    let entries: Result<Vec<_>, _> = walk
+        .into_iter()
+        .filter_entry(|it| {
+            it.path().is_dir() || it.path().extension().is_some_and(|ext| ext == "ink")
+        })
+        .collect();
+    match entries {
+        Ok(entries) => Ok(entries
+            .into_iter()
+            .filter(|it| !it.path().is_dir())
+            .map(|it| it.into_path())
+            .collect()),
+        Err(e) => Err(e.into_io_error().expect("what else could it be?")),
+    }
+}
+
+/// `--verify`: Report every file (or STDIN) that doesn't survive formatting intact.
+fn verify(input: Option<&Path>, formatter: &Formatter) -> AppResult<()> {
+    let problems: Vec<(String, String)> = match input {
+        None => {
+            let mut source = String::new();
+            std::io::stdin().lock().read_to_string(&mut source)?;
+            match formatter.verify(&source) {
+                Ok(_) => Vec::new(),
+                Err(problem) => vec![("<stdin>".to_string(), problem.to_string())],
+            }
+        }
+        Some(path) if path.extension().is_some_and(|ext| ext == "md") => {
+            return Err("--verify only works on ink files".into());
+        }
+        Some(path) => {
+            let paths = if path.is_dir() {
+                ink_files(path)?
+            } else {
+                vec![path.to_path_buf()]
+            };
+            let results = formatter.parallel(paths.clone(), |formatter, path| {
+                log::info!("Verifying {}", path.display());
+                match std::fs::read_to_string(&path) {
+                    Ok(source) => formatter.verify(&source).err().map(|it| it.to_string()),
+                    Err(err) => Some(err.to_string()),
+                }
+            });
+            paths
+                .iter()
+                .zip(results)
+                .filter_map(|(path, result)| {
+                    let problem = result.unwrap_or_else(Some)?;
+                    Some((path.display().to_string(), problem))
+                })
+                .collect()
+        }
+    };
+    for (name, problem) in &problems {
+        eprintln!("{name}: {problem}");
+    }
+    match problems.len() {
+        0 => Ok(()),
+        failed => Err(format!("{failed} file(s) failed verification").into()),
+    }
+}
+
+fn fmt_single(input: &Path, output: &Path, formatter: &Formatter) -> std::io::Result<()> {
     log::info!("Formatting {}", output.display());
     let source = std::fs::read_to_string(input)?;
-    let formatted = ink_tool::fmt::format(source);
+    let formatted = format(&input.display().to_string(), &source, formatter);
+    // Not touching formatted files keeps --watch (and other watchers) from running again.
+    if input == output && formatted == source {
+        return Ok(());
+    }
     std::fs::write(output, formatted)
 }
+
+/// Format a file without writing it. Returns what to report if formatting would change it.
+fn check_single(
+    input: &Path,
+    formatter: &Formatter,
+    opt: &FmtOpt,
+) -> std::io::Result<Option<String>> {
+    log::info!("Checking {}", input.display());
+    let source = std::fs::read_to_string(input)?;
+    let name = input.display().to_string();
+    let formatted = format(&name, &source, formatter);
+    Ok(compare(&name, &source, &formatted, opt))
+}
+
+/// Format `source` (or its ink blocks, if `name` is a Markdown file), warning about the parts
+/// that are left as they are because of syntax errors.
+fn format(name: &str, source: &str, formatter: &Formatter) -> String {
+    let formatted = if name.ends_with(".md") {
+        formatter.format_markdown(source.to_string())
+    } else {
+        formatter.format(source.to_string())
+    };
+    for range in &formatted.unformatted {
+        let line = ink_tool::fmt::line_number(source, range.start);
+        eprintln!("{name}:{line}: syntax error, left this part unformatted");
+    }
+    formatted.text
+}
+
+/// The report of a difference between `source` and `formatted`, the way `opt` asks for it.
+/// `None` if there is no difference.
+fn compare(name: &str, source: &str, formatted: &str, opt: &FmtOpt) -> Option<String> {
+    if source == formatted {
+        return None;
+    }
+    if opt.diff {
+        let diff = TextDiff::from_lines(source, formatted);
+        Some(diff.unified_diff().header(name, name).to_string())
+    } else {
+        Some(format!("{name}\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{output_path, watched_inputs};
+
+    fn paths(them: &[&str]) -> Vec<PathBuf> {
+        them.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn watch_ignores_what_it_writes_itself() {
+        let changed = paths(&["/story/a.ink", "/story/out/a.ink", "/elsewhere/b.ink"]);
+        let inputs = watched_inputs(
+            changed,
+            Path::new("story"),
+            Path::new("/story"),
+            Some(Path::new("/story/out")),
+        );
+        assert_eq!(inputs, paths(&["story/a.ink"]));
+    }
+
+    #[test]
+    fn watch_mirrors_changed_files_into_the_output() {
+        let changed = paths(&["/story/a.ink", "/story/chapter/b.ink"]);
+        let inputs = watched_inputs(
+            changed,
+            Path::new("story"),
+            Path::new("/story"),
+            Some(Path::new("/formatted")),
+        );
+        let outputs = inputs
+            .iter()
+            .map(|it| output_path(it, Some(Path::new("story")), Some(Path::new("formatted"))))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outputs,
+            paths(&["formatted/a.ink", "formatted/chapter/b.ink"])
+        );
+    }
+
+    #[test]
+    fn watching_a_single_file() {
+        let inputs = watched_inputs(
+            paths(&["/story/main.ink"]),
+            Path::new("main.ink"),
+            Path::new("/story/main.ink"),
+            None,
+        );
+        assert_eq!(inputs, paths(&["main.ink"]));
+        assert_eq!(
+            output_path(&inputs[0], None, Some(Path::new("out.ink"))),
+            PathBuf::from("out.ink")
+        );
+        assert_eq!(
+            output_path(&inputs[0], None, None),
+            PathBuf::from("main.ink")
+        );
+    }
+}
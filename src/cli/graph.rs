@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use ink_tool::{lsp::load_workspace, AppResult};
+
+#[derive(Args, Debug)]
+/// Print the story's flow graph: which knots, stitches and labelled gathers lead where
+pub(crate) struct GraphOpt {
+    /// The project directory
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// How to print the graph
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// Graphviz, for `dot -Tsvg`
+    #[default]
+    Dot,
+    /// A Mermaid flowchart
+    Mermaid,
+    /// An object with `nodes` and `edges`
+    Json,
+}
+
+pub(crate) fn graph(opt: GraphOpt) -> AppResult<()> {
+    let state = load_workspace(&opt.root)?;
+    let graph = state.story_graph();
+    match opt.format {
+        Format::Dot => print!("{}", graph.to_dot()),
+        Format::Mermaid => print!("{}", graph.to_mermaid()),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&graph.to_json())?),
+    }
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+use ink_tool::{
+    config::Config,
+    l10n::{translate, Entry, Extractor, FileFormat},
+    AppResult,
+};
+
+use super::fmt::ink_files;
+
+#[derive(Args, Debug)]
+/// Localise a story: extract its text for translators, and build translated copies of it
+///
+/// Lines are identified by an ID tag (`#id:…`, see `[l10n] id-tag` in `ink-tool.toml`).
+pub(crate) struct L10nOpt {
+    #[command(subcommand)]
+    command: L10nCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum L10nCommand {
+    Extract(ExtractOpt),
+    Apply(ApplyOpt),
+}
+
+#[derive(Args, Debug)]
+/// Write every line, choice and string to a translation file
+///
+/// Lines without an ID get one, which is written back to the ink files.
+struct ExtractOpt {
+    /// The project directory
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// The translation file to write
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The format of the translation file. Guessed from its extension if not given.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+}
+
+#[derive(Args, Debug)]
+/// Write a copy of the project's ink files with the translations of a translation file
+///
+/// Anything without a translation stays as it is.
+struct ApplyOpt {
+    /// The project directory
+    root: PathBuf,
+
+    /// The translation file to read
+    translations: PathBuf,
+
+    /// The directory for the translated files. The file structure mirrors the project.
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The format of the translation file. Guessed from its extension if not given.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// gettext (`.po`, `.pot`)
+    Po,
+    /// XLIFF 1.2 (`.xlf`, `.xliff`)
+    Xliff,
+    /// Comma separated, with `id` and `target` columns (`.csv`)
+    Csv,
+}
+
+impl From<Format> for FileFormat {
+    fn from(value: Format) -> Self {
+        match value {
+            Format::Po => FileFormat::Po,
+            Format::Xliff => FileFormat::Xliff,
+            Format::Csv => FileFormat::Csv,
+        }
+    }
+}
+
+pub(crate) fn l10n(opt: L10nOpt) -> AppResult<()> {
+    match opt.command {
+        L10nCommand::Extract(opt) => extract(opt),
+        L10nCommand::Apply(opt) => apply(opt),
+    }
+}
+
+fn extract(opt: ExtractOpt) -> AppResult<()> {
+    let format = file_format(opt.format, &opt.output)?;
+    let config = Config::discover(&opt.root)?;
+    config.l10n.check(&config.tags)?;
+    let files = read_files(&opt.root)?;
+
+    let mut extractor = Extractor::new(&config.l10n);
+    for (path, source) in &files {
+        extractor
+            .scan(source)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+    }
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut added = 0;
+    for (path, source) in &files {
+        let name = relative(path, &opt.root);
+        let extracted = extractor
+            .extract(&name, source)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        if extracted.added > 0 {
+            std::fs::write(path, &extracted.text)?;
+            added += extracted.added;
+        }
+        // The same string in several files only needs translating once.
+        for entry in extracted.entries {
+            if !entries.iter().any(|it| it.id == entry.id) {
+                entries.push(entry);
+            }
+        }
+    }
+
+    std::fs::write(&opt.output, format.write(&entries, &config.l10n))?;
+    eprintln!(
+        "Extracted {} entries from {} file(s), added {added} ID(s)",
+        entries.len(),
+        files.len()
+    );
+    Ok(())
+}
+
+fn apply(opt: ApplyOpt) -> AppResult<()> {
+    let format = file_format(opt.format, &opt.translations)?;
+    let config = Config::discover(&opt.root)?;
+    let translations = format
+        .read(&std::fs::read_to_string(&opt.translations)?)
+        .map_err(|err| format!("{}: {err}", opt.translations.display()))?;
+
+    let (mut translated, mut untranslated) = (0, 0);
+    let files = read_files(&opt.root)?;
+    for (path, source) in &files {
+        let result = translate(source, &config.l10n, &translations)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        let target = opt.output.join(relative(path, &opt.root));
+        if let Some(dir) = target.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(target, result.text)?;
+        translated += result.translated;
+        untranslated += result.untranslated;
+    }
+    eprintln!(
+        "Translated {translated} entries in {} file(s), {untranslated} left as they were",
+        files.len()
+    );
+    Ok(())
+}
+
+fn file_format(format: Option<Format>, path: &Path) -> AppResult<FileFormat> {
+    match format {
+        Some(format) => Ok(format.into()),
+        None => FileFormat::of(path).ok_or_else(|| {
+            format!("Can't tell the format of {}, use --format", path.display()).into()
+        }),
+    }
+}
+
+/// The ink files of the project and their text, in a stable order.
+fn read_files(root: &Path) -> AppResult<Vec<(PathBuf, String)>> {
+    let mut paths = ink_files(root)?;
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| -> AppResult<_> {
+            let text = std::fs::read_to_string(&path)?;
+            Ok((path, text))
+        })
+        .collect()
+}
+
+fn relative(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use ink_tool::{
+    lsp::{load_workspace, uri_to_path, RenameError},
+    AppResult,
+};
+use similar::TextDiff;
+
+use super::check::display_path;
+
+#[derive(Args, Debug)]
+/// Rename a knot, stitch, variable (or anything else with a name) and all its usages
+pub(crate) struct RenameOpt {
+    /// The project directory
+    root: PathBuf,
+
+    /// What to rename, qualified as far as needed to tell it apart (`knot.stitch`,
+    /// `knot.temp_var`). See `ink-tool symbols`.
+    name: String,
+
+    /// The new name (without qualification)
+    new_name: String,
+
+    /// Don't write anything, print a unified diff of the changes instead
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub(crate) fn rename(opt: RenameOpt) -> AppResult<()> {
+    let state = load_workspace(&opt.root)?;
+    let edits = match state.rename_by_name(&opt.name, &opt.new_name) {
+        Ok(edits) => edits,
+        Err(RenameError::RenameFailed(message)) => return Err(message.into()),
+        Err(RenameError::LocationError(_)) => {
+            return Err(format!("Couldn't find the usages of `{}`", opt.name).into())
+        }
+    };
+
+    let cwd = std::env::current_dir()?;
+    let mut changes = edits
+        .changes
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+    let mut count = 0;
+    for (uri, edits) in &changes {
+        let source = state.text(uri)?;
+        let mut text = source.clone();
+        // They come back to front, so the ranges stay valid.
+        for edit in edits {
+            text.replace_range(state.byte_range(uri, edit.range)?, &edit.new_text);
+            count += 1;
+        }
+        let path = display_path(uri, &cwd);
+        if opt.dry_run {
+            let diff = TextDiff::from_lines(&source, &text);
+            print!("{}", diff.unified_diff().header(&path, &path));
+        } else {
+            std::fs::write(uri_to_path(uri), text)?;
+        }
+    }
+    eprintln!("Renamed {count} occurrence(s) in {} file(s)", changes.len());
+    Ok(())
+}
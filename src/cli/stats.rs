@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use ink_tool::{
+    lsp::{load_workspace, salsa::Stats, StoryStats},
+    AppResult,
+};
+use serde_json::json;
+
+use super::check::display_path;
+
+#[derive(Args, Debug)]
+/// Count the words, choices, gathers and diverts of each story, file, knot and stitch
+///
+/// The minimum is an estimate of how few words a player can get away with reading: the
+/// shortest of each set of choices and alternatives, no conditional text, and (for whole
+/// stories) the shortest way through the knots and stitches to an ending.
+pub(crate) struct StatsOpt {
+    /// The project directory
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// How to print the numbers
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// One row per story, file, knot and stitch
+    #[default]
+    Table,
+    /// A list of stories, each with its `files`, each with its `sections`
+    Json,
+}
+
+pub(crate) fn stats(opt: StatsOpt) -> AppResult<()> {
+    let cwd = std::env::current_dir()?;
+    let stories = load_workspace(&opt.root)?.story_stats();
+    match opt.format {
+        Format::Table => print!("{}", table(&stories, &cwd)),
+        Format::Json => {
+            let json = stories
+                .iter()
+                .map(|it| to_json(it, &cwd))
+                .collect::<Vec<_>>();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+    }
+    Ok(())
+}
+
+const COLUMNS: [&str; 6] = ["words", "choices", "gathers", "diverts", "depth", "minimum"];
+
+fn table(stories: &[StoryStats], cwd: &Path) -> String {
+    let mut rows: Vec<(String, Stats)> = Vec::new();
+    for story in stories {
+        rows.push((
+            format!("{} (story)", display_path(&story.root, cwd)),
+            story.total,
+        ));
+        for (uri, file) in &story.files {
+            rows.push((format!("  {}", display_path(uri, cwd)), file.total()));
+            for section in &file.sections {
+                rows.push((format!("    {}", section.path), section.stats));
+            }
+        }
+    }
+
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = format!("{:width$}", "");
+    for column in COLUMNS {
+        out.push_str(&format!("  {column:>7}"));
+    }
+    out.push('\n');
+    for (name, stats) in rows {
+        out.push_str(&format!("{name:width$}"));
+        for value in numbers(&stats) {
+            out.push_str(&format!("  {value:>7}"));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn numbers(stats: &Stats) -> [usize; 6] {
+    [
+        stats.words,
+        stats.choices,
+        stats.gathers,
+        stats.diverts,
+        stats.max_depth,
+        stats.min_words,
+    ]
+}
+
+fn to_json(story: &StoryStats, cwd: &Path) -> serde_json::Value {
+    let files = story
+        .files
+        .iter()
+        .map(|(uri, file)| {
+            let sections = file
+                .sections
+                .iter()
+                .map(|section| {
+                    let object = json!({
+                        "name": section.path,
+                        "kind": section.kind.as_str(),
+                        "line": section.range.start.line + 1,
+                    });
+                    with_numbers(object, &section.stats)
+                })
+                .collect::<Vec<_>>();
+            let object = json!({
+                "file": display_path(uri, cwd),
+                "sections": sections,
+            });
+            with_numbers(object, &file.total())
+        })
+        .collect::<Vec<_>>();
+    let object = json!({
+        "root": display_path(&story.root, cwd),
+        "files": files,
+    });
+    with_numbers(object, &story.total)
+}
+
+fn with_numbers(mut object: serde_json::Value, stats: &Stats) -> serde_json::Value {
+    let keys = [
+        "words",
+        "choices",
+        "gathers",
+        "diverts",
+        "max_choice_depth",
+        "min_words",
+    ];
+    if let Some(object) = object.as_object_mut() {
+        for (key, value) in keys.into_iter().zip(numbers(stats)) {
+            object.insert(key.to_string(), value.into());
+        }
+    }
+    object
+}
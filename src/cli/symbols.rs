@@ -0,0 +1,171 @@
+//! `ink-tool symbols` and `ink-tool refs`, which print the same kind of records.
+
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use ink_tool::{
+    lsp::{load_workspace, SymbolReport},
+    AppResult,
+};
+use lsp_types::Location;
+use serde_json::json;
+
+use super::check::{display_path, json_range, one_based};
+
+#[derive(Args, Debug)]
+/// List all definitions and usages of names in a project, and what each usage resolves to
+pub(crate) struct SymbolsOpt {
+    /// The project directory
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// How to print the results
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Args, Debug)]
+/// List the definitions of a name, and every usage that resolves to them
+pub(crate) struct RefsOpt {
+    /// The project directory
+    root: PathBuf,
+
+    /// The name, qualified as far as needed to tell it apart (`knot.stitch`, `knot.temp_var`)
+    name: String,
+
+    /// How to print the results
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    /// An object with `definitions` and `usages`
+    #[default]
+    Json,
+    /// One line per definition (`def name kind file:line:column parent stories`) and per
+    /// usage (`use name kind file:line:column definitions`), separated by tabs
+    Tsv,
+}
+
+pub(crate) fn symbols(opt: SymbolsOpt) -> AppResult<()> {
+    let report = load_workspace(&opt.root)?.symbol_report();
+    let definitions = (0..report.definitions.len()).collect::<Vec<_>>();
+    let usages = (0..report.usages.len()).collect::<Vec<_>>();
+    print(&report, &definitions, &usages, opt.format)
+}
+
+pub(crate) fn refs(opt: RefsOpt) -> AppResult<()> {
+    let report = load_workspace(&opt.root)?.symbol_report();
+    let definitions = report
+        .definitions
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| it.qualified_name == opt.name)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if definitions.is_empty() {
+        return Err(format!("Nothing named `{}` is defined", opt.name).into());
+    }
+    let usages = report
+        .usages
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| it.resolves_to.iter().any(|def| definitions.contains(def)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    print(&report, &definitions, &usages, opt.format)
+}
+
+/// Print the given definitions and usages (indexes into `report`).
+fn print(
+    report: &SymbolReport,
+    definitions: &[usize],
+    usages: &[usize],
+    format: Format,
+) -> AppResult<()> {
+    let cwd = std::env::current_dir()?;
+    let definitions = definitions.iter().map(|it| &report.definitions[*it]);
+    let usages = usages.iter().map(|it| &report.usages[*it]);
+    match format {
+        Format::Json => {
+            let location = |it: &Location| {
+                json!({
+                    "path": display_path(&it.uri, &cwd),
+                    "range": json_range(it.range),
+                })
+            };
+            let definitions = definitions
+                .map(|def| {
+                    json!({
+                        "name": def.qualified_name,
+                        "kind": def.kind,
+                        "location": location(&def.location),
+                        "parent": def.parent,
+                        "stories": def.stories.iter().map(|it| display_path(it, &cwd)).collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let usages = usages
+                .map(|usage| {
+                    let resolves_to = usage
+                        .resolves_to
+                        .iter()
+                        .map(|it| {
+                            let def = &report.definitions[*it];
+                            json!({
+                                "name": def.qualified_name,
+                                "location": location(&def.location),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    json!({
+                        "name": usage.name,
+                        "kind": usage.kind,
+                        "location": location(&usage.location),
+                        "resolves_to": resolves_to,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let output = json!({ "definitions": definitions, "usages": usages });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        Format::Tsv => {
+            for def in definitions {
+                let stories = def
+                    .stories
+                    .iter()
+                    .map(|it| display_path(it, &cwd))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                println!(
+                    "def\t{}\t{}\t{}\t{}\t{stories}",
+                    def.qualified_name,
+                    def.kind,
+                    tsv_location(&def.location, &cwd),
+                    def.parent.as_deref().unwrap_or_default(),
+                );
+            }
+            for usage in usages {
+                let resolves_to = usage
+                    .resolves_to
+                    .iter()
+                    .map(|it| tsv_location(&report.definitions[*it].location, &cwd))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                println!(
+                    "use\t{}\t{}\t{}\t{resolves_to}",
+                    usage.name,
+                    usage.kind,
+                    tsv_location(&usage.location, &cwd),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn tsv_location(location: &Location, cwd: &Path) -> String {
+    let (line, column) = one_based(location.range.start);
+    format!("{}:{line}:{column}", display_path(&location.uri, cwd))
+}
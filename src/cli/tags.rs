@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use ink_tool::{lsp::load_workspace, AppResult};
+
+#[derive(Args, Debug)]
+/// List all `#tags` used in a project
+pub(crate) struct TagsOpt {
+    /// The project directory
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// Also list where each tag is used
+    #[arg(short, long)]
+    locations: bool,
+}
+
+pub(crate) fn tags(opt: TagsOpt) -> AppResult<()> {
+    let state = load_workspace(&opt.root)?;
+    let prefix = state.common_file_prefix();
+    let report = state.tag_report();
+
+    let width = report
+        .values()
+        .map(|it| it.locations.len().to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    for (tag, usage) in &report {
+        let count = usage.locations.len();
+        let note = if usage.valid {
+            ""
+        } else {
+            "  (not in tag schema)"
+        };
+        println!("{count:>width$}  #{tag}{note}");
+        if opt.locations {
+            for location in &usage.locations {
+                let uri = location.uri.as_str();
+                let path = uri.strip_prefix(&prefix).unwrap_or(uri);
+                let line = location.range.start.line + 1;
+                let column = location.range.start.character + 1;
+                println!("{:width$}    {path}:{line}:{column}", "");
+            }
+        }
+    }
+
+    if report.values().any(|it| !it.valid) {
+        Err("Some tags don't match the tag schema".into())
+    } else {
+        Ok(())
+    }
+}
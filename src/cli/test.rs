@@ -1,24 +1,60 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::Args;
 use ink_test::TestFailures;
-use ink_tool::AppResult;
+use ink_tool::{lsp::uri_to_path, AppResult};
+
+use super::watch::watch;
 
 #[derive(Args, Debug)]
 /// Run ink tests
 pub(crate) struct TestOpt {
     /// The files to run. Must be ink files with special `TEST` block comments.
     inputs: Vec<PathBuf>,
+
+    /// Keep running, and run the tests again whenever a file they are in a story with
+    /// changes. Watches the ink files below the directories of the given files.
+    #[arg(long)]
+    watch: bool,
 }
 
 pub(crate) fn test(opt: TestOpt) -> AppResult<()> {
     if opt.inputs.is_empty() {
         return Err("Need at least one file".to_string().into());
     }
+    if opt.watch {
+        let inputs = opt
+            .inputs
+            .iter()
+            .map(|it| Ok((it.as_path(), std::path::absolute(it)?)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut roots = inputs
+            .iter()
+            .filter_map(|(_, absolute)| absolute.parent())
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        roots.sort();
+        // A directory below another one is already watched along with that.
+        roots.dedup_by(|below, above| below.starts_with(above));
+        return watch(&roots, |state, changed| {
+            let affected = changed.map(|it| state.affected_files(it));
+            let inputs = inputs
+                .iter()
+                .filter(|(_, absolute)| match &affected {
+                    Some(affected) => affected.iter().any(|it| uri_to_path(it) == *absolute),
+                    None => true,
+                })
+                .map(|(input, _)| *input);
+            run(inputs)
+        });
+    }
+    run(opt.inputs.iter().map(PathBuf::as_path))
+}
 
+fn run<'a>(inputs: impl IntoIterator<Item = &'a Path>) -> AppResult<()> {
     let mut errors = TestFailures::empty();
-    for input in opt.inputs {
-        if let Err(error) = ink_test::run_tests_in_file(&input) {
+    for input in inputs {
+        if let Err(error) = ink_test::run_tests_in_file(input) {
             errors += error;
         }
     }
@@ -0,0 +1,41 @@
+//! `--watch` for `fmt`, `check` and `test`, for writers who work in a terminal next to an
+//! editor that doesn't speak LSP.
+
+use std::{path::PathBuf, sync::mpsc, time::Duration};
+
+use ink_tool::{
+    lsp::{watch_workspace, State},
+    AppResult,
+};
+use lsp_types::Uri;
+
+/// Editors often save in several steps; wait this long for more before running again.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Run `run` with everything (`None`), then again with the files that changed whenever
+/// ink files below `roots` change. The state stays the same, so only what changed needs to
+/// be analysed again. Failures are reported, but don't stop the watching.
+pub(crate) fn watch(
+    roots: &[PathBuf],
+    mut run: impl FnMut(&State, Option<&[Uri]>) -> AppResult<()>,
+) -> AppResult<()> {
+    let (sender, changes) = mpsc::channel();
+    let (state, _watcher) = watch_workspace(roots, sender)?;
+    report(run(&*state.lock()?, None));
+    while let Ok(mut changed) = changes.recv() {
+        while let Ok(more) = changes.recv_timeout(SETTLE) {
+            changed.extend(more);
+        }
+        changed.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        changed.dedup();
+        report(run(&*state.lock()?, Some(&changed)));
+    }
+    Ok(())
+}
+
+fn report(result: AppResult<()>) {
+    if let Err(err) = result {
+        eprintln!("{err}");
+    }
+    eprintln!("Watching for changes, press Ctrl-C to stop");
+}
@@ -0,0 +1,93 @@
+//! Project configuration, read from an `ink-tool.toml` file in the project
+//! directory (or any of its parents).
+//!
+//! The formatter settings can also live in their own `ink-fmt.toml`.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    fmt::FormatConfig,
+    l10n::L10nConfig,
+    lsp::{lints::LintConfig, tag_schema::TagSchema},
+    AppResult,
+};
+
+pub const CONFIG_FILE_NAME: &str = "ink-tool.toml";
+pub const FMT_CONFIG_FILE_NAME: &str = "ink-fmt.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Rule ID → level (`allow`, `hint`, `info`, `warn`, `error`)
+    pub lints: LintConfig,
+    /// Allowed shapes of `#tags`
+    pub tags: TagSchema,
+    /// Formatter settings (unless there's a closer `ink-fmt.toml`)
+    pub fmt: FormatConfig,
+    /// Localisation settings, for `ink-tool l10n`
+    pub l10n: L10nConfig,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    pub fn read(path: &Path) -> AppResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()).into())
+    }
+
+    /// The nearest config file in `dir` or one of its ancestors.
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        dir.ancestors()
+            .map(|it| it.join(CONFIG_FILE_NAME))
+            .find(|it| it.is_file())
+    }
+
+    /// Read the nearest config file, or use the defaults if there is none.
+    pub fn discover(dir: &Path) -> AppResult<Self> {
+        match Self::find(dir) {
+            Some(path) => {
+                log::info!("Using config file {}", path.display());
+                Self::read(&path)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The formatter settings for files in `dir`: from the nearest `ink-fmt.toml`, or the
+    /// `[fmt]` section of the nearest `ink-tool.toml`, whichever is closer.
+    ///
+    /// The path of the `rules` file is resolved against the directory of the config file.
+    pub fn discover_fmt(dir: &Path) -> AppResult<FormatConfig> {
+        Ok(Self::find_fmt(dir)?.unwrap_or_default())
+    }
+
+    /// Like [`Config::discover_fmt`], but `None` if there's no config file at all.
+    pub fn find_fmt(dir: &Path) -> AppResult<Option<FormatConfig>> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        for dir in dir.ancestors() {
+            let fmt_file = dir.join(FMT_CONFIG_FILE_NAME);
+            let mut config = if fmt_file.is_file() {
+                log::info!("Using formatter config file {}", fmt_file.display());
+                let text = std::fs::read_to_string(&fmt_file)?;
+                FormatConfig::parse(&text)
+                    .map_err(|err| format!("{}: {err}", fmt_file.display()))?
+            } else {
+                let config_file = dir.join(CONFIG_FILE_NAME);
+                if !config_file.is_file() {
+                    continue;
+                }
+                log::info!("Using config file {}", config_file.display());
+                Self::read(&config_file)?.fmt
+            };
+            config.rules = config.rules.map(|it| dir.join(it));
+            return Ok(Some(config));
+        }
+        Ok(None)
+    }
+}
@@ -1,43 +1,195 @@
+mod config;
 mod constrained_value;
+mod edits;
+mod explain;
 mod format_item;
 mod formatting;
+mod markdown;
 mod node_rule;
+mod reflow;
+mod rules;
 mod scanner;
+mod verbatim;
+mod verify;
 
 pub(crate) type CaptureIndex = u32;
 pub(crate) type PatternIndex = usize;
 pub(crate) type NodeId = usize;
 
-use tree_sitter::{Language, Parser, Query};
+use std::{
+    any::Any,
+    cell::RefCell,
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    sync::Mutex,
+};
 
+use tree_sitter::{Parser, Tree};
+
+pub use self::markdown::format_markdown;
 use crate::fmt::scanner::FormatScanner;
+pub use config::{FormatConfig, IndentStyle, Reflow};
+pub use edits::{edits, Edit};
+pub use explain::explain;
+pub use rules::Rules;
+pub use verify::Problem;
 
 use self::formatting::{Layout, Tracing};
 
-static QUERY: &str = include_str!("fmt/format.scm");
-
-/// Convenience function for quickly formatting a string.
+/// Convenience function for quickly formatting a string with the default settings.
 ///
-/// For multiple repeated formatting operations, you'll want to construct a [`FormatScanner`] and re-use that.
+/// For multiple repeated formatting operations, you'll want to construct a [`Formatter`] and re-use that.
 pub fn format(source: String) -> String {
-    let language: Language = tree_sitter_ink::LANGUAGE.into();
-
-    let mut parser = Parser::new();
-    parser
-        .set_language(&language)
-        .expect("We should be able to load a language.");
-
-    let tree = parser
-        .parse(&source, None)
-        .expect("There should be a tree here.");
-
-    let query = Query::new(&language, QUERY).expect("query should be valid");
-    let mut scanner = FormatScanner::new(query);
-    let mut result = String::new();
-    let mut formatter = Tracing::new(Layout::new(Tracing::new(&mut result)));
-
-    scanner.scan(&tree, &source, &mut formatter);
-    // log::debug!("outer:\n{}", formatter.trace);
-    // log::debug!("inner:\n{}\n", formatter.downstream.downstream.trace);
-    result
+    format_with(source, &FormatConfig::default())
+}
+
+/// Like [`format`], with custom settings.
+///
+/// Parts with syntax errors are left as they are, with a warning in the log.
+pub fn format_with(source: String, config: &FormatConfig) -> String {
+    let formatted = format_checked(source.clone(), config);
+    for range in formatted.unformatted {
+        log::warn!(
+            "Syntax error in line {}, leaving it unformatted",
+            line_number(&source, range.start)
+        );
+    }
+    formatted.text
+}
+
+/// The result of [`format_checked`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formatted {
+    pub text: String,
+    /// Byte ranges of the source that have syntax errors, and were therefore left as they are.
+    pub unformatted: Vec<Range<usize>>,
+}
+
+/// The 1-based line that byte `offset` of `source` is on.
+pub fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+/// Like [`format_with`], but tells which parts couldn't be formatted instead of logging them.
+pub fn format_checked(source: String, config: &FormatConfig) -> Formatted {
+    format_with_rules(source, config, &Rules::default())
+}
+
+/// Like [`format_checked`], with project specific formatting rules.
+pub fn format_with_rules(source: String, config: &FormatConfig, rules: &Rules) -> Formatted {
+    Formatter::new(config, rules).format(source)
+}
+
+/// Formats any number of files with the same settings. The formatting rules are only compiled
+/// once, and one formatter can be used from several threads at the same time.
+pub struct Formatter {
+    scanner: FormatScanner,
+    config: FormatConfig,
+}
+
+impl Formatter {
+    pub fn new(config: &FormatConfig, rules: &Rules) -> Self {
+        let (query, extra_patterns) = rules.query();
+        Self {
+            scanner: FormatScanner::new(query, extra_patterns, config),
+            config: config.clone(),
+        }
+    }
+
+    pub fn format(&self, source: String) -> Formatted {
+        let tree = parse(&source);
+        let mut result = String::new();
+        let mut formatter = Tracing::new(Layout::new(
+            Tracing::new(&mut result),
+            self.config.indent_style,
+        ));
+
+        let unformatted = self.scanner.scan(&tree, &source, &mut formatter);
+        // log::debug!("outer:\n{}", formatter.trace);
+        // log::debug!("inner:\n{}\n", formatter.downstream.downstream.trace);
+        let text = match self.config.reflow {
+            Reflow::Keep => result,
+            Reflow::Wrap => reflow::wrap(
+                &result,
+                self.config.line_width.into(),
+                self.config.indent_style,
+            ),
+            Reflow::Unwrap => reflow::unwrap(&result, self.config.indent_style),
+        };
+        Formatted { text, unformatted }
+    }
+
+    /// Run `job` for each of `items`, spread over as many threads as there are cores.
+    /// The results are in the same order as the items.
+    ///
+    /// Jobs should return their failures instead of panicking. If one panics anyway, its
+    /// result is the panic message, so that one broken file doesn't keep the others from
+    /// being formatted.
+    pub fn parallel<T, R>(
+        &self,
+        items: Vec<T>,
+        job: impl Fn(&Self, T) -> R + Sync,
+    ) -> Vec<Result<R, String>>
+    where
+        T: Send,
+        R: Send,
+    {
+        let threads = std::thread::available_parallelism()
+            .map_or(1, usize::from)
+            .clamp(1, items.len().max(1));
+        let queue = Mutex::new(items.into_iter().enumerate());
+        let mut results: Vec<(usize, Result<R, String>)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let next = queue.lock().expect("jobs don't hold the lock").next();
+                            let Some((index, item)) = next else {
+                                break done;
+                            };
+                            let result = panic::catch_unwind(AssertUnwindSafe(|| job(self, item)));
+                            done.push((index, result.map_err(|it| panic_message(&*it))));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|it| it.join().expect("panics are caught in the jobs"))
+                .collect()
+        });
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+/// What a job of [`Formatter::parallel`] panicked with.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or_default();
+    format!("formatting failed unexpectedly: {message}")
+}
+
+thread_local! {
+    /// Making a parser for every file adds up when formatting many of them, so every thread
+    /// (like the ones of [`Formatter::parallel`]) keeps one.
+    static PARSER: RefCell<Parser> = RefCell::new({
+        let mut parser = Parser::new();
+        parser
+            .set_language(&tree_sitter_ink::LANGUAGE.into())
+            .expect("We should be able to load a language.");
+        parser
+    });
+}
+
+pub(crate) fn parse(source: &str) -> Tree {
+    PARSER.with_borrow_mut(|parser| {
+        parser
+            .parse(source, None)
+            .expect("There should be a tree here.")
+    })
 }
@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// Formatter settings, read from an `ink-fmt.toml` file or the `[fmt]` section of `ink-tool.toml`:
+///
+/// ``` toml
+/// gap-after-marks = 1
+/// indent-style = "tabs"
+/// blank-lines-before-knot = 2
+/// blank-lines-before-stitch = 1
+/// indent-empty-gathers = true
+/// max-blank-lines = 2
+/// reflow = "wrap"
+/// line-width = 100
+/// rules = "house-style.scm"
+/// ```
+///
+/// The defaults are the ones described in `doc/ink-fmt.md`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FormatConfig {
+    /// Spaces between choice/gather marks and the content after them
+    pub gap_after_marks: u8,
+    pub indent_style: IndentStyle,
+    pub blank_lines_before_knot: u8,
+    pub blank_lines_before_stitch: u8,
+    /// Whether the content after a gather without label or content (`- -` on its own) is indented
+    pub indent_empty_gathers: bool,
+    /// The most blank lines that may separate paragraphs (and other content)
+    pub max_blank_lines: u8,
+    /// Whether long lines of prose are wrapped (or wrapped ones joined)
+    pub reflow: Reflow,
+    /// The column that [`Reflow::Wrap`] wraps at, and that `LIST` definitions have to fit in
+    /// before they get one item per line
+    pub line_width: u16,
+    /// A query file with extra formatting rules (see [`crate::fmt::Rules`]), relative to the
+    /// config file
+    pub rules: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+    #[default]
+    Spaces,
+    /// Indent with as many tabs (of 4 columns each) as fit, and spaces for the rest.
+    Tabs,
+}
+
+/// See `fmt/reflow.rs` for what gets wrapped, and how.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reflow {
+    /// Leave lines as they are
+    #[default]
+    Keep,
+    /// Break lines longer than `line-width` with glue (`<>`)
+    Wrap,
+    /// Join lines that were broken with glue
+    Unwrap,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            gap_after_marks: 3,
+            indent_style: IndentStyle::Spaces,
+            blank_lines_before_knot: 3,
+            blank_lines_before_stitch: 2,
+            indent_empty_gathers: false,
+            max_blank_lines: 1,
+            reflow: Reflow::Keep,
+            line_width: 80,
+            rules: None,
+        }
+    }
+}
+
+impl FormatConfig {
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Boolean settings, by their name in the config file. For `#if-option` in the formatting query.
+    pub(crate) fn option(&self, name: &str) -> Option<bool> {
+        match name {
+            "indent-empty-gathers" => Some(self.indent_empty_gathers),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fmt::format_with;
+    use indoc::indoc;
+
+    fn format(config: &str, source: &str) -> String {
+        format_with(source.to_string(), &FormatConfig::parse(config).unwrap())
+    }
+
+    #[test]
+    fn defaults_match_standard_formatting() {
+        let source = "* choice\n- -\ngathered\n";
+        assert_eq!(
+            format_with(source.to_string(), &FormatConfig::default()),
+            crate::fmt::format(source.to_string())
+        );
+    }
+
+    #[test]
+    fn gap_after_marks() {
+        let output = format("gap-after-marks = 1", "* choice\nmore\n");
+        assert_eq!(output, "* choice\n  more");
+    }
+
+    #[test]
+    fn tabs() {
+        let output = format(
+            r#"indent-style = "tabs""#,
+            indoc! {"
+                * a
+                * * a.1
+                more a.1
+            "},
+        );
+        assert_eq!(output, "*   a\n\t* *   a.1\n\t\t  more a.1");
+    }
+
+    #[test]
+    fn blank_lines() {
+        let output = format(
+            "blank-lines-before-knot = 1\nmax-blank-lines = 0",
+            "one\n\n\ntwo\n=== knot\nthree\n",
+        );
+        assert_eq!(output, "one\ntwo\n\n=== knot ===\n\nthree");
+    }
+
+    #[test]
+    fn empty_gathers() {
+        let source = "* choice\n-\ngathered\n";
+        assert_eq!(format("", source), "*   choice\n-\ngathered");
+        assert_eq!(
+            format("indent-empty-gathers = true", source),
+            "*   choice\n-\n    gathered"
+        );
+    }
+
+    #[test]
+    fn reflow() {
+        let source = "A line that is somewhat too long.";
+        let wrapped = format("reflow = \"wrap\"\nline-width = 20", source);
+        assert_eq!(wrapped, "A line that is <>\nsomewhat too long.");
+        assert_eq!(format("reflow = \"unwrap\"", &wrapped), source);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(FormatConfig::parse("gap-after-mark = 1").is_err());
+    }
+}
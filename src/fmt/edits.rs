@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use similar::{DiffOp, TextDiff};
+
+/// Replace the bytes in `range` of the original text with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// The smallest edits that turn `source` into `formatted`, in order and not overlapping.
+///
+/// Since formatting mostly moves whitespace around, applying these instead of replacing the
+/// whole text leaves everything else alone (cursor positions, undo history, …).
+pub fn edits(source: &str, formatted: &str) -> Vec<Edit> {
+    let mut result = Vec::new();
+    // Lines first, so that the (quadratic) character diff only sees the lines that changed.
+    let lines = TextDiff::from_lines(source, formatted);
+    let old_offsets = offsets(lines.old_slices());
+    let new_offsets = offsets(lines.new_slices());
+    for op in lines.ops() {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let old = old_offsets[op.old_range().start]..old_offsets[op.old_range().end];
+        let new = new_offsets[op.new_range().start]..new_offsets[op.new_range().end];
+        char_edits(
+            &source[old.clone()],
+            &formatted[new],
+            old.start,
+            &mut result,
+        );
+    }
+    result
+}
+
+fn char_edits(old: &str, new: &str, base: usize, result: &mut Vec<Edit>) {
+    let chars = TextDiff::from_chars(old, new);
+    let old_offsets = offsets(chars.old_slices());
+    let new_offsets = offsets(chars.new_slices());
+    for op in chars.ops() {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let range =
+            base + old_offsets[op.old_range().start]..base + old_offsets[op.old_range().end];
+        let text = &new[new_offsets[op.new_range().start]..new_offsets[op.new_range().end]];
+        match result.last_mut() {
+            // A deletion right before an insertion (or the other way round) is one replacement.
+            Some(last) if last.range.end == range.start => {
+                last.range.end = range.end;
+                last.text.push_str(text);
+            }
+            _ => result.push(Edit {
+                range,
+                text: text.to_string(),
+            }),
+        }
+    }
+}
+
+/// Where each slice starts, plus the end of the last one.
+fn offsets(slices: &[&str]) -> Vec<usize> {
+    let mut result = Vec::with_capacity(slices.len() + 1);
+    let mut offset = 0;
+    result.push(offset);
+    for slice in slices {
+        offset += slice.len();
+        result.push(offset);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edits, Edit};
+
+    fn apply(source: &str, edits: &[Edit]) -> String {
+        let mut result = source.to_string();
+        for edit in edits.iter().rev() {
+            result.replace_range(edit.range.clone(), &edit.text);
+        }
+        result
+    }
+
+    #[test]
+    fn only_touches_what_changed() {
+        let source = "*choice\ntext\nmore\n";
+        let formatted = "*   choice\n    text\nmore\n";
+        let edits = edits(source, formatted);
+        assert_eq!(
+            edits,
+            vec![
+                Edit {
+                    range: 1..1,
+                    text: "   ".to_string()
+                },
+                Edit {
+                    range: 8..8,
+                    text: "    ".to_string()
+                },
+            ]
+        );
+        assert_eq!(apply(source, &edits), formatted);
+    }
+
+    #[test]
+    fn deletions_and_replacements() {
+        let source = "===  knot  ==\n~x=1\n\n\n\ntext";
+        let formatted = "=== knot ===\n~ x = 1\n\ntext";
+        assert_eq!(apply(source, &edits(source, formatted)), formatted);
+    }
+
+    #[test]
+    fn nothing_to_do() {
+        assert_eq!(edits("same\n", "same\n"), vec![]);
+    }
+}
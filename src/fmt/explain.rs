@@ -0,0 +1,138 @@
+//! `ink-tool fmt --explain`: Which rules decided the whitespace in the output.
+
+use std::fmt::Write;
+
+use super::{
+    format_item::Origin,
+    formatting::{Decision, Explaining, Layout},
+    parse,
+    rules::position,
+    scanner::FormatScanner,
+    FormatConfig, Rules,
+};
+
+/// For each piece of whitespace in the formatted `source`, list where it is in the output,
+/// what it turned out to be, and which requests it was decided from:
+///
+/// ``` text
+/// 5:1 ⏎4 before "==="
+///     ⏎≈=4     @break.before.knot (format.scm:7) on knot_block
+///     ⏎≈1      whitespace in the source on knot_block
+/// ```
+///
+/// `⏎` stands for line breaks and `␣` for spaces. The requests show their constraints
+/// (`≈desired`, `=exactly`, `≥at least`, `≤at most`).
+///
+/// Reflowing (see [`crate::fmt::Reflow`]) happens afterwards and isn't explained.
+pub fn explain(source: String, config: &FormatConfig, rules: &Rules) -> String {
+    let tree = parse(&source);
+    let (query, extra_patterns) = rules.query();
+    let scanner = FormatScanner::new(query, extra_patterns, config);
+    let mut result = String::new();
+    let decisions = {
+        let mut formatter = Explaining::new(Layout::new(&mut result, config.indent_style));
+        scanner.scan(&tree, &source, &mut formatter);
+        formatter.decisions
+    };
+
+    let mut out = String::new();
+    let mut cursor = 0;
+    for Decision { requests, next } in decisions {
+        let Some(offset) = result[cursor..].find(&next) else {
+            log::warn!("Couldn't find {next:?} in the output, stopping the explanation");
+            break;
+        };
+        let at = cursor + offset;
+        let before = &result[cursor..at];
+        let whitespace = &before[before.trim_end().len()..];
+        cursor = at + next.len();
+        if requests.is_empty() {
+            continue;
+        }
+
+        let (line, column) = position(&result, at);
+        let preview: String = next
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(20)
+            .collect();
+        writeln!(
+            out,
+            "{line}:{column} {} before {preview:?}",
+            decided(whitespace)
+        )
+        .unwrap();
+        for request in requests {
+            let symbol = if request.line { '⏎' } else { '␣' };
+            let why = match request.why {
+                Some((kind, origin)) => format!("{} on {kind}", describe(&scanner, rules, origin)),
+                None => "(unknown)".to_string(),
+            };
+            let repeats = format!("{symbol}{:?}", request.repeats);
+            writeln!(out, "    {repeats:<8} {why}").unwrap();
+        }
+    }
+    out
+}
+
+fn describe(scanner: &FormatScanner, rules: &Rules, origin: Origin) -> String {
+    let query = scanner.query();
+    match origin {
+        Origin::Capture(pattern, capture) => format!(
+            "@{} ({})",
+            query.capture_names()[capture as usize],
+            rules.locate(query.start_byte_for_pattern(pattern))
+        ),
+        Origin::Alignment(pattern) => format!(
+            "@align.after ({})",
+            rules.locate(query.start_byte_for_pattern(pattern))
+        ),
+        Origin::Source => "whitespace in the source".to_string(),
+        Origin::Verbatim => "unformatted region".to_string(),
+    }
+}
+
+/// `⏎2` for two line breaks, `␣1` for a space.
+fn decided(whitespace: &str) -> String {
+    match whitespace.matches('\n').count() {
+        0 => format!("␣{}", whitespace.chars().count()),
+        lines => format!("⏎{lines}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain;
+    use crate::fmt::{FormatConfig, Rules};
+
+    #[test]
+    fn names_the_captures() {
+        let explanation = explain(
+            "text\n=== knot\n".to_string(),
+            &FormatConfig::default(),
+            &Rules::default(),
+        );
+        assert!(explanation.contains("5:1 ⏎4 before"), "{explanation}");
+        assert!(
+            explanation.contains("@break.before.knot (format.scm:7) on knot_block"),
+            "{explanation}"
+        );
+    }
+
+    #[test]
+    fn names_the_extra_rules() {
+        let rules = Rules::parse("\n(knot_block) @break.before.2", "house.scm").unwrap();
+        let explanation = explain(
+            "text\n=== knot\n".to_string(),
+            &FormatConfig::default(),
+            &rules,
+        );
+        assert!(explanation.contains("3:1 ⏎2 before"), "{explanation}");
+        assert!(
+            explanation.contains("@break.before.2 (house.scm:2) on knot_block"),
+            "{explanation}"
+        );
+    }
+}
@@ -3,10 +3,11 @@
 ; We have to check for consecutive blocks (instead of simply saying `(some_block) @break.before.…`)
 ; because comments can get in the way and add unwanted lines.
 ; That means that comments can disrupt spacing rules, but let's call that a feature ;).
-(knot_block (knot !function)) @break.before.4
-(stitch_block) @break.before.3
+; (The number of lines is configurable, see `FormatConfig`)
+(knot_block (knot !function)) @break.before.knot
+(stitch_block) @break.before.stitch
 
-; Allow individual content items to be offset by a single blank line at most
+; Allow individual content items to be offset by a single blank line at most (by default)
 [(todo_comment)
  (paragraph)
  (code)
@@ -15,7 +16,7 @@
  (global)
  (list)
  (choice_block)
- (gather_block)] @break.before.0-2 @break.after.0-2
+ (gather_block)] @break.before.paragraph @break.after.paragraph
 
 ; Text is a tricky beast; it actually has individual children (such as '<' '-' etc, to allow for parsing syntax elements)
 (text) @take.as-is @space.before.0-1 @space.after.0-1
@@ -30,6 +31,9 @@
 (gather_block (gather label: (_) @indent.anchor)) @dedent
 (gather_block (gather !label !eol) . (_) @indent.anchor) @dedent
 (gather_block (gather eol: (_) @delete) @break.after)
+; … unless configured otherwise.
+((gather_block (gather !label eol: (_)) @indent) @dedent
+ (#if-option "indent-empty-gathers"))
 
 ; Idea for a different style, basically a more 'extreme' version of the above:
 ; Only, gathers with content on the same line get indentation, all the other ones don't
@@ -80,7 +84,7 @@
 
 ;;; Normalize Choices and gathers
 [(choice_mark) (gather_mark)] @space.after
-[(choice_marks) (gather_marks)] @space.after.3 ; Visually offset text. A lot of example ink does this and it seems like a neat idea.
+[(choice_marks) (gather_marks)] @space.after.marks ; Visually offset text. A lot of example ink does this and it seems like a neat idea.
 
 (choice choice_only: (_) @space.before.0-1 @space.after.0-1)
 
@@ -135,3 +139,23 @@
 ["temp" "return" "VAR" "EXTERNAL" "LIST"] @space.after
 
 (paren "(" @space.after.0 ")" @space.before.0)
+(postfix (_) @space.after.0)
+(assignment _ @space.before @space.after (#any-of? @space.before "+=" "-="))
+(list_values _ @space.after.0 (#eq? @space.after.0 "("))
+(list_values _ @space.before.0 (#eq? @space.before.0 ")"))
+
+;;; Diverts: `-> target`, `->-> target`, `knot.stitch`
+(_ _ @space.after (#eq? @space.after "->->"))
+(qualified_name _ @space.before.0 @space.after.0 (#eq? @space.before.0 "."))
+
+;;; Declarations
+
+; The `=` of consecutive VAR/CONST lines line up
+(global name: (_) @align.after)
+
+; Lists that don't fit on a line get one item per line, aligned to the first one.
+; (The line width is configurable, see `FormatConfig`)
+((list values: (list_value_defs . (_) @indent.anchor) @dedent) @list
+ (#if-long @list))
+((list values: (list_value_defs "," @break.after)) @list
+ (#if-long @list))
@@ -1,6 +1,19 @@
-use crate::fmt::constrained_value::Constrained;
+use crate::fmt::{constrained_value::Constrained, CaptureIndex, PatternIndex};
 use std::fmt::{Debug, Write};
 
+/// Why some whitespace is asked for. Only used to explain the formatting (`fmt --explain`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Origin {
+    /// A capture in one of the patterns of the formatting query
+    Capture(PatternIndex, CaptureIndex),
+    /// `@align.after`, in one of the patterns of the formatting query
+    Alignment(PatternIndex),
+    /// The whitespace that was there in the source
+    Source,
+    /// A region that is left as it is
+    Verbatim,
+}
+
 #[derive(PartialEq)]
 pub struct Space {
     pub repeats: Constrained,
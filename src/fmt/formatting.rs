@@ -1,10 +1,11 @@
 mod formatters;
 mod whitespace;
 
-pub(crate) use formatters::layout::Layout;
+pub(crate) use formatters::explaining::{Decision, Explaining};
+pub(crate) use formatters::layout::{Layout, INDENT_WIDTH};
 pub(crate) use formatters::tracing::Tracing;
 
-use crate::fmt::constrained_value::Constrained;
+use crate::fmt::{constrained_value::Constrained, format_item::Origin};
 
 /// The operations to format a document with.
 pub trait Formatting {
@@ -16,6 +17,13 @@ pub trait Formatting {
     fn line(&mut self, repeats: impl Into<Constrained>);
 
     fn text(&mut self, s: &str);
+    /// Text to be output exactly as it is. Unlike [`Formatting::text`], its lines aren't
+    /// re-indented, so it should start at the beginning of a line.
+    fn verbatim(&mut self, s: &str);
+
+    /// Why the next space or line is asked for, and for which kind of node.
+    /// Only formatters that explain the formatting care about this.
+    fn explain(&mut self, _kind: &'static str, _origin: Origin) {}
 }
 
 impl<T: Formatting> Formatting for &mut T {
@@ -42,4 +50,12 @@ impl<T: Formatting> Formatting for &mut T {
     fn text(&mut self, s: &str) {
         (*self).text(s)
     }
+
+    fn verbatim(&mut self, s: &str) {
+        (*self).verbatim(s)
+    }
+
+    fn explain(&mut self, kind: &'static str, origin: Origin) {
+        (*self).explain(kind, origin)
+    }
 }
@@ -1,3 +1,4 @@
+pub(crate) mod explaining;
 pub(crate) mod layout;
 pub(crate) mod string;
 pub(crate) mod tracing;
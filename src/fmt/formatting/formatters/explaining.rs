@@ -0,0 +1,98 @@
+use crate::fmt::{constrained_value::Constrained, format_item::Origin, formatting::Formatting};
+
+/// Remember why each space and line was asked for, grouped by the text that follows them.
+///
+/// This sits in front of [`Layout`](super::layout::Layout), so it sees the instructions
+/// before they are combined into the whitespace that actually ends up in the output.
+#[derive(Debug)]
+pub(crate) struct Explaining<T> {
+    pub(crate) downstream: T,
+    why: Option<(&'static str, Origin)>,
+    pending: Vec<Request>,
+    pub(crate) decisions: Vec<Decision>,
+}
+
+/// A call to [`Formatting::space`] or [`Formatting::line`], and why it was made.
+#[derive(Debug, Clone)]
+pub(crate) struct Request {
+    pub(crate) line: bool,
+    pub(crate) repeats: Constrained,
+    /// The kind of node, and where the request comes from (if anyone said)
+    pub(crate) why: Option<(&'static str, Origin)>,
+}
+
+/// All the requests that decide the whitespace in front of `next` (there may be none).
+#[derive(Debug, Clone)]
+pub(crate) struct Decision {
+    pub(crate) requests: Vec<Request>,
+    pub(crate) next: String,
+}
+
+impl<T> Explaining<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Self {
+            downstream: inner,
+            why: None,
+            pending: Vec::new(),
+            decisions: Vec::new(),
+        }
+    }
+
+    fn request(&mut self, line: bool, repeats: Constrained) {
+        self.pending.push(Request {
+            line,
+            repeats,
+            why: self.why.take(),
+        });
+    }
+
+    fn decide(&mut self, next: &str) {
+        self.why = None;
+        if !next.is_empty() {
+            self.decisions.push(Decision {
+                requests: std::mem::take(&mut self.pending),
+                next: next.to_string(),
+            });
+        }
+    }
+}
+
+impl<T: Formatting> Formatting for Explaining<T> {
+    fn indent(&mut self) {
+        self.downstream.indent();
+    }
+
+    fn dedent(&mut self) {
+        self.downstream.dedent();
+    }
+
+    fn align_indent_to_current_column(&mut self) {
+        self.downstream.align_indent_to_current_column();
+    }
+
+    fn space(&mut self, repeats: impl Into<Constrained>) {
+        let repeats: Constrained = repeats.into();
+        self.request(false, repeats);
+        self.downstream.space(repeats);
+    }
+
+    fn line(&mut self, repeats: impl Into<Constrained>) {
+        let repeats: Constrained = repeats.into();
+        self.request(true, repeats);
+        self.downstream.line(repeats);
+    }
+
+    fn text(&mut self, s: &str) {
+        self.decide(s);
+        self.downstream.text(s);
+    }
+
+    fn verbatim(&mut self, s: &str) {
+        self.decide(s);
+        self.downstream.verbatim(s);
+    }
+
+    fn explain(&mut self, kind: &'static str, origin: Origin) {
+        self.why = Some((kind, origin));
+    }
+}
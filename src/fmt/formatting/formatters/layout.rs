@@ -1,4 +1,5 @@
 use crate::fmt::{
+    config::IndentStyle,
     constrained_value::Constrained,
     formatting::{whitespace, Formatting},
 };
@@ -13,8 +14,12 @@ pub(crate) struct Layout<T> {
     relative_indent: i8,
     current_column: usize,
     alignment: Option<Alignment>,
+    indent_style: IndentStyle,
 }
 
+/// Columns per indentation level (and per tab).
+pub(crate) const INDENT_WIDTH: usize = 4;
+
 /// When aligning, we need to wait for all the content to come in before we can decide how many spaces to add.
 #[derive(Debug)]
 enum Alignment {
@@ -61,10 +66,14 @@ impl<T: Formatting> Formatting for Layout<T> {
     fn text(&mut self, s: &str) {
         self.handle_next_bufferable(Bufferable::Text(s.to_owned()));
     }
+
+    fn verbatim(&mut self, s: &str) {
+        self.handle_next_bufferable(Bufferable::Verbatim(s.to_owned()));
+    }
 }
 
 impl<T: Formatting> Layout<T> {
-    pub fn new(downstream: T) -> Self {
+    pub fn new(downstream: T, indent_style: IndentStyle) -> Self {
         Self {
             downstream,
             buffer_item: None,
@@ -72,7 +81,23 @@ impl<T: Formatting> Layout<T> {
             relative_indent: 0,
             current_column: 0,
             alignment: None,
+            indent_style,
+        }
+    }
+
+    /// Move to `column` at the start of a line.
+    fn indent_to(&mut self, column: usize) {
+        match self.indent_style {
+            IndentStyle::Spaces => self.downstream.space(column),
+            IndentStyle::Tabs => {
+                let tabs = column / INDENT_WIDTH;
+                if tabs > 0 {
+                    self.downstream.text(&"\t".repeat(tabs));
+                }
+                self.downstream.space(column % INDENT_WIDTH);
+            }
         }
+        self.current_column = column;
     }
 
     pub(crate) fn handle_next_bufferable(&mut self, next: Bufferable) {
@@ -81,7 +106,7 @@ impl<T: Formatting> Layout<T> {
             return;
         }
 
-        let next_is_text = matches!(next, Bufferable::Text(_));
+        let next_is_text = !matches!(next, Bufferable::Whitespace(_));
 
         let buf = self
             .buffer_item
@@ -91,55 +116,69 @@ impl<T: Formatting> Layout<T> {
         use Bufferable as B;
         self.buffer_item = Some(match (buf, next) {
             (B::Whitespace(a), B::Whitespace(b)) => B::Whitespace(a + b),
-            (B::Whitespace(undecided), text @ B::Text(_)) => {
-                match whitespace::Whitespace::from(undecided) {
-                    whitespace::Whitespace::Space(spaces) => {
-                        self.downstream.space(spaces);
-                        self.current_column += spaces.value() as usize;
-                        text
-                    }
-                    whitespace::Whitespace::Newline(newlines) => {
-                        // new text after a line break. This is where we must handle indentation, and only here!
-                        self.downstream.line(newlines);
-                        if self.relative_indent > 0 {
-                            let next_indent = match self.alignment {
-                                Some(Alignment::Determined(column)) => column,
-                                Some(Alignment::Tentative) => {
-                                    log::warn!("Tentative alignment at newline. Is this a bug?");
-                                    *self.indents.last().expect("this shouldn't be empty") + 4
-                                }
-                                None => *self.indents.last().expect("this shouldn't be empty") + 4,
-                            };
-                            for _ in 0..self.relative_indent {
-                                self.indents.push(next_indent);
-                            }
-                        } else if self.relative_indent < 0 {
-                            for _ in self.relative_indent..0 {
-                                self.indents.pop();
-                            }
-                        }
-                        self.relative_indent = 0;
-                        self.alignment = None;
-                        let column_for_next_line =
-                            *self.indents.last().expect("this shouldn't be empty");
-                        self.downstream.space(column_for_next_line);
-                        self.current_column = column_for_next_line;
-
-                        text
+            (B::Whitespace(undecided), text) => match whitespace::Whitespace::from(undecided) {
+                whitespace::Whitespace::Space(spaces) => {
+                    self.downstream.space(spaces);
+                    self.current_column += spaces.value() as usize;
+                    text
+                }
+                whitespace::Whitespace::Newline(newlines) => {
+                    // new text after a line break. This is where we must handle indentation, and only here!
+                    let column_for_next_line = self.start_line(newlines);
+                    if let B::Verbatim(_) = text {
+                        // brings its own indentation
+                        self.current_column = 0;
+                    } else {
+                        self.indent_to(column_for_next_line);
                     }
+                    text
                 }
-            }
+            },
             (B::Text(l), B::Text(r)) => B::Text(l + &r),
-            (B::Text(t), whitespace @ B::Whitespace(_)) => {
+            (B::Text(t), next) => {
                 self.current_column += t.len();
                 self.downstream.text(&t);
-                whitespace
+                next
+            }
+            (B::Verbatim(t), next) => {
+                self.current_column = match t.rfind('\n') {
+                    Some(newline) => t.len() - newline - 1,
+                    None => self.current_column + t.len(),
+                };
+                self.downstream.text(&t);
+                next
             }
         });
         if next_is_text && matches!(self.alignment, Some(Alignment::Tentative)) {
             self.alignment = Some(Alignment::Determined(self.current_column));
         }
     }
+
+    /// Output line breaks and update the indentation levels. Returns the column the next line
+    /// should be indented to.
+    fn start_line(&mut self, newlines: Constrained) -> usize {
+        self.downstream.line(newlines);
+        if self.relative_indent > 0 {
+            let next_indent = match self.alignment {
+                Some(Alignment::Determined(column)) => column,
+                Some(Alignment::Tentative) => {
+                    log::warn!("Tentative alignment at newline. Is this a bug?");
+                    *self.indents.last().expect("this shouldn't be empty") + INDENT_WIDTH
+                }
+                None => *self.indents.last().expect("this shouldn't be empty") + INDENT_WIDTH,
+            };
+            for _ in 0..self.relative_indent {
+                self.indents.push(next_indent);
+            }
+        } else if self.relative_indent < 0 {
+            for _ in self.relative_indent..0 {
+                self.indents.pop();
+            }
+        }
+        self.relative_indent = 0;
+        self.alignment = None;
+        *self.indents.last().expect("this shouldn't be empty")
+    }
 }
 
 /// Buffer to hold the next piece of content.
@@ -149,6 +188,8 @@ impl<T: Formatting> Layout<T> {
 pub(crate) enum Bufferable {
     Whitespace(whitespace::Undecided),
     Text(String),
+    /// Text that is output exactly as it is (including the indentation of its lines)
+    Verbatim(String),
 }
 
 impl std::fmt::Debug for Bufferable {
@@ -156,6 +197,7 @@ impl std::fmt::Debug for Bufferable {
         match self {
             Bufferable::Whitespace(it) => it.fmt(f),
             Bufferable::Text(it) => write!(f, "'{:?}'", it),
+            Bufferable::Verbatim(it) => write!(f, "`{:?}`", it),
         }
     }
 }
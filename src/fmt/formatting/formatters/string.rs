@@ -25,4 +25,8 @@ impl Formatting for &mut String {
     fn text(&mut self, s: &str) {
         self.push_str(s)
     }
+
+    fn verbatim(&mut self, s: &str) {
+        self.push_str(s)
+    }
 }
@@ -1,4 +1,4 @@
-use crate::fmt::{constrained_value::Constrained, formatting::Formatting};
+use crate::fmt::{constrained_value::Constrained, format_item::Origin, formatting::Formatting};
 
 /// Keep a log of formatting instructions.
 ///
@@ -65,4 +65,16 @@ impl<T: Formatting> Formatting for Tracing<T> {
         self.trace.push_str(s);
         self.trace.push('\'');
     }
+
+    fn verbatim(&mut self, s: &str) {
+        self.downstream.verbatim(s);
+        self.sep_space();
+        self.trace.push('`');
+        self.trace.push_str(s);
+        self.trace.push('`');
+    }
+
+    fn explain(&mut self, kind: &'static str, origin: Origin) {
+        self.downstream.explain(kind, origin);
+    }
 }
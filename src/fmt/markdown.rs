@@ -0,0 +1,167 @@
+//! Formatting the ink code blocks in Markdown files, such as `doc/ink-fmt.md`.
+
+use markdown::{mdast::Node, ParseOptions};
+
+use super::{FormatConfig, Formatted, Formatter, Rules};
+
+/// Words in the info string of a code block (```` ```ink no-fmt ````) that keep it as it is.
+/// `input` is for examples of unformatted ink, like the ones in `doc/ink-fmt.md`.
+const OPT_OUT: &[&str] = &["input", "no-fmt"];
+
+/// Format every ```` ```ink ```` block in `source`, see [`Formatter::format_markdown`].
+pub fn format_markdown(source: String, config: &FormatConfig, rules: &Rules) -> Formatted {
+    Formatter::new(config, rules).format_markdown(source)
+}
+
+impl Formatter {
+    /// Format every ```` ```ink ```` block in `source`, and leave everything else untouched.
+    ///
+    /// Only blocks at the top level of the document are formatted, not ones in lists or quotes.
+    /// The unformatted ranges refer to `source`.
+    pub fn format_markdown(&self, source: String) -> Formatted {
+        let mut text = String::with_capacity(source.len());
+        let mut unformatted = Vec::new();
+        let mut copied = 0;
+        for (start, code) in ink_blocks(&source) {
+            let end = start + code.len();
+            let formatted = self.format(code.to_string());
+            text.push_str(&source[copied..start]);
+            text.push_str(&formatted.text);
+            unformatted.extend(
+                formatted
+                    .unformatted
+                    .into_iter()
+                    .map(|it| it.start + start..it.end + start),
+            );
+            copied = end;
+        }
+        text.push_str(&source[copied..]);
+        Formatted { text, unformatted }
+    }
+}
+
+/// The start and content of each ink code block that should be formatted.
+fn ink_blocks(source: &str) -> Vec<(usize, &str)> {
+    let Ok(root) = markdown::to_mdast(source, &ParseOptions::gfm()) else {
+        // Markdown has no syntax errors, so this doesn't happen.
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+    for node in root.children().into_iter().flatten() {
+        let Node::Code(code) = node else {
+            continue;
+        };
+        let Some(position) = &code.position else {
+            continue;
+        };
+        let opted_out = code
+            .meta
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|it| OPT_OUT.contains(&it));
+        if code.lang.as_deref() != Some("ink") || opted_out {
+            continue;
+        }
+        // The content starts on the line after the opening fence. Blocks that are indented
+        // don't have their content there verbatim; those are left alone.
+        let fenced = &source[position.start.offset..position.end.offset];
+        let Some(first_line) = fenced.find('\n') else {
+            continue;
+        };
+        let start = position.start.offset + first_line + 1;
+        if source[start..].starts_with(code.value.as_str()) {
+            result.push((start, &source[start..start + code.value.len()]));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_markdown;
+    use crate::fmt::{FormatConfig, Rules};
+    use indoc::indoc;
+
+    fn format(source: &str) -> String {
+        format_markdown(
+            source.to_string(),
+            &FormatConfig::default(),
+            &Rules::default(),
+        )
+        .text
+    }
+
+    #[test]
+    fn formats_ink_blocks() {
+        let source = indoc! {"
+            # Example
+
+            Some *text*   that stays.
+
+            ```ink
+            *choice
+            more
+            ```
+
+            ```rust
+            fn  main()  {}
+            ```
+        "};
+        assert_eq!(
+            format(source),
+            indoc! {"
+                # Example
+
+                Some *text*   that stays.
+
+                ```ink
+                *   choice
+                    more
+                ```
+
+                ```rust
+                fn  main()  {}
+                ```
+            "}
+        );
+    }
+
+    #[test]
+    fn skips_opted_out_blocks() {
+        let source = indoc! {"
+            ```ink input
+            *choice
+            ```
+
+            ```ink no-fmt
+            *choice
+            ```
+        "};
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn leaves_nested_blocks_alone() {
+        let source = indoc! {"
+            * A list item:
+
+              ```ink
+              *choice
+              ```
+        "};
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn errors_are_reported_in_the_markdown_file() {
+        let source = "Text\n\n```ink\n* choice {a\n```\n";
+        let formatted = format_markdown(
+            source.to_string(),
+            &FormatConfig::default(),
+            &Rules::default(),
+        );
+        assert_eq!(formatted.text, source);
+        assert_eq!(formatted.unformatted[0].start, source.find('*').unwrap());
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt::{Debug, Write};
 
 use std::collections::HashMap;
 
-use super::format_item::FormatItem;
+use super::format_item::{FormatItem, Origin};
 use super::NodeId;
 
 pub(crate) type NodeRules = HashMap<NodeId, NodeRule>;
@@ -32,11 +32,26 @@ pub(crate) struct NodeRule {
     pub(crate) indent: IndentType,
     pub(crate) dedent: DedentType,
     pub(crate) take_asis: bool,
-    pub(crate) before: Vec<FormatItem>,
-    pub(crate) after: Vec<FormatItem>,
+    pub(crate) before: Vec<(FormatItem, Origin)>,
+    pub(crate) after: Vec<(FormatItem, Origin)>,
     pub(crate) replace: Option<FormatItem>,
 }
 
+impl NodeRule {
+    /// Use the spacing from `other` instead of this rule's own, on the sides where `other` has any.
+    /// Text that is prepended or appended stays.
+    pub(crate) fn override_spacing(&mut self, other: NodeRule) {
+        fn replace(items: &mut Vec<(FormatItem, Origin)>, spacing: Vec<(FormatItem, Origin)>) {
+            if !spacing.is_empty() {
+                items.retain(|(it, _)| !matches!(it, FormatItem::Space(_) | FormatItem::Line(_)));
+                items.extend(spacing);
+            }
+        }
+        replace(&mut self.before, other.before);
+        replace(&mut self.after, other.after);
+    }
+}
+
 impl Debug for NodeRule {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.take_asis {
@@ -52,13 +67,15 @@ impl Debug for NodeRule {
             f.write_char('‹')?;
         }
         if !self.before.is_empty() {
-            f.write_fmt(format_args!("⨭{:?}", self.before))?
+            let items: Vec<_> = self.before.iter().map(|(it, _)| it).collect();
+            f.write_fmt(format_args!("⨭{:?}", items))?
         }
         if let Some(ref it) = self.replace {
             f.write_fmt(format_args!("•{:?}", it))?
         }
         if !self.after.is_empty() {
-            f.write_fmt(format_args!("{:?}⨮", self.after))?
+            let items: Vec<_> = self.after.iter().map(|(it, _)| it).collect();
+            f.write_fmt(format_args!("{:?}⨮", items))?
         }
         if let DedentType::DedentThis = self.dedent {
             f.write_char('‹')?;
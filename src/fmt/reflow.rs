@@ -0,0 +1,378 @@
+//! Wrapping long lines of prose, and joining them back together.
+//!
+//! In ink, every line is its own paragraph, so a line can't simply be broken in two.
+//! Instead, the first half ends in glue (`<>`), which makes ink join the halves again:
+//!
+//! ``` ink
+//! This line is a bit too long, <>
+//! so it continues here.
+//! ```
+//!
+//! Only lines that consist of nothing but a paragraph are wrapped, and only at spaces in the
+//! text itself (not inside `{…}`, tags or diverts, and not where the continuation would be read
+//! as syntax, such as `* …` or `VAR …`). Choices are wrapped after their `]`, where the text is
+//! only output; glue before it would change what the choice shows, so choices without `[…]`
+//! and gathers are left alone. A choice continues at the indentation of its content.
+//!
+//! Glue isn't marked as added by [`wrap`], so only glue that looks like it was is joined
+//! again: a single space and `<>` at the end of a line, followed by text on the next line at
+//! the indentation that `wrap` would use. Glue written any other way (`word<>`, or `<>` at
+//! the start of the next line) stays where it is.
+
+use std::ops::Range;
+
+use tree_sitter::{Node, Tree};
+
+use super::{
+    formatting::INDENT_WIDTH,
+    parse,
+    verbatim::{line_end, line_start, Verbatim},
+    IndentStyle,
+};
+
+/// What goes between the halves of a wrapped line (plus the indentation).
+const GLUE: &str = " <>\n";
+
+/// Keywords that would make a continuation line something else than text.
+const KEYWORDS: &[&str] = &["VAR", "CONST", "LIST", "INCLUDE", "EXTERNAL", "TODO"];
+
+/// A line that can be wrapped.
+struct Line<'t> {
+    /// A paragraph or a choice
+    node: Node<'t>,
+    /// Where the part that can be wrapped starts, which is after the `]` of a choice
+    from: usize,
+    /// The indentation of the lines that continue it
+    indent: String,
+}
+
+/// Break lines longer than `width` into glued lines. Lines that were wrapped before are
+/// joined first, so that paragraphs are filled evenly after editing.
+pub(crate) fn wrap(source: &str, width: usize, style: IndentStyle) -> String {
+    let source = unwrap(source, style);
+    let tree = parse(&source);
+    let verbatim = Verbatim::new(&tree, &source);
+
+    let mut edits = Vec::new();
+    for line in lines(&tree, &source, &verbatim, style) {
+        let end = line_end(&source, text_end(line.node, &source));
+        // Width of a line that starts with `indent`, followed by the text from `from` to `to`
+        let width_of =
+            |indent: &str, from: usize, to: usize| columns(indent) + columns(&source[from..to]);
+
+        let mut start = line_start(&source, line.node.start_byte());
+        let mut indent = "";
+        let mut breaks = break_points(&line, &source).into_iter().peekable();
+        while width_of(indent, start, end) > width {
+            // The last break that still fits, or the first one, if none does.
+            let Some(mut at) = breaks.next() else {
+                break;
+            };
+            while let Some(&next) = breaks.peek() {
+                if width_of(indent, start, next) + " <>".len() > width {
+                    break;
+                }
+                at = next;
+                breaks.next();
+            }
+            edits.push((at..at + 1, format!("{GLUE}{}", line.indent)));
+            start = at + 1;
+            indent = &line.indent;
+        }
+    }
+    apply(&source, edits)
+}
+
+/// Join the lines that [`wrap`] broke, undoing it.
+pub(crate) fn unwrap(source: &str, style: IndentStyle) -> String {
+    join(source, style, true)
+}
+
+/// Join every line that ends in glue with the paragraph on the next line, whether [`wrap`]
+/// put the glue there or not. For comparing texts that may have been wrapped differently.
+pub(crate) fn join_all(source: &str) -> String {
+    join(source, IndentStyle::Spaces, false)
+}
+
+fn join(source: &str, style: IndentStyle, only_wrapped: bool) -> String {
+    let tree = parse(source);
+    let verbatim = Verbatim::new(&tree, source);
+    let lines = lines(&tree, source, &verbatim, style);
+
+    let mut edits = Vec::new();
+    for (first, second) in lines.iter().zip(lines.iter().skip(1)) {
+        let end = text_end(first.node, source);
+        let Some(glue) = end.checked_sub("<>".len()) else {
+            continue;
+        };
+        let is_glued = first
+            .node
+            .descendant_for_byte_range(glue, end)
+            .is_some_and(|it| it.kind() == "glue");
+        let eol = line_end(source, end);
+        let is_next = source[end..eol].trim().is_empty()
+            && second.node.kind() == "paragraph"
+            && eol + 1 == line_start(source, second.node.start_byte())
+            && continues(first.node, second.node);
+        if is_glued && is_next && (!only_wrapped || looks_wrapped(first, second, glue, source)) {
+            // Whatever space there is before the glue stays.
+            edits.push((glue..second.node.start_byte(), String::new()));
+        }
+    }
+    apply(source, edits)
+}
+
+/// Whether `second` (a paragraph on the next line) belongs to the same block as `first`.
+fn continues(first: Node, second: Node) -> bool {
+    match first.kind() {
+        // The paragraph is the first thing in the choice's block.
+        "choice" => first
+            .parent()
+            .is_some_and(|it| it.byte_range().contains(&second.start_byte())),
+        _ => first.parent() == second.parent(),
+    }
+}
+
+/// Whether the glue at `glue` (the end of `first`) and the start of `second` look like
+/// [`wrap`] made them, see the module documentation.
+fn looks_wrapped(first: &Line, second: &Line, glue: usize, source: &str) -> bool {
+    let before = &source[first.from..glue];
+    let Some(text) = before.strip_suffix(' ') else {
+        return false;
+    };
+    let indent = &source[line_start(source, second.node.start_byte())..second.node.start_byte()];
+    let word = source[second.node.byte_range()]
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    !text.trim().is_empty()
+        && !text.ends_with(char::is_whitespace)
+        && indent == first.indent
+        && can_start_line(word)
+}
+
+/// Paragraphs that have a line to themselves (except for indentation and comments), and
+/// choices with `[…]` on a line of their own, outside of verbatim regions, in order.
+fn lines<'t>(
+    tree: &'t Tree,
+    source: &str,
+    verbatim: &Verbatim,
+    style: IndentStyle,
+) -> Vec<Line<'t>> {
+    let mut result = Vec::new();
+    let mut cursor = tree.walk();
+    'nodes: loop {
+        let node = cursor.node();
+        if matches!(node.kind(), "paragraph" | "choice") {
+            let start = node.start_byte();
+            let line = line_start(source, start);
+            let alone = source[line..start].trim().is_empty()
+                && !source[node.byte_range()].trim_end().contains('\n')
+                && verbatim.region_of(node, source).is_none();
+            let wrappable = match node.kind() {
+                _ if !alone => None,
+                "paragraph" => Some(Line {
+                    node,
+                    from: start,
+                    indent: source[line..start].to_string(),
+                }),
+                _ => choice_line(node, source, style),
+            };
+            result.extend(wrappable);
+        } else if cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                continue 'nodes;
+            }
+            if !cursor.goto_parent() {
+                break 'nodes;
+            }
+        }
+    }
+    result
+}
+
+/// The part of `choice` after its last `]`, if it has one. Its content starts right after the
+/// marks, and the choice's block is indented to there.
+fn choice_line<'t>(choice: Node<'t>, source: &str, style: IndentStyle) -> Option<Line<'t>> {
+    let items = items(choice);
+    let marks = items.iter().position(|it| it.kind() == "choice_marks")?;
+    let content = items.get(marks + 1)?;
+    let output = items.iter().rev().find(|it| it.kind() == "choice_only")?;
+    let column = columns(&source[line_start(source, choice.start_byte())..content.start_byte()]);
+    Some(Line {
+        node: choice,
+        from: output.end_byte(),
+        indent: indentation(column, style),
+    })
+}
+
+/// The named children of `node`, with the ones of `content` nodes in their place.
+fn items<'t>(node: Node<'t>) -> Vec<Node<'t>> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flat_map(|it| match it.kind() {
+            "content" => items(it),
+            _ => vec![it],
+        })
+        .collect()
+}
+
+/// Positions of spaces in the wrappable part of `line` where it can be wrapped without
+/// changing what it means.
+fn break_points(line: &Line, source: &str) -> Vec<usize> {
+    let mut logic = Vec::new();
+    collect_logic(line.node, &mut logic);
+    let range = line.from..text_end(line.node, source);
+    source[range.clone()]
+        .match_indices(' ')
+        .map(|(offset, _)| range.start + offset)
+        .filter(|&at| !logic.iter().any(|it| it.contains(&at)))
+        .filter(|&at| {
+            let before = &source[range.start..at];
+            let word = source[at + 1..range.end]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+            let after = &source[at + 1..range.end];
+            !before.trim().is_empty()
+                && !before.ends_with('\\')
+                && !after.starts_with(' ')
+                && can_start_line(word)
+        })
+        .collect()
+}
+
+/// The ranges of everything in `node` that isn't plain text.
+fn collect_logic(node: Node, result: &mut Vec<Range<usize>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "text" => {}
+            "content" => collect_logic(child, result),
+            _ => result.push(child.byte_range()),
+        }
+    }
+}
+
+/// Whether a line that starts with `word` is plain text.
+fn can_start_line(word: &str) -> bool {
+    let is_keyword = KEYWORDS.iter().any(|it| word.trim_end_matches(':') == *it);
+    let starts_as_text = word
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || "\"'“‘„«".contains(c));
+    starts_as_text && !is_keyword
+}
+
+fn apply(source: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    let mut result = source.to_string();
+    edits.sort_by_key(|(range, _)| range.start);
+    for (range, replacement) in edits.into_iter().rev() {
+        result.replace_range(range, &replacement);
+    }
+    result
+}
+
+/// Width of `text`, with tabs as wide as the formatter makes them.
+fn columns(text: &str) -> usize {
+    text.chars()
+        .map(|it| if it == '\t' { INDENT_WIDTH } else { 1 })
+        .sum()
+}
+
+/// The indentation the formatter uses to get to `column`.
+fn indentation(column: usize, style: IndentStyle) -> String {
+    match style {
+        IndentStyle::Spaces => " ".repeat(column),
+        IndentStyle::Tabs => {
+            "\t".repeat(column / INDENT_WIDTH) + &" ".repeat(column % INDENT_WIDTH)
+        }
+    }
+}
+
+/// Where `node` ends, not counting trailing whitespace (such as the line break).
+fn text_end(node: Node, source: &str) -> usize {
+    node.start_byte() + source[node.byte_range()].trim_end().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unwrap, wrap};
+    use crate::fmt::IndentStyle::{Spaces, Tabs};
+
+    const LONG: &str = "This is a rather long line of text that goes on.\n";
+    const WRAPPED: &str = "This is a rather <>\nlong line of text <>\nthat goes on.\n";
+
+    #[test]
+    fn wraps_at_width() {
+        assert_eq!(wrap(LONG, 20, Spaces), WRAPPED);
+    }
+
+    #[test]
+    fn unwrap_undoes_wrap() {
+        assert_eq!(unwrap(WRAPPED, Spaces), LONG);
+    }
+
+    #[test]
+    fn rewraps() {
+        assert_eq!(
+            wrap(WRAPPED, 30, Spaces),
+            "This is a rather long line <>\nof text that goes on.\n"
+        );
+    }
+
+    #[test]
+    fn keeps_indentation() {
+        assert_eq!(
+            wrap("*   choice\n    some more words here\n", 16, Spaces),
+            "*   choice\n    some more <>\n    words here\n"
+        );
+    }
+
+    #[test]
+    fn doesnt_break_before_syntax() {
+        assert_eq!(
+            wrap("Some words * more\n", 10, Spaces),
+            "Some <>\nwords * <>\nmore\n"
+        );
+        assert_eq!(wrap("Some VAR more\n", 8, Spaces), "Some VAR <>\nmore\n");
+    }
+
+    #[test]
+    fn doesnt_break_logic() {
+        assert_eq!(
+            wrap("aa {x: bb cc} dd\n", 5, Spaces),
+            "aa {x: bb cc} <>\ndd\n"
+        );
+    }
+
+    #[test]
+    fn leaves_short_lines_and_choices_alone() {
+        let source = "*   a choice with a long text\nshort\n";
+        assert_eq!(wrap(source, 10, Spaces), source);
+    }
+
+    #[test]
+    fn wraps_choices_after_the_brackets() {
+        let source = "*   [Go] and off you go to the far side\n";
+        let wrapped = "*   [Go] and off you <>\n    go to the far side\n";
+        assert_eq!(wrap(source, 24, Spaces), wrapped);
+        assert_eq!(unwrap(wrapped, Spaces), source);
+        assert_eq!(
+            wrap("\t* *   [Go] and off you go to the far side\n", 28, Tabs),
+            "\t* *   [Go] and off <>\n\t\t  you go to the <>\n\t\t  far side\n"
+        );
+    }
+
+    #[test]
+    fn keeps_glue_that_wrap_wouldnt_add() {
+        let source = "Hello<>\nworld, <>\n  and the rest\n<> of it\n";
+        assert_eq!(unwrap(source, Spaces), source);
+        assert_eq!(wrap(source, 20, Spaces), source);
+    }
+}
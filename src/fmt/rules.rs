@@ -0,0 +1,219 @@
+use std::{collections::HashSet, path::Path};
+
+use tree_sitter::{Language, Query, QueryError, QueryErrorKind, QueryPredicateArg};
+
+use crate::{
+    fmt::{scanner::is_known_capture, FormatConfig},
+    AppResult,
+};
+
+static BUILTIN: &str = include_str!("format.scm");
+
+/// The formatting rules: the built-in ones from `format.scm`, plus project specific ones
+/// that are layered on top.
+///
+/// The extra rules use the same captures and predicates as `format.scm`. Where they put
+/// spacing before or after a node, it replaces the built-in spacing on that side, so that
+/// something like
+///
+/// ``` scheme
+/// (stitch_block) @break.before.2
+/// ```
+///
+/// means "exactly one blank line before stitches", no matter what the built-in rules say.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rules {
+    extra: String,
+    /// Where `extra` comes from
+    name: String,
+}
+
+impl Rules {
+    /// Check the extra rules in `text`. Errors mention `name` and the position of the problem.
+    pub fn parse(text: &str, name: &str) -> Result<Self, String> {
+        let query = Query::new(&language(), text).map_err(|err| describe(&err, name))?;
+        let mut arguments = HashSet::new();
+        for pattern in 0..query.pattern_count() {
+            for predicate in query.general_predicates(pattern) {
+                if let Err(problem) = check_predicate(&predicate.operator, &predicate.args) {
+                    let (line, column) = position(text, query.start_byte_for_pattern(pattern));
+                    return Err(format!(
+                        "{name}:{line}:{column}: invalid formatting rule: {problem}"
+                    ));
+                }
+                arguments.extend(predicate.args.iter().filter_map(|it| match it {
+                    QueryPredicateArg::Capture(index) => Some(*index),
+                    QueryPredicateArg::String(_) => None,
+                }));
+            }
+        }
+        // The scanner ignores captures it doesn't know, so a typo would go unnoticed.
+        for (index, capture) in query.capture_names().iter().enumerate() {
+            if !is_known_capture(capture) && !arguments.contains(&(index as u32)) {
+                let (line, column) = position(text, capture_offset(text, capture));
+                return Err(format!(
+                    "{name}:{line}:{column}: unknown capture '{capture}'"
+                ));
+            }
+        }
+        Ok(Self {
+            extra: text.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    pub fn read(path: &Path) -> AppResult<Self> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(Self::parse(&text, &path.display().to_string())?)
+    }
+
+    /// The query with all the rules, and the index of the first pattern of the extra ones.
+    pub(crate) fn query(&self) -> (Query, usize) {
+        let language = language();
+        let builtin = Query::new(&language, BUILTIN).expect("query should be valid");
+        if self.extra.is_empty() {
+            let count = builtin.pattern_count();
+            return (builtin, count);
+        }
+        let query = Query::new(&language, &format!("{BUILTIN}\n{}", self.extra))
+            .expect("both parts have been checked");
+        (query, builtin.pattern_count())
+    }
+
+    /// `file:line` of the pattern that starts at `byte` of the combined query.
+    pub(crate) fn locate(&self, byte: usize) -> String {
+        if byte < BUILTIN.len() {
+            format!("format.scm:{}", position(BUILTIN, byte).0)
+        } else {
+            let byte = (byte - BUILTIN.len()).saturating_sub(1);
+            format!("{}:{}", self.name, position(&self.extra, byte).0)
+        }
+    }
+}
+
+fn language() -> Language {
+    tree_sitter_ink::LANGUAGE.into()
+}
+
+/// The predicates that `FormatScanner` understands.
+fn check_predicate(operator: &str, args: &[QueryPredicateArg]) -> Result<(), String> {
+    use QueryPredicateArg::{Capture, String as Str};
+    match (operator, args) {
+        ("prepend" | "append" | "replace", [Capture(_), Str(_)]) => Ok(()),
+        ("if-long", [Capture(_)]) => Ok(()),
+        ("if-option", [Str(name)]) => match FormatConfig::default().option(name) {
+            Some(_) => Ok(()),
+            None => Err(format!("unknown option '{name}' in #if-option")),
+        },
+        ("prepend" | "append" | "replace" | "if-long" | "if-option", _) => {
+            Err(format!("wrong arguments for #{operator}"))
+        }
+        _ => Err(format!("unknown predicate #{operator}")),
+    }
+}
+
+fn describe(err: &QueryError, name: &str) -> String {
+    let problem = match err.kind {
+        QueryErrorKind::Syntax => "syntax error".to_string(),
+        QueryErrorKind::NodeType => format!("unknown node type '{}'", err.message),
+        QueryErrorKind::Field => format!("unknown field '{}'", err.message),
+        QueryErrorKind::Capture => format!("unknown capture '{}'", err.message),
+        QueryErrorKind::Predicate => err.message.clone(),
+        QueryErrorKind::Structure => "this pattern can never match".to_string(),
+        QueryErrorKind::Language => err.message.clone(),
+    };
+    format!(
+        "{name}:{}:{}: invalid formatting rule: {problem}",
+        err.row + 1,
+        err.column + 1
+    )
+}
+
+/// Where `@capture` first appears in `text`.
+fn capture_offset(text: &str, capture: &str) -> usize {
+    let is_name = |c: char| c.is_alphanumeric() || "_-.".contains(c);
+    text.match_indices(&format!("@{capture}"))
+        .map(|(offset, _)| offset)
+        .find(|offset| !text[offset + 1 + capture.len()..].starts_with(is_name))
+        .unwrap_or(0)
+}
+
+/// 1-based line and column of `offset`.
+pub(crate) fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |it| it + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rules;
+    use crate::fmt::{format_with_rules, FormatConfig};
+
+    fn format(rules: &str, source: &str) -> String {
+        let rules = Rules::parse(rules, "test.scm").unwrap();
+        format_with_rules(source.to_string(), &FormatConfig::default(), &rules).text
+    }
+
+    #[test]
+    fn extra_rules_override_builtin_spacing() {
+        let output = format("(stitch_block) @break.before.1", "text\n= stitch\nmore\n");
+        assert!(output.starts_with("text\n= stitch"), "{output}");
+    }
+
+    #[test]
+    fn house_style() {
+        let output = format(
+            "[(choice_marks) (gather_marks)] @space.after.1",
+            "* choice\n- gather\n",
+        );
+        assert_eq!(output, "* choice\n- gather");
+    }
+
+    #[test]
+    fn syntax_errors_have_a_position() {
+        let err = Rules::parse("(paragraph) @break.after\n(stitch_block", "my.scm").unwrap_err();
+        assert!(err.starts_with("my.scm:2:"), "{err}");
+    }
+
+    #[test]
+    fn unknown_node_types() {
+        let err = Rules::parse("\n  (paragraf) @break.after", "my.scm").unwrap_err();
+        assert_eq!(
+            err,
+            "my.scm:2:4: invalid formatting rule: unknown node type 'paragraf'"
+        );
+    }
+
+    #[test]
+    fn unknown_predicates() {
+        let err = Rules::parse("((paragraph) @p (#frobnicate @p))", "my.scm").unwrap_err();
+        assert_eq!(
+            err,
+            "my.scm:1:1: invalid formatting rule: unknown predicate #frobnicate"
+        );
+    }
+
+    #[test]
+    fn unknown_captures() {
+        for (rules, capture) in [
+            ("(paragraph) @space.afer.1", "space.afer.1"),
+            ("(knot_block) @break.before.knott", "break.before.knott"),
+            ("(paragraph)\n  (choice) @indnet", "indnet"),
+        ] {
+            let err = Rules::parse(rules, "my.scm").unwrap_err();
+            assert!(
+                err.ends_with(&format!(": unknown capture '{capture}'")),
+                "{err}"
+            );
+        }
+        let err = Rules::parse("(paragraph)\n  (choice) @indnet", "my.scm").unwrap_err();
+        assert_eq!(err, "my.scm:2:12: unknown capture 'indnet'");
+        // Captures that predicates refer to can be called anything.
+        assert!(Rules::parse("((paragraph) @it (#if-long @it)) @break.after", "my.scm").is_ok());
+    }
+}
@@ -1,11 +1,20 @@
-use super::{format_item::FormatItem, node_rule::NodeRule, CaptureIndex, NodeId, PatternIndex};
+use super::{
+    format_item::{FormatItem, Origin},
+    node_rule::NodeRule,
+    CaptureIndex, NodeId, PatternIndex,
+};
 use crate::fmt::{
+    config::FormatConfig,
     constrained_value::Constrained,
     formatting::Formatting,
     node_rule::{DedentType, IndentType, NodeRules},
+    verbatim::Verbatim,
 };
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 use tree_sitter::{Node, QueryPredicateArg, StreamingIterator, TreeCursor};
 use tree_sitter::{Query, QueryCursor};
 
@@ -16,6 +25,7 @@ struct CapIndex {
     dedent: Option<CaptureIndex>,
     dedent_this: Option<CaptureIndex>,
     take_asis: Option<CaptureIndex>,
+    align_after: Option<CaptureIndex>,
 
     spacing: HashMap<CaptureIndex, RulePositioning>,
 
@@ -30,64 +40,22 @@ enum RulePositioning {
 
 pub struct FormatScanner {
     query: Query,
-    cursor: QueryCursor,
+    /// Patterns from here on are project specific rules, see [`crate::fmt::Rules`]
+    extra_patterns: PatternIndex,
     captures: CapIndex,
+    config: FormatConfig,
 }
 
 impl FormatScanner {
-    pub fn new(query: Query) -> Self {
-        let spacing = query.capture_names().into_iter().map(|capture| {
-            let mut split = capture.splitn(3, '.');
-            let kind = split.next();
-            let position = split.next();
-            let repeats = split.next();
-
-            let item = match kind {
-                Some("space") => FormatItem::Space,
-                Some("break") => FormatItem::Line,
-                _ => return Err(capture),
-            };
-
-            let pos = match position {
-                Some("before") => RulePositioning::Before,
-                Some("after") => RulePositioning::After,
-                _ => return Err(capture),
-            };
-
-            let constraint = match repeats {
-                Some(n) => {
-                    let mut split = n.splitn(2, "-");
-                    let (a, b) = match (split.next(), split.next()) {
-                        (Some(a), Some(b)) => match (a.parse::<u8>(), b.parse::<u8>()) {
-                            (Ok(n1), Ok(n2)) => (n1, n2),
-                            _ => return Err(capture),
-                        },
-                        (Some(a), None) => match a.parse::<u8>() {
-                            Ok(n1) => (n1, n1),
-                            _ => return Err(capture),
-                        },
-
-                        _ => return Err(capture),
-                    };
-                    Constrained::between(a, b)
-                }
-                None => Constrained::between(1, 1),
-            };
-
-            if split.next().is_some() {
-                // We want exact matches, so if there happens to be another part, then the capture doesn't match.
-                return Err(capture);
-            }
-
-            let cap_index = query
-                .capture_index_for_name(&capture)
-                .expect("we're iterating over existing strings");
-
-            Ok((cap_index, pos(item(constraint))))
-        });
-
-        // explicit filter step so it's easy to debug-print if something is off.
-        let spacing = spacing.filter_map(Result::ok).collect();
+    pub fn new(query: Query, extra_patterns: PatternIndex, config: &FormatConfig) -> Self {
+        let spacing = query
+            .capture_names()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, capture)| {
+                Some((index as CaptureIndex, spacing_rule(capture, config)?))
+            })
+            .collect();
         // log::debug!("{:?}", spacing);
 
         let captures = CapIndex {
@@ -96,6 +64,7 @@ impl FormatScanner {
             dedent: query.capture_index_for_name("dedent"),
             dedent_this: query.capture_index_for_name("dedent.this"),
             take_asis: query.capture_index_for_name("take.as-is"),
+            align_after: query.capture_index_for_name("align.after"),
 
             spacing,
 
@@ -103,21 +72,35 @@ impl FormatScanner {
         };
         Self {
             query,
-            cursor: QueryCursor::new(),
+            extra_patterns,
             captures,
+            config: config.clone(),
         }
     }
 
+    pub(crate) fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Returns the byte ranges of the source that were left unformatted because of syntax errors.
     pub fn scan(
-        &mut self,
+        &self,
         tree: &tree_sitter::Tree,
         source: &str,
         formatter: &mut impl Formatting,
-    ) {
+    ) -> Vec<Range<usize>> {
         let mut rules: HashMap<NodeId, NodeRule> = HashMap::new();
 
         let mut node_actions: HashMap<(PatternIndex, CaptureIndex, &str), Box<str>> =
             HashMap::new();
+        // Patterns that only apply if some option is set (which it isn't).
+        let mut disabled_patterns: HashSet<PatternIndex> = HashSet::new();
+        // Patterns that only apply if a captured node doesn't fit on a line.
+        let mut long_patterns: HashMap<PatternIndex, CaptureIndex> = HashMap::new();
+        // Nodes that consecutive lines should be aligned after.
+        let mut aligned: Vec<(PatternIndex, Node)> = Vec::new();
+        // Spacing from project specific rules, which replaces the built-in spacing.
+        let mut overrides: HashMap<NodeId, NodeRule> = HashMap::new();
 
         // let capturenames: Vec<_> = self.query.capture_names().iter().enumerate().collect();
         // dbg!(capturenames);
@@ -141,6 +124,18 @@ impl FormatScanner {
                         )
                         }
                     }
+                    ("if-option", [QueryPredicateArg::String(name)]) => {
+                        match self.config.option(name) {
+                            Some(true) => {}
+                            Some(false) => {
+                                disabled_patterns.insert(pattern_index);
+                            }
+                            None => panic!("Pattern {pattern_index}: Unknown option '{name}'"),
+                        }
+                    }
+                    ("if-long", [QueryPredicateArg::Capture(index)]) => {
+                        long_patterns.insert(pattern_index, *index);
+                    }
                     (op, args) => {
                         panic!(
                             "Pattern {pattern_index}: Unknown query predicate #{op}({:?})",
@@ -151,10 +146,24 @@ impl FormatScanner {
             }
         }
 
-        let mut matches = self
-            .cursor
-            .matches(&self.query, tree.root_node(), source.as_bytes());
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(&self.query, tree.root_node(), source.as_bytes());
         while let Some(match_) = matches.next() {
+            if disabled_patterns.contains(&match_.pattern_index) {
+                continue;
+            }
+            if let Some(index) = long_patterns.get(&match_.pattern_index) {
+                let line_width = usize::from(self.config.line_width);
+                let fits = match_
+                    .captures
+                    .iter()
+                    .filter(|it| it.index == *index)
+                    .all(|it| width_on_line(it.node, source) <= line_width);
+                if fits {
+                    continue;
+                }
+            }
+            let overriding = match_.pattern_index >= self.extra_patterns;
             for cap in match_.captures {
                 let rule = rules.entry(cap.node.id()).or_default();
                 // Deleting completely clobers all other intentions related to that node.
@@ -163,9 +172,19 @@ impl FormatScanner {
                     continue;
                 }
 
+                let origin = Origin::Capture(match_.pattern_index, cap.index);
+                let spacing = if overriding {
+                    overrides.entry(cap.node.id()).or_default()
+                } else {
+                    &mut *rule
+                };
                 match self.captures.spacing.get(&cap.index) {
-                    Some(RulePositioning::Before(item)) => rule.before.push(item.clone()),
-                    Some(RulePositioning::After(item)) => rule.after.push(item.clone()),
+                    Some(RulePositioning::Before(item)) => {
+                        spacing.before.push((item.clone(), origin))
+                    }
+                    Some(RulePositioning::After(item)) => {
+                        spacing.after.push((item.clone(), origin))
+                    }
                     None => {
                         let cap_index = Some(cap.index);
                         if cap_index == self.captures.delete {
@@ -182,6 +201,8 @@ impl FormatScanner {
                             rule.dedent = DedentType::DedentFollowing;
                         } else if cap_index == self.captures.take_asis {
                             rule.take_asis = true;
+                        } else if cap_index == self.captures.align_after {
+                            aligned.push((match_.pattern_index, cap.node));
                         }
                     }
                 }
@@ -189,7 +210,7 @@ impl FormatScanner {
                 if let Some(action) =
                     node_actions.get(&(match_.pattern_index, cap.index, "replace"))
                 {
-                    if let Some(ref existing) = rule.replace {
+                    if let Some(existing) = rule.replace.as_ref().filter(|_| !overriding) {
                         panic!(
                             "Conflicting directives for replacement of {:?}: {:?} vs {:?}",
                             cap, existing, action
@@ -202,30 +223,161 @@ impl FormatScanner {
                     node_actions.get(&(match_.pattern_index, cap.index, "prepend"))
                 {
                     rule.before
-                        .push(FormatItem::Text(action.clone().into_string()))
+                        .push((FormatItem::Text(action.clone().into_string()), origin))
                 }
                 if let Some(action) = node_actions.get(&(match_.pattern_index, cap.index, "append"))
                 {
                     rule.after
-                        .push(FormatItem::Text(action.clone().into_string()))
+                        .push((FormatItem::Text(action.clone().into_string()), origin))
                 }
             }
         }
+        for (id, spacing) in overrides {
+            rules.entry(id).or_default().override_spacing(spacing);
+        }
         // doc::debug!("{:#?}", rules);
+        align(&mut rules, aligned, source);
 
+        let mut verbatim = Verbatim::new(tree, source);
         let mut iter = tree.walk();
-        collect_outputs(formatter, &mut rules, iter.node(), &mut iter, source);
+        collect_outputs(
+            formatter,
+            &mut rules,
+            &mut verbatim,
+            iter.node(),
+            &mut iter,
+            source,
+        );
+        verbatim.errors().to_vec()
+    }
+}
+
+/// The captures that aren't spacing rules (see [`spacing_rule`]).
+const CAPTURES: &[&str] = &[
+    "indent.anchor",
+    "indent",
+    "dedent",
+    "dedent.this",
+    "take.as-is",
+    "align.after",
+    "delete",
+];
+
+/// Whether `capture` means something to the scanner. Other captures only make sense as
+/// arguments of predicates.
+pub(crate) fn is_known_capture(capture: &str) -> bool {
+    CAPTURES.contains(&capture) || spacing_rule(capture, &FormatConfig::default()).is_some()
+}
+
+/// The spacing that a capture like `@space.before`, `@break.after.1-2` or
+/// `@break.before.knot` stands for.
+fn spacing_rule(capture: &str, config: &FormatConfig) -> Option<RulePositioning> {
+    let mut split = capture.splitn(3, '.');
+    let kind = split.next();
+    let position = split.next();
+    let repeats = split.next();
+
+    let item = match kind {
+        Some("space") => FormatItem::Space,
+        Some("break") => FormatItem::Line,
+        _ => return None,
+    };
+
+    let pos = match position {
+        Some("before") => RulePositioning::Before,
+        Some("after") => RulePositioning::After,
+        _ => return None,
+    };
+
+    let constraint = match repeats {
+        Some(name) if name.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+            configured_repeats(name, config)?
+        }
+        Some(n) => {
+            // We want exact matches, so `1-2-3` or `1.2` don't count.
+            let (a, b) = match n.split_once('-') {
+                Some((a, b)) => (a.parse::<u8>().ok()?, b.parse::<u8>().ok()?),
+                None => {
+                    let n = n.parse::<u8>().ok()?;
+                    (n, n)
+                }
+            };
+            Constrained::between(a, b)
+        }
+        None => Constrained::between(1, 1),
+    };
+
+    Some(pos(item(constraint)))
+}
+
+/// Repeats that depend on the config, such as `@break.before.knot`.
+fn configured_repeats(name: &str, config: &FormatConfig) -> Option<Constrained> {
+    let exactly = |n: u8| Constrained::between(n, n);
+    Some(match name {
+        "marks" => exactly(config.gap_after_marks),
+        // n blank lines means n+1 line breaks
+        "knot" => exactly(config.blank_lines_before_knot.saturating_add(1)),
+        "stitch" => exactly(config.blank_lines_before_stitch.saturating_add(1)),
+        "paragraph" => Constrained::between(0, config.max_blank_lines.saturating_add(1)),
+        _ => return None,
+    })
+}
+
+/// `@align.after`: Pad the captured nodes of consecutive lines, so that whatever comes after
+/// them lines up. Widths are measured from the start of the parent node, assuming that its
+/// parts are separated by single spaces.
+fn align(rules: &mut NodeRules, mut aligned: Vec<(PatternIndex, Node)>, source: &str) {
+    aligned.sort_by_key(|(pattern, node)| (*pattern, node.start_byte()));
+    aligned.dedup_by_key(|(_, node)| node.id());
+    let runs = aligned.chunk_by(|(pattern_a, a), (pattern_b, b)| {
+        pattern_a == pattern_b && b.start_position().row == a.start_position().row + 1
+    });
+    for run in runs.filter(|it| it.len() > 1) {
+        let widths = run
+            .iter()
+            .map(|(_, node)| {
+                let start = node.parent().unwrap_or(*node).start_byte();
+                source[start..node.end_byte()]
+                    .split_whitespace()
+                    .join(" ")
+                    .chars()
+                    .count()
+            })
+            .collect_vec();
+        let widest = widths.iter().copied().max().unwrap_or_default();
+        for ((pattern, node), width) in run.iter().zip(widths) {
+            let gap = u8::try_from(widest - width + 1).unwrap_or(u8::MAX);
+            rules.entry(node.id()).or_default().after.push((
+                FormatItem::Space(Constrained::between(gap, gap)),
+                Origin::Alignment(*pattern),
+            ));
+        }
     }
 }
 
+/// How wide the line up to the end of `node` is, with whitespace collapsed to single spaces.
+fn width_on_line(node: Node, source: &str) -> usize {
+    let line_start = source[..node.start_byte()]
+        .rfind('\n')
+        .map_or(0, |it| it + 1);
+    source[line_start..node.end_byte()]
+        .split_whitespace()
+        .join(" ")
+        .chars()
+        .count()
+}
+
 /// If `text` is all non-newline whitespace, keep that number of spaces. If it has
 /// any newlines, output that many newlines without any spaces. If it contains,
 /// non-whitespace, output the text verbatim.
-fn collect_whitespace(outs: &mut impl Formatting, text: &str) {
+fn collect_whitespace(outs: &mut impl Formatting, kind: &'static str, text: &str) {
     let newlines = text
         .chars()
         .map(|c| c.is_whitespace().then_some(c))
         .fold_options(0usize, |sum, c| if c == '\n' { sum + 1 } else { sum });
+    if newlines.is_some() {
+        outs.explain(kind, Origin::Source);
+    }
     match newlines {
         Some(0) => outs.space(text.len()),
         Some(n) => outs.line(n),
@@ -243,6 +395,19 @@ fn item_to_inkfmt(outs: &mut impl Formatting, item: FormatItem) {
     }
 }
 
+/// Like [`collect_whitespace`], but leaves out whatever is part of a verbatim region.
+fn collect_whitespace_outside(
+    outs: &mut impl Formatting,
+    verbatim: &Verbatim,
+    kind: &'static str,
+    source: &str,
+    range: Range<usize>,
+) {
+    for part in verbatim.outside(range) {
+        collect_whitespace(outs, kind, &source[part]);
+    }
+}
+
 /// Applies the appropriate rule from `rules` (if any) to the current node.
 /// If no rule applies, simply copies the input to the output for leaf nodes,
 /// including leading and trailing whitspace.
@@ -253,6 +418,7 @@ fn item_to_inkfmt(outs: &mut impl Formatting, item: FormatItem) {
 fn collect_outputs<'t>(
     outs: &mut impl Formatting,
     rules: &mut NodeRules,
+    verbatim: &mut Verbatim,
     node: Node<'t>,
     iter: &mut TreeCursor<'t>,
     source: &str,
@@ -264,16 +430,81 @@ fn collect_outputs<'t>(
     // TODO: We double up existing whitespace by adding it before and after. It probably makes sense to not do that.
     // (unlike spaces added by rules, there's not much debugging value in duplicate existing whitespace).
     if let Some(prev) = node.prev_sibling() {
-        collect_whitespace(outs, &source[prev.end_byte()..node.start_byte()]);
+        collect_whitespace_outside(
+            outs,
+            verbatim,
+            node.kind(),
+            source,
+            prev.end_byte()..node.start_byte(),
+        );
     } else if let Some(parent) = node.parent() {
-        collect_whitespace(outs, &source[parent.start_byte()..node.start_byte()]);
+        collect_whitespace_outside(
+            outs,
+            verbatim,
+            node.kind(),
+            source,
+            parent.start_byte()..node.start_byte(),
+        );
+    }
+
+    if let Some(region) = verbatim.region_of(node, source) {
+        let range = verbatim.range(region);
+        if verbatim.take(region) {
+            // Regions start and end with a line
+            if range.start > 0 {
+                outs.explain(node.kind(), Origin::Verbatim);
+                outs.line(Constrained::at_least(1));
+            }
+            outs.verbatim(&source[range.clone()]);
+            if source[range.end..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with(['\r', '\n'])
+            {
+                outs.explain(node.kind(), Origin::Verbatim);
+                outs.line(Constrained::at_least(1));
+            }
+        }
+        keep_indentation(outs, rules, rule, node, iter);
+        // Trailing whitespace isn't part of the region
+        collect_whitespace_outside(outs, verbatim, node.kind(), source, node.byte_range());
+    } else {
+        apply_rule(outs, rules, verbatim, rule, node, iter, source);
     }
 
+    if let Some(next) = node.next_sibling() {
+        collect_whitespace_outside(
+            outs,
+            verbatim,
+            node.kind(),
+            source,
+            node.end_byte()..next.start_byte(),
+        );
+    } else if let Some(parent) = node.parent() {
+        collect_whitespace_outside(
+            outs,
+            verbatim,
+            node.kind(),
+            source,
+            node.end_byte()..parent.end_byte(),
+        );
+    }
+}
+
+fn apply_rule<'t>(
+    outs: &mut impl Formatting,
+    rules: &mut NodeRules,
+    verbatim: &mut Verbatim,
+    rule: NodeRule,
+    node: Node<'t>,
+    iter: &mut TreeCursor<'t>,
+    source: &str,
+) {
     if matches!(rule.dedent, DedentType::DedentThis) {
         outs.dedent();
     }
 
-    for output in rule.before {
+    for (output, origin) in rule.before {
+        outs.explain(node.kind(), origin);
         item_to_inkfmt(outs, output);
     }
 
@@ -290,21 +521,47 @@ fn collect_outputs<'t>(
     } else {
         let children: Vec<_> = node.children(iter).collect();
         for child in children {
-            collect_outputs(outs, rules, child, iter, source);
+            collect_outputs(outs, rules, verbatim, child, iter, source);
         }
     }
 
-    for output in rule.after {
+    for (output, origin) in rule.after {
+        outs.explain(node.kind(), origin);
         item_to_inkfmt(outs, output);
     }
 
     if matches!(rule.dedent, DedentType::DedentFollowing) {
         outs.dedent();
     }
+}
 
-    if let Some(next) = node.next_sibling() {
-        collect_whitespace(outs, &source[node.end_byte()..next.start_byte()]);
-    } else if let Some(parent) = node.parent() {
-        collect_whitespace(outs, &source[node.end_byte()..parent.end_byte()]);
+/// Nodes in verbatim regions don't output anything, but their indentation still has to add up
+/// with that of the nodes around them. There's no column to align to, so anchors simply indent.
+fn keep_indentation<'t>(
+    outs: &mut impl Formatting,
+    rules: &mut NodeRules,
+    rule: NodeRule,
+    node: Node<'t>,
+    iter: &mut TreeCursor<'t>,
+) {
+    if matches!(rule.dedent, DedentType::DedentThis) {
+        outs.dedent();
+    }
+
+    match rule.indent {
+        IndentType::Indent | IndentType::Anchor => outs.indent(),
+        IndentType::None => (),
+    }
+
+    if rule.replace.is_none() && !rule.take_asis {
+        let children: Vec<_> = node.children(iter).collect();
+        for child in children {
+            let rule = rules.remove(&child.id()).unwrap_or_default();
+            keep_indentation(outs, rules, rule, child, iter);
+        }
+    }
+
+    if matches!(rule.dedent, DedentType::DedentFollowing) {
+        outs.dedent();
     }
 }
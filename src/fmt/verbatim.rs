@@ -0,0 +1,355 @@
+use std::ops::Range;
+
+use tree_sitter::{Node, Tree};
+
+/// Parts of the source that are copied to the output unchanged, because comments ask for it:
+///
+/// ``` ink
+/// // ink-fmt: off
+/// VAR a   = 1
+/// VAR bcd = 2
+/// // ink-fmt: on
+///
+/// // ink-fmt: skip
+/// *   {a}   | one
+/// *   {bcd} | two
+/// ```
+///
+/// `off` lasts until the next `on` (or the end of the file), `skip` applies to the node after it.
+///
+/// Blocks with syntax errors are kept as they are too, because we can't know what they
+/// are supposed to look like.
+///
+/// Regions always start at the beginning of a line, so they keep their indentation.
+#[derive(Debug, Default)]
+pub(crate) struct Verbatim {
+    /// Byte ranges, in order, without trailing whitespace
+    regions: Vec<Range<usize>>,
+    /// The regions (or parts of them) that are kept because of syntax errors
+    errors: Vec<Range<usize>>,
+    /// How many regions have been output so far
+    written: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Directive {
+    Off,
+    On,
+    Skip,
+}
+
+impl Verbatim {
+    pub(crate) fn new(tree: &Tree, source: &str) -> Self {
+        let mut regions: Vec<Range<usize>> = Vec::new();
+        let mut off_since = None;
+        for comment in line_comments(tree) {
+            if regions
+                .last()
+                .is_some_and(|it| comment.start_byte() < it.end)
+            {
+                // Part of a skipped node
+                continue;
+            }
+            let Some(directive) = parse_directive(&source[comment.byte_range()]) else {
+                continue;
+            };
+            match (directive, off_since) {
+                (Directive::Off, None) => {
+                    off_since = Some(line_start(source, next_content(source, comment.end_byte())));
+                }
+                (Directive::On, Some(start)) => {
+                    let end = source[..line_start(source, comment.start_byte())]
+                        .trim_end()
+                        .len();
+                    regions.push(start..end);
+                    off_since = None;
+                }
+                (Directive::Skip, None) => {
+                    if let Some(node) = node_after(tree, source, comment.end_byte()) {
+                        let start = node.start_byte();
+                        let end = start + source[node.byte_range()].trim_end().len();
+                        regions.push(line_start(source, start)..end);
+                    }
+                }
+                // Already off, or not off in the first place
+                _ => {}
+            }
+        }
+        if let Some(start) = off_since {
+            regions.push(start..source.trim_end().len());
+        }
+
+        let errors = merge(error_regions(tree, source));
+        regions.extend(errors.iter().cloned());
+        Self {
+            regions: merge(regions),
+            errors,
+            written: 0,
+        }
+    }
+
+    pub(crate) fn errors(&self) -> &[Range<usize>] {
+        &self.errors
+    }
+
+    /// The index of the region that contains all of `node` (except trailing whitespace).
+    pub(crate) fn region_of(&self, node: Node, source: &str) -> Option<usize> {
+        let start = node.start_byte();
+        let end = start + source[node.byte_range()].trim_end().len();
+        self.regions
+            .iter()
+            .position(|it| it.start <= start && start < it.end && end <= it.end)
+    }
+
+    pub(crate) fn range(&self, region: usize) -> Range<usize> {
+        self.regions[region].clone()
+    }
+
+    /// Whether `region` still has to be output. Only returns `true` once per region.
+    pub(crate) fn take(&mut self, region: usize) -> bool {
+        let first_time = region >= self.written;
+        self.written = self.written.max(region + 1);
+        first_time
+    }
+
+    /// The parts of `range` that are not in any region. An untouched range is returned as is,
+    /// even if it's empty.
+    pub(crate) fn outside(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let overlapping = self
+            .regions
+            .iter()
+            .filter(|it| it.start < range.end && range.start < it.end);
+        let mut result = Vec::new();
+        let mut start = range.start;
+        let mut clipped = false;
+        for region in overlapping {
+            if start < region.start {
+                result.push(start..region.start);
+            }
+            start = start.max(region.end);
+            clipped = true;
+        }
+        if !clipped || start < range.end {
+            result.push(start..range.end);
+        }
+        result
+    }
+}
+
+/// Sorts `regions` and combines the ones that overlap. Drops empty ones.
+fn merge(mut regions: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    regions.retain(|it| !it.is_empty());
+    regions.sort_by_key(|it| it.start);
+    let mut result: Vec<Range<usize>> = Vec::with_capacity(regions.len());
+    for region in regions {
+        match result.last_mut() {
+            Some(last) if region.start <= last.end => last.end = last.end.max(region.end),
+            _ => result.push(region),
+        }
+    }
+    result
+}
+
+/// The lines of the smallest block around each `ERROR` or `MISSING` node.
+fn error_regions(tree: &Tree, source: &str) -> Vec<Range<usize>> {
+    let mut result = Vec::new();
+    if !tree.root_node().has_error() {
+        return result;
+    }
+    let mut cursor = tree.walk();
+    'nodes: loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            let block = enclosing_block(node);
+            let start = line_start(source, block.start_byte());
+            let end = line_end(source, block.end_byte());
+            result.push(start..start + source[start..end].trim_end().len());
+        } else if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                continue 'nodes;
+            }
+            if !cursor.goto_parent() {
+                break 'nodes;
+            }
+        }
+    }
+    result
+}
+
+/// The closest `…_block` around `node`, or the top level element that contains it.
+fn enclosing_block(node: Node) -> Node {
+    let mut block = node;
+    while let Some(parent) = block.parent() {
+        if parent.parent().is_none() {
+            break;
+        }
+        block = parent;
+        if block.kind().ends_with("_block") {
+            break;
+        }
+    }
+    block
+}
+
+/// `// ink-fmt: off` → `Some(Directive::Off)`
+fn parse_directive(comment: &str) -> Option<Directive> {
+    let directive = comment
+        .strip_prefix("//")?
+        .trim()
+        .strip_prefix("ink-fmt:")?
+        .trim();
+    match directive {
+        "off" => Some(Directive::Off),
+        "on" => Some(Directive::On),
+        "skip" => Some(Directive::Skip),
+        _ => {
+            log::warn!("Unknown formatter directive '{directive}'");
+            None
+        }
+    }
+}
+
+/// All `// …` comments, in the order they appear in.
+fn line_comments(tree: &Tree) -> Vec<Node<'_>> {
+    let mut result = Vec::new();
+    let mut cursor = tree.walk();
+    'nodes: loop {
+        let node = cursor.node();
+        if node.kind() == "line_comment" {
+            result.push(node);
+        }
+        if cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                continue 'nodes;
+            }
+            if !cursor.goto_parent() {
+                break 'nodes;
+            }
+        }
+    }
+    result
+}
+
+/// The biggest node that starts with the first content after `pos`, not counting other comments.
+fn node_after<'t>(tree: &'t Tree, source: &str, mut pos: usize) -> Option<Node<'t>> {
+    let root = tree.root_node();
+    loop {
+        let start = next_content(source, pos);
+        let mut node = root.descendant_for_byte_range(start, start)?;
+        while let Some(parent) = node
+            .parent()
+            .filter(|it| it.start_byte() == start && *it != root)
+        {
+            node = parent;
+        }
+        if node == root || node.start_byte() != start {
+            return None;
+        }
+        if node.kind() != "line_comment" {
+            return Some(node);
+        }
+        pos = node.end_byte();
+    }
+}
+
+/// The position of the first non-whitespace at or after `pos`.
+fn next_content(source: &str, pos: usize) -> usize {
+    let rest = &source[pos..];
+    pos + (rest.len() - rest.trim_start().len())
+}
+
+pub(crate) fn line_start(source: &str, pos: usize) -> usize {
+    source[..pos].rfind('\n').map_or(0, |it| it + 1)
+}
+
+/// The position of the line break at or after `pos` (or the end of `source`).
+pub(crate) fn line_end(source: &str, pos: usize) -> usize {
+    source[pos..].find('\n').map_or(source.len(), |it| pos + it)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, parse_directive, Directive};
+    use crate::fmt::{format, format_checked, FormatConfig};
+    use indoc::indoc;
+
+    #[test]
+    fn directives() {
+        assert_eq!(parse_directive("// ink-fmt: off"), Some(Directive::Off));
+        assert_eq!(parse_directive("//ink-fmt:on"), Some(Directive::On));
+        assert_eq!(parse_directive("// ink-fmt: skip "), Some(Directive::Skip));
+        assert_eq!(parse_directive("// ink-fmt: of"), None);
+        assert_eq!(parse_directive("// off"), None);
+    }
+
+    #[test]
+    fn merging() {
+        assert_eq!(
+            merge(vec![5..9, 0..3, 2..4, 7..7, 9..12]),
+            vec![0..4, 5..12]
+        );
+    }
+
+    #[test]
+    fn off_and_on() {
+        let output = format(
+            indoc! {"
+                VAR a=1
+                // ink-fmt: off
+                VAR b   =   2
+                VAR cc  =   3
+                // ink-fmt: on
+                VAR d=4
+            "}
+            .to_string(),
+        );
+        assert!(output.contains("VAR a = 1\n"), "{output}");
+        assert!(
+            output.contains("VAR b   =   2\nVAR cc  =   3\n"),
+            "{output}"
+        );
+        assert!(output.ends_with("VAR d = 4"), "{output}");
+    }
+
+    #[test]
+    fn off_until_the_end() {
+        let output = format("VAR a=1\n// ink-fmt: off\n*a\n  * *b\n".to_string());
+        assert!(output.contains("VAR a = 1\n"), "{output}");
+        assert!(output.ends_with("*a\n  * *b"), "{output}");
+    }
+
+    #[test]
+    fn syntax_errors_are_kept() {
+        let source = "* choice {a\n  *  nested\n";
+        let formatted = format_checked(source.to_string(), &FormatConfig::default());
+        assert!(
+            formatted.text.contains("* choice {a\n"),
+            "{}",
+            formatted.text
+        );
+        assert!(!formatted.unformatted.is_empty());
+        assert!(formatted.unformatted[0].start == 0);
+    }
+
+    #[test]
+    fn skip() {
+        let output = format(
+            indoc! {"
+                VAR a=1
+                // ink-fmt: skip
+                VAR  b=2
+                VAR c=3
+            "}
+            .to_string(),
+        );
+        assert!(output.contains("VAR a = 1\n"), "{output}");
+        assert!(output.contains("\nVAR  b=2\n"), "{output}");
+        assert!(output.ends_with("VAR c = 3"), "{output}");
+    }
+}
@@ -0,0 +1,243 @@
+//! Checking that formatting is stable and doesn't change what a story means.
+
+use derive_more::Display;
+use itertools::{EitherOrBoth, Itertools};
+use tree_sitter::Tree;
+
+use super::{line_number, parse, reflow, Formatted, Formatter, Reflow};
+
+/// Fields whose text formatting is allowed to change, or add and remove (`=== knot ===`).
+const NORMALIZED_FIELDS: &[&str] = &["start_mark", "end_mark"];
+
+/// Something that formatting got wrong.
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum Problem {
+    /// Formatting the formatted text changes it again, first at `line` (of the formatted text).
+    #[display("formatting again changes line {line}")]
+    NotIdempotent { line: usize },
+    /// The formatted text doesn't parse to the same tree as the original.
+    #[display(
+        "formatting changes the structure: line {line} has {expected}, but the formatted line {formatted_line} has {found}"
+    )]
+    ChangedStructure {
+        line: usize,
+        formatted_line: usize,
+        expected: String,
+        found: String,
+    },
+}
+
+impl Formatter {
+    /// Format `source` and check that the result
+    ///
+    /// - stays the same when it is formatted again, and
+    /// - has the same syntax tree as `source`, apart from whitespace, anonymous tokens (like
+    ///   parentheses, which can move) and knot marks.
+    pub fn verify(&self, source: &str) -> Result<Formatted, Problem> {
+        let once = self.format(source.to_string());
+        let twice = self.format(once.text.clone());
+        if let Some(line) = first_different_line(&once.text, &twice.text) {
+            return Err(Problem::NotIdempotent { line });
+        }
+
+        // Wrapping adds glue on purpose, so compare the unwrapped texts.
+        let (original, formatted) = match self.config.reflow {
+            Reflow::Keep => (source.to_string(), once.text.clone()),
+            Reflow::Wrap | Reflow::Unwrap => {
+                (reflow::join_all(source), reflow::join_all(&once.text))
+            }
+        };
+        let original_tree = parse(&original);
+        let formatted_tree = parse(&formatted);
+        let pairs = outline(&original_tree, &original)
+            .into_iter()
+            .zip_longest(outline(&formatted_tree, &formatted));
+        for pair in pairs {
+            let (expected, found) = match pair {
+                EitherOrBoth::Both(a, b) if a.same_as(&b) => continue,
+                EitherOrBoth::Both(a, b) => (Some(a), Some(b)),
+                EitherOrBoth::Left(a) => (Some(a), None),
+                EitherOrBoth::Right(b) => (None, Some(b)),
+            };
+            let line_of = |part: &Option<Part>, text: &str| {
+                line_number(text, part.as_ref().map_or(text.len(), |it| it.start))
+            };
+            return Err(Problem::ChangedStructure {
+                line: line_of(&expected, &original),
+                formatted_line: line_of(&found, &formatted),
+                expected: describe(expected),
+                found: describe(found),
+            });
+        }
+        Ok(once)
+    }
+}
+
+/// A named node, where it is in the tree, and its text if it is a leaf.
+#[derive(Debug)]
+struct Part {
+    depth: u32,
+    kind: &'static str,
+    text: Option<String>,
+    start: usize,
+}
+
+impl Part {
+    fn same_as(&self, other: &Part) -> bool {
+        self.depth == other.depth && self.kind == other.kind && self.text == other.text
+    }
+}
+
+fn describe(part: Option<Part>) -> String {
+    match part {
+        Some(Part {
+            kind,
+            text: Some(text),
+            ..
+        }) => format!("{kind} {text:?}"),
+        Some(Part { kind, .. }) => kind.to_string(),
+        None => "nothing".to_string(),
+    }
+}
+
+/// The named nodes of `tree` in order, except for whitespace-only ones and the ones in
+/// [`NORMALIZED_FIELDS`]. The text of leaves has its whitespace collapsed.
+fn outline(tree: &Tree, source: &str) -> Vec<Part> {
+    let mut result = Vec::new();
+    let mut cursor = tree.walk();
+    'nodes: loop {
+        let node = cursor.node();
+        let normalized = cursor
+            .field_name()
+            .is_some_and(|it| NORMALIZED_FIELDS.contains(&it));
+        let text = source[node.byte_range()].split_whitespace().join(" ");
+        if node.is_named() && !normalized && !text.is_empty() {
+            result.push(Part {
+                depth: cursor.depth(),
+                kind: node.kind(),
+                text: (node.child_count() == 0).then_some(text),
+                start: node.start_byte(),
+            });
+        }
+        if !normalized && cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                continue 'nodes;
+            }
+            if !cursor.goto_parent() {
+                break 'nodes;
+            }
+        }
+    }
+    result
+}
+
+/// The 1-based number of the first line that differs between `a` and `b`.
+fn first_different_line(a: &str, b: &str) -> Option<usize> {
+    if a == b {
+        return None;
+    }
+    let same = a
+        .split_inclusive('\n')
+        .zip(b.split_inclusive('\n'))
+        .take_while(|(a, b)| a == b)
+        .count();
+    Some(same + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{first_different_line, Problem};
+    use crate::fmt::{FormatConfig, Formatter, Rules};
+    use quickcheck::{quickcheck, Arbitrary, Gen, TestResult};
+
+    fn formatter() -> Formatter {
+        Formatter::new(&FormatConfig::default(), &Rules::default())
+    }
+
+    #[test]
+    fn formatted_examples_verify() {
+        let source = "=== knot\n*choice\n** sub choice -> knot\n-   (label)gathered\n~x=1\n";
+        assert!(formatter().verify(source).is_ok());
+    }
+
+    #[test]
+    fn differences_are_found() {
+        assert_eq!(first_different_line("a\nb\nc", "a\nb\nc"), None);
+        assert_eq!(first_different_line("a\nb\nc", "a\nx\nc"), Some(2));
+        assert_eq!(first_different_line("a\nb", "a\nb\n"), Some(2));
+    }
+
+    #[test]
+    fn rules_that_change_the_structure_are_caught() {
+        // Still valid ink, but the paragraph turns into a choice.
+        let rules = Rules::parse("((paragraph) @p (#prepend @p \"* \"))", "test.scm").unwrap();
+        let formatter = Formatter::new(&FormatConfig::default(), &rules);
+        let result = formatter.verify("text\n");
+        assert!(
+            matches!(result, Err(Problem::ChangedStructure { line: 1, .. })),
+            "{result:?}"
+        );
+    }
+
+    /// Lines of ink with the usual stuff, randomly spaced and indented.
+    #[derive(Debug, Clone)]
+    struct Snippet(String);
+
+    const WORDS: &[&str] = &["the", "cat", "sat", "on", "a", "mat", "\"Hello!\""];
+
+    impl Arbitrary for Snippet {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let space = |g: &mut Gen| " ".repeat(*g.choose(&[0, 1, 1, 3]).unwrap());
+            let gap = |g: &mut Gen| " ".repeat(*g.choose(&[1, 1, 4]).unwrap());
+            let text = |g: &mut Gen| {
+                let len = usize::arbitrary(g) % 4 + 1;
+                (0..len)
+                    .map(|_| *g.choose(WORDS).unwrap())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            let lines = usize::arbitrary(g) % 12;
+            let mut result = String::new();
+            for _ in 0..lines {
+                let indent = space(g);
+                let line = match u8::arbitrary(g) % 9 {
+                    0 => format!("==={}knot{}", gap(g), space(g)),
+                    1 => format!("={}stitch", gap(g)),
+                    2 | 3 => text(g),
+                    4 => {
+                        let depth = usize::arbitrary(g) % 3 + 1;
+                        let marks = vec!["*"; depth].join(&space(g));
+                        format!("{marks}{}{}", space(g), text(g))
+                    }
+                    5 => format!("-{}{}", gap(g), text(g)),
+                    6 => format!("->{}knot", gap(g)),
+                    7 => format!("VAR{}x{}={}1", gap(g), space(g), space(g)),
+                    _ => format!(
+                        "~{}x{}={}x{}+{}1",
+                        space(g),
+                        space(g),
+                        space(g),
+                        space(g),
+                        space(g)
+                    ),
+                };
+                result.push_str(&indent);
+                result.push_str(&line);
+                result.push_str(g.choose(&["\n", "\n", "\n\n"]).unwrap());
+            }
+            Snippet(result)
+        }
+    }
+
+    quickcheck! {
+        fn formatting_verifies(snippet: Snippet) -> TestResult {
+            match formatter().verify(&snippet.0) {
+                Ok(_) => TestResult::passed(),
+                Err(problem) => TestResult::error(format!("{problem}\n{}", snippet.0)),
+            }
+        }
+    }
+}
@@ -0,0 +1,742 @@
+//! Localisation: pulling the text the player sees out of a story, and putting translations
+//! back in.
+//!
+//! Every line of text (a paragraph or a choice) is identified by a tag at its end
+//! (`#id:intro.3fa2b1`). [`Extractor`] adds one to every line that doesn't have one yet.
+//! From then on the ID stays with the line, whether the line is edited, moved or
+//! formatted. Lines that were wrapped with glue (`<>`) count as one, with the ID at the
+//! end of the last part.
+//!
+//! String literals (`~ name = "Bob"`) can't have tags, so their ID is the knot (and
+//! stitch) they are in plus a hash of their text. Editing one makes it a new string.
+//! Strings that something is compared with (`{mood == "happy"}`) aren't for the player
+//! and are left out.
+//!
+//! Configured in the `[l10n]` section of `ink-tool.toml`:
+//!
+//! ``` toml
+//! [l10n]
+//! id-tag = "loc"          # if the project already uses `#id:` for something else
+//! source-language = "de"  # for XLIFF files
+//! ```
+
+mod formats;
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use itertools::Itertools as _;
+use serde::Deserialize;
+use tree_sitter::Node;
+
+use crate::{
+    fmt::{line_number, parse},
+    lsp::tag_schema::{tag_text, TagSchema},
+};
+
+pub use formats::FileFormat;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct L10nConfig {
+    /// The tag that holds the ID of a line, without `#` and `:`
+    pub id_tag: String,
+    /// The language the story is written in
+    pub source_language: String,
+}
+
+impl Default for L10nConfig {
+    fn default() -> Self {
+        Self {
+            id_tag: "id".to_string(),
+            source_language: "en".to_string(),
+        }
+    }
+}
+
+impl L10nConfig {
+    /// The ID tags, as a pattern for the [`TagSchema`].
+    pub fn id_pattern(&self) -> String {
+        format!("{}:<id>", self.id_tag)
+    }
+
+    /// Make sure ID tags can't be mistaken for tags that mean something else in the project.
+    pub fn check(&self, schema: &TagSchema) -> Result<(), String> {
+        if self.id_tag.is_empty() || !self.id_tag.chars().all(is_id_char) {
+            return Err(format!("`{}` can't be used as the ID tag", self.id_tag));
+        }
+        for pattern in &schema.patterns {
+            let segments = pattern.as_str().split(':').map(str::trim).collect_vec();
+            let is_id = matches!(
+                segments.as_slice(),
+                [tag, value] if *tag == self.id_tag && value.starts_with('<')
+            );
+            if segments[0] == self.id_tag && !is_id {
+                return Err(format!(
+                    "The tag schema already has `#{}`, so `#{}:` can't hold IDs. \
+                     Set `[l10n] id-tag` to another tag.",
+                    pattern.as_str(),
+                    self.id_tag
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A piece of text for translators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub id: String,
+    pub kind: EntryKind,
+    /// The text to translate, with any `{…}` and `[…]` in it as written
+    pub source: String,
+    /// The knot (or `knot.stitch`) it is in, empty at the top of a file
+    pub context: String,
+    pub file: String,
+    /// 1-based
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Line,
+    Choice,
+    String,
+}
+
+impl EntryKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::Line => "line",
+            EntryKind::Choice => "choice",
+            EntryKind::String => "string",
+        }
+    }
+}
+
+/// The result of [`Extractor::extract`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extracted {
+    pub entries: Vec<Entry>,
+    /// The source, with an ID for every line
+    pub text: String,
+    /// How many lines got a new ID
+    pub added: usize,
+}
+
+/// Extracts the text of all files of a project, with IDs that are unique in the project.
+#[derive(Debug, Clone)]
+pub struct Extractor {
+    config: L10nConfig,
+    /// Every ID in the project, so new ones don't clash with any of them
+    known: HashSet<String>,
+    /// The IDs that belong to a line we've already extracted
+    claimed: HashSet<String>,
+}
+
+impl Extractor {
+    pub fn new(config: &L10nConfig) -> Self {
+        Self {
+            config: config.clone(),
+            known: HashSet::new(),
+            claimed: HashSet::new(),
+        }
+    }
+
+    /// Take note of the IDs in `source`. Do this for every file before extracting any of them.
+    pub fn scan(&mut self, source: &str) -> Result<(), String> {
+        let units = units(source, &self.config.id_tag)?;
+        self.known
+            .extend(units.into_iter().filter_map(|it| Some(it.id?.0)));
+        Ok(())
+    }
+
+    /// The entries of `source`, and `source` with an ID tag for every line. A line whose ID
+    /// was already used by another line (because it was copied, say) gets a new one.
+    pub fn extract(&mut self, file: &str, source: &str) -> Result<Extracted, String> {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut edits = Vec::new();
+        for unit in units(source, &self.config.id_tag)? {
+            let text = unit.text(source);
+            let id = match (unit.kind, unit.id) {
+                (EntryKind::String, _) => string_id(&unit.context, &text),
+                (_, Some((id, _))) if self.claimed.insert(id.clone()) => id,
+                (_, existing) => {
+                    let id = self.new_id(&unit.context, &text);
+                    match existing {
+                        Some((_, range)) => edits.push((range, id.clone())),
+                        None => edits
+                            .push((unit.end..unit.end, format!(" #{}:{id}", self.config.id_tag))),
+                    }
+                    id
+                }
+            };
+            // The same string in the same place only needs translating once.
+            if entries.iter().any(|it| it.id == id) {
+                continue;
+            }
+            entries.push(Entry {
+                id,
+                kind: unit.kind,
+                source: text,
+                context: unit.context,
+                file: file.to_string(),
+                line: line_number(source, unit.parts[0].start),
+            });
+        }
+        Ok(Extracted {
+            entries,
+            added: edits.len(),
+            text: apply(source, edits),
+        })
+    }
+
+    fn new_id(&mut self, context: &str, text: &str) -> String {
+        let hash = format!("{:06x}", hash(text) & 0xff_ffff);
+        let base = if context.is_empty() {
+            hash
+        } else {
+            format!("{context}.{hash}")
+        };
+        let id = (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                n => format!("{base}-{n}"),
+            })
+            .find(|it| !self.known.contains(it))
+            .expect("there are infinitely many");
+        self.known.insert(id.clone());
+        self.claimed.insert(id.clone());
+        id
+    }
+}
+
+/// The result of [`translate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translated {
+    pub text: String,
+    pub translated: usize,
+    /// Lines and strings without a translation (or without an ID), which are left as they are
+    pub untranslated: usize,
+}
+
+/// Replace every line and string of `source` that has a translation (by ID).
+///
+/// Translations are ink as written, like the source text they were made from, so `{…}`
+/// and `\` escapes in them are kept. What would turn text into something else is escaped:
+/// `#` and comments in lines, `{` and `}` in lines that had no logic to begin with, and `"`
+/// in strings. Unbalanced `{…}` are an error.
+pub fn translate(
+    source: &str,
+    config: &L10nConfig,
+    translations: &HashMap<String, String>,
+) -> Result<Translated, String> {
+    let mut edits = Vec::new();
+    let mut untranslated = 0;
+    for unit in units(source, &config.id_tag)? {
+        let id = match (unit.kind, &unit.id) {
+            (EntryKind::String, _) => Some(string_id(&unit.context, &unit.text(source))),
+            (_, id) => id.as_ref().map(|(id, _)| id.clone()),
+        };
+        let translation = id.and_then(|id| {
+            let translation = translations.get(&id).filter(|it| !it.trim().is_empty())?;
+            Some((id, translation))
+        });
+        let Some((id, translation)) = translation else {
+            untranslated += 1;
+            continue;
+        };
+        let start = unit.parts[0].start;
+        let end = unit.parts.last().expect("units have text").end;
+        // One line in, one line out.
+        let text = translation.split_whitespace().join(" ");
+        let text = escape(&text, &unit.text(source), unit.kind)
+            .map_err(|problem| format!("The translation of `{id}` {problem}"))?;
+        edits.push((start..end, text));
+    }
+    Ok(Translated {
+        translated: edits.len(),
+        untranslated,
+        text: apply(source, edits),
+    })
+}
+
+/// `translation` of `original`, escaped as described in [`translate`].
+fn escape(translation: &str, original: &str, kind: EntryKind) -> Result<String, String> {
+    let is_string = kind == EntryKind::String;
+    let has_logic = !is_string && original.contains('{');
+    let mut result = String::with_capacity(translation.len());
+    let mut depth = 0_usize;
+    let mut chars = translation.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match c {
+            // Already escaped
+            '\\' => {
+                result.push(c);
+                result.extend(chars.next());
+                continue;
+            }
+            '"' => is_string,
+            '#' => !is_string,
+            '/' => !is_string && matches!(chars.peek(), Some('/' | '*')),
+            '{' | '}' if !is_string && !has_logic => true,
+            '{' => {
+                depth += 1;
+                false
+            }
+            '}' => {
+                depth = depth.checked_sub(1).ok_or("has a `}` without a `{`")?;
+                false
+            }
+            _ => false,
+        };
+        if escaped {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    if depth > 0 {
+        return Err("has a `{` without a `}`".to_string());
+    }
+    Ok(result)
+}
+
+/// Kinds that end the text of a line.
+const TEXT_END: &[&str] = &[
+    "divert",
+    "tunnel",
+    "thread",
+    "tag",
+    "eol",
+    "line_comment",
+    "block_comment",
+    "todo_comment",
+];
+
+/// A line or string, as found in the source.
+#[derive(Debug)]
+struct Unit {
+    kind: EntryKind,
+    /// The text to translate. Lines that were wrapped with glue have several parts.
+    parts: Vec<Range<usize>>,
+    /// The ID tag's value and where it is
+    id: Option<(String, Range<usize>)>,
+    /// Where a new ID tag goes
+    end: usize,
+    context: String,
+    /// Whether the line continues on the next one
+    glued: bool,
+    parent: Option<usize>,
+}
+
+impl Unit {
+    fn text(&self, source: &str) -> String {
+        self.parts
+            .iter()
+            .map(|it| source[it.clone()].trim())
+            .join(" ")
+    }
+}
+
+/// The lines and strings of `source`, in order.
+fn units(source: &str, id_tag: &str) -> Result<Vec<Unit>, String> {
+    let tree = parse(source);
+    let mut result: Vec<Unit> = Vec::new();
+    collect(
+        tree.root_node(),
+        source,
+        id_tag,
+        &mut Vec::new(),
+        &mut result,
+    )?;
+
+    // Join lines that were wrapped, like `fmt::reflow::unwrap` does.
+    let mut joined: Vec<Unit> = Vec::new();
+    for unit in result {
+        if let Some(last) = joined.last_mut() {
+            let is_next_line = line_number(source, unit.parts[0].start)
+                == line_number(source, last.end) + 1
+                && source[..unit.parts[0].start]
+                    .rsplit('\n')
+                    .next()
+                    .is_some_and(|it| it.trim().is_empty());
+            if last.glued
+                && last.kind == EntryKind::Line
+                && unit.kind == EntryKind::Line
+                && last.parent == unit.parent
+                && is_next_line
+            {
+                last.parts.extend(unit.parts);
+                last.id = unit.id;
+                last.end = unit.end;
+                last.glued = unit.glued;
+                continue;
+            }
+        }
+        joined.push(unit);
+    }
+    Ok(joined)
+}
+
+fn collect(
+    node: Node,
+    source: &str,
+    id_tag: &str,
+    context: &mut Vec<String>,
+    result: &mut Vec<Unit>,
+) -> Result<(), String> {
+    let line =
+        |kind, leading: &[&str]| line_unit(node, source, id_tag, &context.join("."), kind, leading);
+    let unit = match node.kind() {
+        "paragraph" => Some(line(EntryKind::Line, &[])?),
+        "choice" => Some(line(
+            EntryKind::Choice,
+            &["choice_marks", "label", "condition"],
+        )?),
+        "gather" if !children(node).any(|it| it.kind() == "paragraph") => {
+            Some(line(EntryKind::Line, &["gather_marks", "label"])?)
+        }
+        "string" => Some(string_unit(node, source, &context.join("."))),
+        "tag" | "include" | "line_comment" | "block_comment" | "todo_comment" => Some(None),
+        _ => None,
+    };
+    if let Some(unit) = unit {
+        result.extend(unit);
+        return Ok(());
+    }
+
+    let name = match node.kind() {
+        "knot_block" => section_name(node, "knot", source),
+        "stitch_block" => section_name(node, "stitch", source),
+        _ => None,
+    };
+    let pushed = name.is_some();
+    context.extend(name);
+    for child in children(node) {
+        collect(child, source, id_tag, context, result)?;
+    }
+    if pushed {
+        context.pop();
+    }
+    Ok(())
+}
+
+fn children<'t>(node: Node<'t>) -> impl Iterator<Item = Node<'t>> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor).collect_vec().into_iter()
+}
+
+/// The named children, with `content` nodes replaced by their children.
+fn items<'t>(node: Node<'t>) -> Vec<Node<'t>> {
+    children(node)
+        .flat_map(|it| match it.kind() {
+            "content" => items(it),
+            _ => vec![it],
+        })
+        .collect()
+}
+
+fn section_name(block: Node, header: &str, source: &str) -> Option<String> {
+    let header = children(block).find(|it| it.kind() == header)?;
+    let name = header.child_by_field_name("name")?;
+    Some(source[name.byte_range()].to_string())
+}
+
+/// A paragraph, choice or gather, if it has any words in it. `leading` are the kinds that
+/// come before the text (like the `*` of a choice).
+fn line_unit(
+    node: Node,
+    source: &str,
+    id_tag: &str,
+    context: &str,
+    kind: EntryKind,
+    leading: &[&str],
+) -> Result<Option<Unit>, String> {
+    let items = items(node);
+    let mut text = items
+        .iter()
+        .skip_while(|it| leading.contains(&it.kind()))
+        .take_while(|it| !TEXT_END.contains(&it.kind()))
+        .collect_vec();
+    let glued = text.last().is_some_and(|it| it.kind() == "glue");
+    while text.last().is_some_and(|it| it.kind() == "glue") {
+        text.pop();
+    }
+    let (Some(first), Some(last)) = (text.first(), text.last()) else {
+        return Ok(None);
+    };
+    if !text.iter().any(|it| has_words(**it, source)) {
+        return Ok(None);
+    }
+    let start = first.start_byte();
+    let end = start + source[start..last.end_byte()].trim_end().len();
+
+    let mut id = None;
+    for tag in tags(node) {
+        let written = tag_text(&source[tag.byte_range()]);
+        let Some(value) = written
+            .strip_prefix(id_tag)
+            .and_then(|it| it.strip_prefix(':'))
+        else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() || !value.chars().all(is_id_char) {
+            return Err(format!(
+                "line {}: `#{written}` isn't an ID. If the project uses `#{id_tag}:` for \
+                 something else, set `[l10n] id-tag` to another tag.",
+                line_number(source, tag.start_byte())
+            ));
+        }
+        let offset = tag.start_byte() + source[tag.byte_range()].find(value).unwrap_or(0);
+        id = Some((value.to_string(), offset..offset + value.len()));
+    }
+
+    Ok(Some(Unit {
+        kind,
+        parts: vec![start..end],
+        id,
+        end,
+        context: context.to_string(),
+        glued,
+        parent: node.parent().map(|it| it.id()),
+    }))
+}
+
+fn string_unit(node: Node, source: &str, context: &str) -> Option<Unit> {
+    // `{mood == "happy"}`: not something the player reads.
+    if node.parent().is_some_and(|it| it.kind() == "binary") {
+        return None;
+    }
+    let range = node.byte_range();
+    let quoted = &source[range.clone()];
+    let inner = quoted.strip_prefix('"')?.strip_suffix('"')?;
+    if !inner.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+    let start = range.start + 1;
+    Some(Unit {
+        kind: EntryKind::String,
+        parts: vec![start..start + inner.len()],
+        id: None,
+        end: range.end,
+        context: context.to_string(),
+        glued: false,
+        parent: None,
+    })
+}
+
+/// The tags of a line, including the ones in `[…]`.
+fn tags<'t>(node: Node<'t>) -> Vec<Node<'t>> {
+    items(node)
+        .into_iter()
+        .flat_map(|it| match it.kind() {
+            "tag" => vec![it],
+            "choice_only" => tags(it),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+fn has_words(node: Node, source: &str) -> bool {
+    match node.kind() {
+        "text" => source[node.byte_range()].chars().any(char::is_alphanumeric),
+        "choice_only" => items(node).into_iter().any(|it| has_words(it, source)),
+        _ => false,
+    }
+}
+
+fn string_id(context: &str, text: &str) -> String {
+    let hash = hash(text);
+    if context.is_empty() {
+        format!("str-{hash:08x}")
+    } else {
+        format!("{context}.str-{hash:08x}")
+    }
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-.".contains(c)
+}
+
+/// FNV-1a, because IDs must be the same for every build.
+fn hash(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn apply(source: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    let mut result = source.to_string();
+    edits.sort_by_key(|(range, _)| range.start);
+    for (range, replacement) in edits.into_iter().rev() {
+        result.replace_range(range, &replacement);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{translate, EntryKind, Extractor, L10nConfig};
+    use crate::{
+        fmt::{format_with, FormatConfig, Reflow},
+        lsp::tag_schema::TagSchema,
+    };
+    use indoc::indoc;
+
+    fn extract(sources: &[&str]) -> Vec<super::Extracted> {
+        let mut extractor = Extractor::new(&L10nConfig::default());
+        for source in sources {
+            extractor.scan(source).unwrap();
+        }
+        sources
+            .iter()
+            .map(|it| extractor.extract("main.ink", it).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn lines_get_ids() {
+        let source = indoc! {r#"
+            VAR name = "Bob"
+            Hello. #mood:happy
+            === knot ===
+            * [Ask] Asking. -> knot
+            - (back) Back again.
+            ~ temp greeting = "Hi there"
+            {name == "Bob": Bob!}
+            -> DONE
+        "#};
+        let extracted = &extract(&[source])[0];
+        let entries = extracted
+            .entries
+            .iter()
+            .map(|it| (it.kind, it.source.as_str(), it.context.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (EntryKind::String, "Bob", ""),
+                (EntryKind::Line, "Hello.", ""),
+                (EntryKind::Choice, "[Ask] Asking.", "knot"),
+                (EntryKind::Line, "Back again.", "knot"),
+                (EntryKind::String, "Hi there", "knot"),
+            ]
+        );
+        assert_eq!(extracted.added, 3);
+        let id = &extracted.entries[2].id;
+        assert!(id.starts_with("knot."), "{id}");
+        assert!(
+            extracted
+                .text
+                .contains(&format!("* [Ask] Asking. #id:{id} -> knot\n")),
+            "{}",
+            extracted.text
+        );
+    }
+
+    #[test]
+    fn ids_stay_put() {
+        let source = "Hello. #id:greeting\nBye.\n";
+        let first = &extract(&[source])[0];
+        assert_eq!(first.entries[0].id, "greeting");
+        assert_eq!(first.added, 1);
+
+        // Formatting (and wrapping) the tagged text changes neither IDs nor text.
+        let config = FormatConfig {
+            reflow: Reflow::Wrap,
+            line_width: 20,
+            ..FormatConfig::default()
+        };
+        let long = format!("{}A rather long line that has to be wrapped.\n", first.text);
+        let once = &extract(&[&long])[0];
+        let formatted = format_with(once.text.clone(), &config);
+        assert!(formatted.contains("<>"), "{formatted}");
+        let again = &extract(&[&formatted])[0];
+        assert_eq!(again.added, 0, "{}", again.text);
+        assert_eq!(again.entries, once.entries);
+    }
+
+    #[test]
+    fn copied_ids_are_replaced() {
+        let extracted = extract(&["Hello. #id:same\n", "Hello again. #id:same\n"]);
+        assert_eq!(extracted[0].text, "Hello. #id:same\n");
+        assert_eq!(extracted[0].added, 0);
+        assert_ne!(extracted[1].entries[0].id, "same");
+        assert_eq!(extracted[1].added, 1);
+    }
+
+    #[test]
+    fn translations_replace_the_text() {
+        let source = indoc! {r#"
+            Hello. #id:hello
+            * [Yes] #id:yes -> END
+            ~ temp name = "Bob"
+            Not translated. #id:other
+        "#};
+        let mut translations = HashMap::from([
+            ("hello".to_string(), "Hallo.".to_string()),
+            ("yes".to_string(), "[Ja]".to_string()),
+        ]);
+        let extracted = &extract(&[source])[0];
+        translations.insert(extracted.entries[2].id.clone(), "Robert".to_string());
+
+        let translated = translate(source, &L10nConfig::default(), &translations).unwrap();
+        assert_eq!(
+            translated.text,
+            indoc! {r#"
+                Hallo. #id:hello
+                * [Ja] #id:yes -> END
+                ~ temp name = "Robert"
+                Not translated. #id:other
+            "#}
+        );
+        assert_eq!((translated.translated, translated.untranslated), (3, 1));
+    }
+
+    #[test]
+    fn translations_are_escaped() {
+        let source = indoc! {r#"
+            Hello. #id:hello
+            Hello, {name}. #id:name
+            ~ temp greeting = "Hi"
+        "#};
+        let extracted = &extract(&[source])[0];
+        let translations = HashMap::from([
+            ("hello".to_string(), "Hallo #1 // {Welt}".to_string()),
+            ("name".to_string(), "Hallo \\#2, {name}.".to_string()),
+            (extracted.entries[2].id.clone(), "Sag \"Hallo\"".to_string()),
+        ]);
+        let translated = translate(source, &L10nConfig::default(), &translations).unwrap();
+        assert_eq!(
+            translated.text,
+            indoc! {r#"
+                Hallo \#1 \// \{Welt\} #id:hello
+                Hallo \#2, {name}. #id:name
+                ~ temp greeting = "Sag \"Hallo\""
+            "#}
+        );
+
+        let broken = HashMap::from([("name".to_string(), "Hallo {name.".to_string())]);
+        assert_eq!(
+            translate(source, &L10nConfig::default(), &broken).unwrap_err(),
+            "The translation of `name` has a `{` without a `}`"
+        );
+    }
+
+    #[test]
+    fn id_tags_must_not_mean_something_else() {
+        let mut extractor = Extractor::new(&L10nConfig::default());
+        assert!(extractor.scan("Hello. #id:not an id\n").is_err());
+
+        let schema: TagSchema = toml::from_str(r#"patterns = ["id:<a>:<b>"]"#).unwrap();
+        assert!(L10nConfig::default().check(&schema).is_err());
+        let schema: TagSchema = toml::from_str(r#"patterns = ["id:<line>"]"#).unwrap();
+        assert!(L10nConfig::default().check(&schema).is_ok());
+    }
+}
@@ -0,0 +1,434 @@
+//! Translation files: gettext PO, XLIFF 1.2 and CSV.
+//!
+//! The ID of an entry is the `msgctxt` of PO files, the `id` of XLIFF `trans-unit`s and
+//! the `id` column of CSV files. Translations are read from `msgstr`, `target` and the
+//! `target` column.
+
+use std::{collections::HashMap, fmt::Write as _, path::Path};
+
+use itertools::Itertools as _;
+
+use super::{Entry, L10nConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Po,
+    Xliff,
+    Csv,
+}
+
+impl FileFormat {
+    /// The format that files with this extension have.
+    pub fn of(path: &Path) -> Option<FileFormat> {
+        match path.extension()?.to_str()? {
+            "po" | "pot" => Some(FileFormat::Po),
+            "xlf" | "xliff" => Some(FileFormat::Xliff),
+            "csv" => Some(FileFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// A file for translators, with an empty translation for every entry.
+    pub fn write(self, entries: &[Entry], config: &L10nConfig) -> String {
+        match self {
+            FileFormat::Po => write_po(entries, config),
+            FileFormat::Xliff => write_xliff(entries, config),
+            FileFormat::Csv => write_csv(entries),
+        }
+    }
+
+    /// The translations of a file, by ID. Entries without a translation are left out.
+    pub fn read(self, text: &str) -> Result<HashMap<String, String>, String> {
+        let translations = match self {
+            FileFormat::Po => read_po(text)?,
+            FileFormat::Xliff => read_xliff(text)?,
+            FileFormat::Csv => read_csv(text)?,
+        };
+        Ok(translations
+            .into_iter()
+            .filter(|(_, translation)| !translation.is_empty())
+            .collect())
+    }
+}
+
+fn write_po(entries: &[Entry], config: &L10nConfig) -> String {
+    let mut out = String::new();
+    out.push_str("msgid \"\"\nmsgstr \"\"\n");
+    out.push_str("\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    writeln!(
+        out,
+        "\"X-Source-Language: {}\\n\"",
+        po_escape(&config.source_language)
+    )
+    .unwrap();
+    for entry in entries {
+        out.push('\n');
+        match entry.context.as_str() {
+            "" => writeln!(out, "#. {}", entry.kind.as_str()),
+            context => writeln!(out, "#. {} in {context}", entry.kind.as_str()),
+        }
+        .unwrap();
+        writeln!(out, "#: {}:{}", entry.file, entry.line).unwrap();
+        writeln!(out, "msgctxt \"{}\"", po_escape(&entry.id)).unwrap();
+        writeln!(out, "msgid \"{}\"", po_escape(&entry.source)).unwrap();
+        out.push_str("msgstr \"\"\n");
+    }
+    out
+}
+
+fn po_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn read_po(text: &str) -> Result<HashMap<String, String>, String> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Context,
+        Id,
+        Translation,
+    }
+
+    let mut result = HashMap::new();
+    let mut context: Option<String> = None;
+    let mut translation = String::new();
+    let mut field = Field::None;
+    let mut finish = |context: &mut Option<String>, translation: &mut String| {
+        if let Some(context) = context.take() {
+            result.insert(context, std::mem::take(translation));
+        }
+        translation.clear();
+    };
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let (keyword, rest) = match line.split_once(char::is_whitespace) {
+            _ if line.is_empty() || line.starts_with('#') => continue,
+            _ if line.starts_with('"') => ("", line),
+            Some((keyword, rest)) => (keyword, rest.trim()),
+            None => return Err(format!("line {}: expected a string", number + 1)),
+        };
+        let value =
+            po_unescape(rest).ok_or_else(|| format!("line {}: expected a string", number + 1))?;
+        match keyword {
+            "msgctxt" => {
+                if field != Field::Context {
+                    finish(&mut context, &mut translation);
+                }
+                context = Some(value);
+                field = Field::Context;
+            }
+            "msgid" => {
+                if field == Field::Translation {
+                    finish(&mut context, &mut translation);
+                }
+                field = Field::Id;
+            }
+            "msgstr" => {
+                translation = value;
+                field = Field::Translation;
+            }
+            "" => match field {
+                Field::Context => context.get_or_insert_with(String::new).push_str(&value),
+                Field::Translation => translation.push_str(&value),
+                Field::Id | Field::None => {}
+            },
+            // Plurals and the like aren't something ink has.
+            _ => field = Field::None,
+        }
+    }
+    finish(&mut context, &mut translation);
+    Ok(result)
+}
+
+/// The content of a quoted PO string.
+fn po_unescape(quoted: &str) -> Option<String> {
+    let inner = quoted.strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => result.push('\n'),
+            't' => result.push('\t'),
+            other => result.push(other),
+        }
+    }
+    Some(result)
+}
+
+fn write_xliff(entries: &[Entry], config: &L10nConfig) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<xliff version=\"1.2\" xmlns=\"urn:oasis:names:tc:xliff:document:1.2\">\n");
+    writeln!(
+        out,
+        "  <file original=\"story\" source-language=\"{}\" datatype=\"plaintext\">",
+        xml_escape(&config.source_language)
+    )
+    .unwrap();
+    out.push_str("    <body>\n");
+    for entry in entries {
+        writeln!(out, "      <trans-unit id=\"{}\">", xml_escape(&entry.id)).unwrap();
+        writeln!(
+            out,
+            "        <source>{}</source>",
+            xml_escape(&entry.source)
+        )
+        .unwrap();
+        let context = match entry.context.as_str() {
+            "" => String::new(),
+            context => format!(" in {context}"),
+        };
+        writeln!(
+            out,
+            "        <note>{}{} ({}:{})</note>",
+            entry.kind.as_str(),
+            xml_escape(&context),
+            xml_escape(&entry.file),
+            entry.line
+        )
+        .unwrap();
+        out.push_str("      </trans-unit>\n");
+    }
+    out.push_str("    </body>\n  </file>\n</xliff>\n");
+    out
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#')?.parse().ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Good enough for XLIFF files, without a full XML parser: finds the `trans-unit`s and
+/// their `target`. Markup inside a target is kept as it is.
+fn read_xliff(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut result = HashMap::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<trans-unit") {
+        rest = &rest[start..];
+        let open_end = rest.find('>').ok_or("unterminated <trans-unit>")?;
+        let id = attribute(&rest[..open_end], "id").ok_or("<trans-unit> without an id")?;
+        let end = rest
+            .find("</trans-unit>")
+            .ok_or_else(|| format!("trans-unit `{id}` isn't closed"))?;
+        let unit = &rest[open_end + 1..end];
+        if let Some(target) = element(unit, "target") {
+            result.insert(xml_unescape(&id), xml_unescape(target));
+        }
+        rest = &rest[end..];
+    }
+    Ok(result)
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let (_, after) = tag.split_once(&format!(" {name}="))?;
+    let quote = after
+        .chars()
+        .next()
+        .filter(|it| *it == '"' || *it == '\'')?;
+    let value = after[1..].split(quote).next()?;
+    Some(value.to_string())
+}
+
+/// The content of the first `<name>` element in `text`.
+fn element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let start = text.find(&format!("<{name}"))?;
+    let open = &text[start..];
+    let open_end = open.find('>')?;
+    if open[..open_end].ends_with('/') {
+        return Some("");
+    }
+    let content = &open[open_end + 1..];
+    let end = content.find(&format!("</{name}>"))?;
+    Some(&content[..end])
+}
+
+fn write_csv(entries: &[Entry]) -> String {
+    let mut out = String::from("id,kind,context,location,source,target\n");
+    for entry in entries {
+        let location = format!("{}:{}", entry.file, entry.line);
+        let fields = [
+            entry.id.as_str(),
+            entry.kind.as_str(),
+            entry.context.as_str(),
+            location.as_str(),
+            entry.source.as_str(),
+            "",
+        ];
+        writeln!(out, "{}", fields.map(csv_escape).join(",")).unwrap();
+    }
+    out
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn read_csv(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut records = csv_records(text).into_iter();
+    let header = records.next().ok_or("the CSV file is empty")?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|it| it.trim() == name)
+            .ok_or_else(|| format!("the CSV file has no `{name}` column"))
+    };
+    let (id, target) = (column("id")?, column("target")?);
+    Ok(records
+        .filter_map(|record| Some((record.get(id)?.clone(), record.get(target)?.clone())))
+        .collect())
+}
+
+/// RFC 4180: fields are separated by commas, and quoted if they contain commas, quotes
+/// (doubled) or line breaks.
+fn csv_records(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::FileFormat;
+    use crate::l10n::{Entry, EntryKind, L10nConfig};
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry {
+                id: "intro.1a2b3c".to_string(),
+                kind: EntryKind::Line,
+                source: "\"Hello,\" she said. <Whispering> & \\waving\\".to_string(),
+                context: "intro".to_string(),
+                file: "main.ink".to_string(),
+                line: 3,
+            },
+            Entry {
+                id: "str-00ff00ff".to_string(),
+                kind: EntryKind::String,
+                source: "Bob".to_string(),
+                context: String::new(),
+                file: "main.ink".to_string(),
+                line: 1,
+            },
+        ]
+    }
+
+    /// Fill in the translations, the way a translation tool would.
+    fn translate(format: FileFormat, written: &str) -> String {
+        match format {
+            // The first entry is followed by another one, the header and the last aren't.
+            FileFormat::Po => written.replacen(
+                "msgstr \"\"\n\n",
+                "msgstr \"\\\"Hallo,\\\" sagte \"\n\"sie.\"\n\n",
+                1,
+            ),
+            FileFormat::Xliff => written.replacen(
+                "</source>",
+                "</source>\n        <target state=\"translated\">&quot;Hallo,&quot; &lt;3</target>",
+                1,
+            ),
+            FileFormat::Csv => written.replacen(",\n", ",\"\"\"Hallo\"\", sagte sie\"\n", 1),
+        }
+    }
+
+    #[test]
+    fn translations_are_read_back() {
+        let expected = [
+            (FileFormat::Po, "\"Hallo,\" sagte sie."),
+            (FileFormat::Xliff, "\"Hallo,\" <3"),
+            (FileFormat::Csv, "\"Hallo\", sagte sie"),
+        ];
+        for (format, translation) in expected {
+            let written = format.write(&entries(), &L10nConfig::default());
+            let translations = format.read(&translate(format, &written)).unwrap();
+            assert_eq!(
+                translations,
+                HashMap::from([("intro.1a2b3c".to_string(), translation.to_string())]),
+                "{format:?}:\n{written}"
+            );
+        }
+    }
+
+    #[test]
+    fn untranslated_files_have_no_translations() {
+        for format in [FileFormat::Po, FileFormat::Xliff, FileFormat::Csv] {
+            let written = format.write(&entries(), &L10nConfig::default());
+            assert_eq!(format.read(&written), Ok(HashMap::new()), "{format:?}");
+        }
+    }
+}
@@ -2,7 +2,9 @@
 
 use std::error::Error;
 
+pub mod config;
 pub mod fmt;
+pub mod l10n;
 pub mod lsp;
 
 pub type AppResult<T> = Result<T, Box<dyn Error + Sync + Send>>;
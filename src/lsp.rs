@@ -1,25 +1,36 @@
-use crate::AppResult;
+use crate::{config::Config, AppResult};
 use futures::FutureExt as _;
 use line_index::WideEncoding;
 use lsp_server::{
     Connection, ExtractError, Message, Notification, Request, RequestId, Response, ResponseError,
 };
 use lsp_types::*;
-use std::{ops::Not, path::Path, time::Duration};
+use std::{
+    ops::Not,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 mod diagnostics;
 mod file_watching;
 mod http_server;
 mod ink_visitors;
+pub mod lints;
 mod location;
 mod notification_handlers;
+mod paths;
 mod request_handlers;
 pub mod salsa;
 mod shared;
 mod state;
+pub mod tag_schema;
 
+pub use paths::{path_to_uri, uri_to_path};
 pub use salsa::{DocId, InkGetters, Ops, StoryRoot};
-pub use state::{DocumentNotFound, GotoLocationError, InvalidPosition, State};
+pub use state::{
+    DocumentNotFound, GotoLocationError, GraphEdge, GraphNode, InvalidPosition, RenameError, State,
+    StoryGraph, StoryStats, SymbolDefinition, SymbolReport, SymbolUsage, TagUsage, STORY_START,
+};
 
 // For that extra bit of convenience
 pub type SharedState = shared::SharedValue<state::State>;
@@ -103,7 +114,12 @@ fn server_capabilities(params: &InitializeParams) -> ServerCapabilities {
         )),
         completion_provider: Some(CompletionOptions {
             resolve_provider: Some(false),
-            trigger_characters: Some(["->", "-> ", "{"].into_iter().map(str::to_string).collect()),
+            trigger_characters: Some(
+                ["->", "-> ", "{", "#"]
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            ),
             all_commit_characters: None,
             work_done_progress_options: WorkDoneProgressOptions {
                 work_done_progress: Some(false),
@@ -111,6 +127,8 @@ fn server_capabilities(params: &InitializeParams) -> ServerCapabilities {
             completion_item: None,
         }),
         rename_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
         position_encoding: find_utf8(params).or(Some(PositionEncodingKind::UTF16)),
         ..Default::default()
     }
@@ -136,6 +154,8 @@ fn handle_request(request: Request, state: &SharedState) -> Result<Response, Req
         GotoDefinition,
         References,
         Rename,
+        Formatting,
+        CodeActionRequest,
     }
 }
 
@@ -166,6 +186,35 @@ enum NotificationHandlerResult {
     NotInterested(Notification),
 }
 
+/// Read all ink files below `root` into a fresh [`State`], the same way the language
+/// server does on startup, and apply the project config. For the command line tools.
+pub fn load_workspace(root: &Path) -> AppResult<State> {
+    let mut state = State::new(None, false);
+    file_watching::read_files(root, &mut state)?;
+    state.configure(&Config::discover(root)?);
+    Ok(state)
+}
+
+/// Like [`load_workspace`] with the ink files below all of `roots` (and the config of the
+/// first one), but the state is kept up to date with them for as long as the returned watcher
+/// lives. After each update, the files that changed are sent to `changes`. For the command
+/// line tools' `--watch`.
+pub fn watch_workspace(
+    roots: &[PathBuf],
+    changes: std::sync::mpsc::Sender<Vec<Uri>>,
+) -> AppResult<(SharedState, impl notify::Watcher)> {
+    let Some((first, rest)) = roots.split_first() else {
+        return Err("Nothing to watch".into());
+    };
+    let mut state = load_workspace(first)?;
+    for root in rest {
+        file_watching::read_files(root, &mut state)?;
+    }
+    let state = SharedState::new(state);
+    let watcher = file_watching::start_file_watcher(state.clone(), roots.to_vec(), Some(changes))?;
+    Ok((state, watcher))
+}
+
 pub fn run_lsp() -> AppResult<()> {
     // Create the transport. Includes the stdio (stdin and stdout) versions but this could
     // also be implemented to use sockets or HTTP.
@@ -255,6 +304,12 @@ pub fn run_lsp() -> AppResult<()> {
         .collect();
 
     log::debug!("Workspace Folders: {workspace_folders:?}");
+    if let Some(root) = workspace_folders.first() {
+        match Config::discover(root) {
+            Ok(config) => state.lock()?.configure(&config),
+            Err(err) => log::error!("Ignoring project config: {err}"),
+        }
+    }
     for path in workspace_folders.iter() {
         file_watching::read_initial_files(&path, &state)?;
     }
@@ -284,6 +339,7 @@ pub fn run_lsp() -> AppResult<()> {
         Some(file_watching::start_file_watcher(
             state.clone(),
             workspace_folders,
+            None,
         ))
     };
 
@@ -320,6 +376,9 @@ pub fn run_lsp() -> AppResult<()> {
         if let Some(reply) = handled {
             let _ = client_connection.sender.send(reply);
         }
+        for message in take_messages(&state) {
+            let _ = client_connection.sender.send(message);
+        }
     }
 
     log::trace!("sending shutdown signal");
@@ -381,6 +440,24 @@ pub(crate) fn handle_message(
     }
 }
 
+/// The messages the handlers left for the user, as `window/showMessage` notifications.
+fn take_messages(state: &SharedState) -> Vec<Message> {
+    let Ok(mut state) = state.lock() else {
+        return Vec::new();
+    };
+    state
+        .take_messages()
+        .into_iter()
+        .filter_map(|params| {
+            Some(Message::Notification(Notification {
+                method: <notification::ShowMessage as notification::Notification>::METHOD
+                    .to_string(),
+                params: serde_json::to_value(params).ok()?,
+            }))
+        })
+        .collect()
+}
+
 trait RequestHandler: lsp_types::request::Request
 where
     Self::Params: std::fmt::Debug,
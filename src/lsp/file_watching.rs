@@ -1,6 +1,6 @@
-use super::SharedState;
+use super::{SharedState, State};
 use crate::{
-    lsp::{DID_CHANGE_WATCHED_FILES, INK_GLOB},
+    lsp::{path_to_uri, DID_CHANGE_WATCHED_FILES, INK_GLOB},
     AppResult,
 };
 use lsp_server::{Connection, Message, Request};
@@ -8,18 +8,20 @@ use lsp_types::{
     request::{self, Request as _},
     GlobPattern, Registration, RegistrationParams, Uri,
 };
-use std::{path::PathBuf, str::FromStr};
+use std::path::PathBuf;
 
 pub(crate) fn read_initial_files(root: &std::path::Path, state: &SharedState) -> AppResult<()> {
+    read_files(root, &mut *state.lock()?)
+}
+
+pub(crate) fn read_files(root: &std::path::Path, state: &mut State) -> AppResult<()> {
     // We'll liberally `?` out of any error. Failing to read initial would leave the server in a weird state.
     for dir_entry in walkdir::WalkDir::new(root) {
         let dir_entry = dir_entry?;
         let path = dir_entry.path();
-        let mut state = state.lock()?;
         if path.is_file() && path.extension().is_some_and(|ext| ext == "ink") {
             let path = std::path::absolute(path)?;
-            let path = path.to_str().ok_or("path wasn't a proper UTF-8 string")?;
-            let uri = Uri::from_str(&format!("file://{path}"))?;
+            let uri = path_to_uri(&path)?;
             let text = std::fs::read_to_string(path)?;
             state.edit(uri, text);
         }
@@ -63,12 +65,14 @@ pub(crate) fn register_file_change_notification(
     Ok(())
 }
 
+/// Keep `state` up to date with the ink files below `roots`. If there are `changes`, the
+/// files that changed are sent there once `state` knows about them.
 pub(crate) fn start_file_watcher(
     state: super::SharedState,
     roots: impl IntoIterator<Item = PathBuf>,
+    changes: Option<std::sync::mpsc::Sender<Vec<Uri>>>,
 ) -> AppResult<impl notify::Watcher> {
     use notify::Watcher as _;
-    use std::str::FromStr;
 
     #[derive(Debug)]
     enum WatchEventKind {
@@ -79,12 +83,16 @@ pub(crate) fn start_file_watcher(
     let mut watcher = notify::recommended_watcher(move |res| match res {
         Ok(notify::Event { kind, paths, .. }) => {
             let kind = match kind {
-                notify::EventKind::Modify(notify::event::ModifyKind::Data(_)) => {
-                    WatchEventKind::Edit
-                }
-                notify::EventKind::Remove(notify::event::RemoveKind::File) => {
-                    WatchEventKind::Forget
-                }
+                // Many editors save by writing a new file and renaming it over the old one.
+                notify::EventKind::Modify(notify::event::ModifyKind::Data(_))
+                | notify::EventKind::Create(notify::event::CreateKind::File)
+                | notify::EventKind::Modify(notify::event::ModifyKind::Name(
+                    notify::event::RenameMode::To,
+                )) => WatchEventKind::Edit,
+                notify::EventKind::Remove(notify::event::RemoveKind::File)
+                | notify::EventKind::Modify(notify::event::ModifyKind::Name(
+                    notify::event::RenameMode::From,
+                )) => WatchEventKind::Forget,
                 _ => return,
             };
             let inks = paths
@@ -93,26 +101,32 @@ pub(crate) fn start_file_watcher(
             let mut state = state
                 .lock()
                 .expect("we should be able to get a lock on the state");
+            let mut changed = Vec::new();
             for path in inks {
                 let path = std::path::absolute(path).expect("file should have a proper path");
-                let path = path.to_str().expect("we should get proper file paths");
-                let uri = Uri::from_str(&format!("file://{path}")).unwrap();
+                let uri = path_to_uri(&path).expect("we should get proper file paths");
                 match kind {
                     WatchEventKind::Edit => {
                         let result =
-                            std::fs::read_to_string(path).map(|text| state.edit(uri, text));
+                            std::fs::read_to_string(path).map(|text| state.edit(uri.clone(), text));
                         if let Err(err) = result {
                             log::error!("document read error: {err:?}");
                             continue;
                         }
                     }
                     WatchEventKind::Forget => {
-                        if let Err(err) = state.forget(uri) {
+                        if let Err(err) = state.forget(uri.clone()) {
                             log::error!("document remove error: {err:?}");
                             continue;
                         }
                     }
                 };
+                changed.push(uri);
+            }
+            drop(state);
+            if let Some(changes) = changes.as_ref().filter(|_| !changed.is_empty()) {
+                // Nobody listening anymore is no reason to stop keeping the state up to date.
+                let _ = changes.send(changed);
             }
         }
         Err(e) => log::error!("watch error: {:?}", e),
@@ -5,14 +5,14 @@ use crate::lsp::{
 
 use super::{state::DocumentNotFound, SharedState};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::IntoResponse,
     routing::get,
     Router,
 };
 use ink_document::ids::NodeId;
 use mini_milc::Db as _;
-use std::future::Future;
+use std::{collections::HashMap, future::Future};
 use tap::Pipe;
 
 pub fn start<F>(state: SharedState, shutdown: F) -> Result<(), std::io::Error>
@@ -28,6 +28,7 @@ where
             let app = Router::new()
                 .route("/", get(root))
                 .route("/workspace-symbols", get(workspace_symbols))
+                .route("/graph", get(graph))
                 .route("/file/{*pth}", get(file::<html::root::Html>))
                 .with_state(state);
 
@@ -68,6 +69,14 @@ async fn root(State(state): State<SharedState>) -> impl IntoResponse {
                 menu.list_item(|li| {
                     li.anchor(|a| a.href("workspace-symbols").text("Workspace Symbols"))
                 });
+                menu.list_item(|li| {
+                    li.text("Story Graph: ");
+                    li.anchor(|a| a.href("graph?format=dot").text("DOT"));
+                    li.text(", ");
+                    li.anchor(|a| a.href("graph?format=mermaid").text("Mermaid"));
+                    li.text(", ");
+                    li.anchor(|a| a.href("graph?format=json").text("JSON"))
+                });
                 menu.list_item(|li| {
                     li.text("Files");
                     li.unordered_list(|ul| {
@@ -108,6 +117,24 @@ async fn workspace_symbols(State(state): State<SharedState>) -> impl IntoRespons
         .pipe(axum::response::Html)
 }
 
+/// The story graph, as `?format=dot`, `mermaid` or `json` (the default).
+async fn graph(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Result<String, (axum::http::StatusCode, String)> {
+    let state = state.lock().expect("I want this lock!");
+    let graph = state.story_graph();
+    match params.get("format").map(String::as_str) {
+        Some("dot") => Ok(graph.to_dot()),
+        Some("mermaid") => Ok(graph.to_mermaid()),
+        Some("json") | None => Ok(graph.to_json().to_string()),
+        Some(other) => Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("Unknown graph format `{other}`, use dot, mermaid or json"),
+        )),
+    }
+}
+
 async fn file<R>(
     Path(path): Path<std::path::PathBuf>,
     State(state): State<SharedState>,
@@ -1,3 +1,5 @@
 pub mod doc_symbols;
 pub mod parse_errors;
+pub mod stats;
+pub mod tags;
 pub mod ws_symbols;
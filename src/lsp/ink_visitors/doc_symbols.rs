@@ -1,5 +1,6 @@
 use std::ops::Deref;
 
+use super::stats::stats;
 use builder::SymbolBuilder;
 use ink_document::InkDocument;
 use ink_syntax::{AllNamed, GlobalKeyword};
@@ -135,6 +136,16 @@ impl<'a> DocumentSymbols<'a> {
     fn lsp_range<N: Node<'a>>(&self, node: N) -> lsp_types::Range {
         self.doc.lsp_range(node.range())
     }
+
+    /// The parameters of a knot or stitch (if any), followed by its [`stats`], so the
+    /// outline shows how long each one is.
+    fn detail(&self, params: Option<String>, block: tree_sitter::Node<'a>) -> String {
+        let stats = stats(self.doc, block);
+        match params {
+            Some(params) => format!("{params} · {stats}"),
+            None => stats.to_string(),
+        }
+    }
 }
 
 impl<'tree> Visitor<'tree, AllNamed<'tree>> for DocumentSymbols<'tree> {
@@ -223,10 +234,11 @@ impl<'tree> Visitor<'tree, AllNamed<'tree>> for DocumentSymbols<'tree> {
                     .text(knot.name())
                     .unwrap_or_else(|| String::from("DUMMY KNOT"));
 
+                let params = knot.params().and_then(|params| self.text(params));
                 let sym = SymbolBuilder::new(kind)
                     .name(name)
                     .range(self.lsp_range(block))
-                    .maybe_detail(knot.params().and_then(|params| self.text(params)))
+                    .maybe_detail(Some(self.detail(params, *block.raw())))
                     .build();
 
                 DescendWith(sym)
@@ -240,10 +252,11 @@ impl<'tree> Visitor<'tree, AllNamed<'tree>> for DocumentSymbols<'tree> {
                     .text(stitch.name())
                     .unwrap_or_else(|| String::from("DUMMY STITCH"));
 
+                let params = stitch.params().and_then(|params| self.text(params));
                 let sym = SymbolBuilder::new(SymbolKind::CLASS)
                     .name(name)
                     .range(self.lsp_range(block))
-                    .maybe_detail(stitch.params().and_then(|params| self.text(params)))
+                    .maybe_detail(Some(self.detail(params, *block.raw())))
                     .build();
 
                 DescendWith(sym)
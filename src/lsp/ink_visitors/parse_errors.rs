@@ -1,3 +1,4 @@
+use crate::lsp::lints::Rule;
 use ink_document::InkDocument;
 use lsp_types::{Diagnostic, DiagnosticSeverity};
 use std::hint::unreachable_unchecked;
@@ -32,6 +33,7 @@ impl<'a> Visitor<'a, UntypedNode<'a>> for ParseErrorsVisitor<'a> {
             state.push(Diagnostic {
                 range: self.doc.lsp_range(node.range()),
                 severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(Rule::SyntaxError.code()),
                 source: Some(String::from("ink-tool")),
                 // tree-sitter makes it very hard to be specific here, so we don't even try.
                 message: String::from("Syntax error"),
@@ -0,0 +1,337 @@
+use std::fmt::Display;
+
+use ink_document::{ids::DefId, InkDocument};
+use tree_sitter::Node;
+use tree_traversal::TreeTraversal as _;
+use type_sitter::Node as _;
+
+use crate::lsp::location::TextRange;
+
+/// How much there is to read and to choose from in a piece of ink.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Words of prose (text, choices, alternatives; not code, strings, tags or comments)
+    pub words: usize,
+    pub choices: usize,
+    pub gathers: usize,
+    /// Diverts, tunnels and threads
+    pub diverts: usize,
+    /// How deeply choices are nested (`* *` is 2). 0 if there are no choices.
+    pub max_depth: usize,
+    /// The fewest words a player reads before the flow leaves: the shortest choice of each
+    /// set of choices, the shortest alternative, no conditional text. Diverts aren't
+    /// followed, so this is only an estimate.
+    pub min_words: usize,
+}
+
+impl Stats {
+    /// Add up the counts of `self` and `other`. The minimum stays that of `self`, because
+    /// the two don't necessarily play one after the other.
+    pub fn add(&mut self, other: &Stats) {
+        self.words += other.words;
+        self.choices += other.choices;
+        self.gathers += other.gathers;
+        self.diverts += other.diverts;
+        self.max_depth = self.max_depth.max(other.max_depth);
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = |n: usize, what: &str| match n {
+            1 => format!("1 {what}"),
+            n => format!("{n} {what}s"),
+        };
+        write!(f, "{}", count(self.words, "word"))?;
+        if self.choices > 0 {
+            write!(
+                f,
+                " · {} (depth {})",
+                count(self.choices, "choice"),
+                self.max_depth
+            )?;
+        }
+        if self.gathers > 0 {
+            write!(f, " · {}", count(self.gathers, "gather"))?;
+        }
+        if self.diverts > 0 {
+            write!(f, " · {}", count(self.diverts, "divert"))?;
+        }
+        write!(f, " · min. {}", self.min_words)
+    }
+}
+
+/// The numbers of a file, as a whole and for each knot and stitch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// Everything before the first knot
+    pub top: Stats,
+    /// In the order they appear in
+    pub sections: Vec<SectionStats>,
+}
+
+impl FileStats {
+    /// The counts of the whole file. The minimum is the one of the top of the file,
+    /// since that is what plays when the story (or the file's INCLUDE) gets there.
+    pub fn total(&self) -> Stats {
+        let mut total = self.top;
+        for section in &self.sections {
+            total.add(&section.stats);
+        }
+        total
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionStats {
+    /// The name of the knot or stitch
+    pub def: DefId,
+    pub kind: SectionKind,
+    /// The full name, like `knot.stitch`
+    pub path: String,
+    pub range: TextRange,
+    /// A knot's numbers don't include its stitches.
+    pub stats: Stats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Knot,
+    Stitch,
+    Function,
+}
+
+impl SectionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SectionKind::Knot => "knot",
+            SectionKind::Stitch => "stitch",
+            SectionKind::Function => "function",
+        }
+    }
+}
+
+pub fn file_stats(doc: &InkDocument) -> FileStats {
+    let mut result = FileStats {
+        top: stats(doc, *doc.root().raw()),
+        sections: Vec::new(),
+    };
+    for block in doc.root().depth_first::<ink_syntax::KnotBlock>() {
+        let Ok(knot) = block.header() else {
+            continue;
+        };
+        let kind = match knot.function() {
+            Some(_) => SectionKind::Function,
+            None => SectionKind::Knot,
+        };
+        let name = doc.node_text(knot.name()).trim();
+        result.sections.push(SectionStats {
+            def: DefId::from(knot),
+            kind,
+            path: name.to_string(),
+            range: doc.lsp_range(block.range()).into(),
+            stats: stats(doc, *block.raw()),
+        });
+        for block in block.depth_first::<ink_syntax::StitchBlock>() {
+            let Ok(stitch) = block.header() else {
+                continue;
+            };
+            result.sections.push(SectionStats {
+                def: DefId::from(stitch),
+                kind: SectionKind::Stitch,
+                path: format!("{name}.{}", doc.node_text(stitch.name()).trim()),
+                range: doc.lsp_range(block.range()).into(),
+                stats: stats(doc, *block.raw()),
+            });
+        }
+    }
+    result
+}
+
+/// The numbers of a knot, stitch or whole file, without the knots or stitches in it.
+pub fn stats(doc: &InkDocument, section: Node) -> Stats {
+    let mut result = Stats::default();
+    for child in children(section) {
+        count(doc, child, &mut result);
+    }
+    result.min_words = sequence(doc, section);
+    result
+}
+
+/// Nothing to read in these.
+const SKIPPED: &[&str] = &[
+    "knot_block",
+    "stitch_block",
+    "knot",
+    "stitch",
+    "string",
+    "tag",
+    "label",
+    "line_comment",
+    "block_comment",
+    "todo_comment",
+    "include",
+    "global",
+    "list",
+    "external",
+];
+
+fn count(doc: &InkDocument, node: Node, stats: &mut Stats) {
+    match node.kind() {
+        kind if SKIPPED.contains(&kind) => return,
+        "text" => stats.words += words(doc, node),
+        "choice" => {
+            stats.choices += 1;
+            let depth = children(node)
+                .find(|it| it.kind() == "choice_marks")
+                .map_or(1, |marks| marks_depth(doc, marks));
+            stats.max_depth = stats.max_depth.max(depth);
+        }
+        "gather" => stats.gathers += 1,
+        // `-> a -> b` is one divert, even if it is nested in the tree.
+        "divert" | "tunnel" | "thread" => {
+            stats.diverts += 1;
+            return;
+        }
+        _ => {}
+    }
+    for child in children(node) {
+        count(doc, child, stats);
+    }
+}
+
+/// The fewest words in `node`, see [`Stats::min_words`].
+fn minimum(doc: &InkDocument, node: Node) -> usize {
+    match node.kind() {
+        kind if SKIPPED.contains(&kind) => 0,
+        "text" => words(doc, node),
+        "divert" | "tunnel" | "thread" | "code" | "conditional_text" | "cond_block" => 0,
+        "alternatives" | "multiline_alternatives" => {
+            children(node).map(|it| minimum(doc, it)).min().unwrap_or(0)
+        }
+        _ => sequence(doc, node),
+    }
+}
+
+/// The fewest words in the children of `node`, which play one after the other. Of the
+/// choices next to each other, the player only picks one.
+fn sequence(doc: &InkDocument, node: Node) -> usize {
+    let mut total = 0;
+    let mut choices: Option<usize> = None;
+    for child in children(node) {
+        let words = minimum(doc, child);
+        if child.kind() == "choice_block" {
+            choices = Some(choices.map_or(words, |it| it.min(words)));
+        } else {
+            total += choices.take().unwrap_or(0) + words;
+        }
+    }
+    total + choices.unwrap_or(0)
+}
+
+fn words(doc: &InkDocument, text: Node) -> usize {
+    doc.text(text.byte_range())
+        .split_whitespace()
+        .filter(|it| it.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// `* *` and `**` are both 2 deep.
+fn marks_depth(doc: &InkDocument, marks: Node) -> usize {
+    doc.text(marks.byte_range())
+        .chars()
+        .filter(|it| matches!(it, '*' | '+'))
+        .count()
+}
+
+fn children<'t>(node: Node<'t>) -> impl Iterator<Item = Node<'t>> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_stats, SectionKind, Stats};
+    use indoc::indoc;
+    use ink_document::InkDocument;
+
+    #[test]
+    fn sections_are_counted_on_their_own() {
+        let doc = InkDocument::new(
+            indoc! {"
+                Once upon a time.
+                -> castle
+                === castle ===
+                The castle is dark.
+                * Knock[] on the door.
+                  Nobody answers.
+                  * * Knock again
+                      Still nothing.
+                * Leave
+                - You walk away. -> END
+                = garden
+                Roses everywhere.
+                -> DONE
+                === function greet(name) ===
+                Hello {name}!
+            "}
+            .to_string(),
+            None,
+        );
+        let stats = file_stats(&doc);
+        assert_eq!(
+            stats.top,
+            Stats {
+                words: 4,
+                diverts: 1,
+                min_words: 4,
+                ..Default::default()
+            }
+        );
+        let sections = stats
+            .sections
+            .iter()
+            .map(|it| (it.path.as_str(), it.kind, it.stats))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            [
+                (
+                    "castle",
+                    SectionKind::Knot,
+                    Stats {
+                        words: 18,
+                        choices: 3,
+                        gathers: 1,
+                        diverts: 1,
+                        max_depth: 2,
+                        // "The castle is dark.", "Leave", "You walk away."
+                        min_words: 8,
+                    }
+                ),
+                (
+                    "castle.garden",
+                    SectionKind::Stitch,
+                    Stats {
+                        words: 2,
+                        diverts: 1,
+                        min_words: 2,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    "greet",
+                    SectionKind::Function,
+                    Stats {
+                        words: 1,
+                        min_words: 1,
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(stats.total().words, 25);
+    }
+}
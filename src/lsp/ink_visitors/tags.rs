@@ -0,0 +1,29 @@
+use ink_document::InkDocument;
+use tree_traversal::TreeTraversal as _;
+use type_sitter::Node as _;
+
+use crate::lsp::{location::TextRange, tag_schema::tag_text};
+
+/// A `#tag` as written in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub range: TextRange,
+    /// The tag without the `#`
+    pub text: String,
+    /// Tags can contain `{…}` logic, which we can only evaluate at runtime.
+    pub dynamic: bool,
+}
+
+pub fn tags(doc: &InkDocument) -> Vec<Tag> {
+    doc.root()
+        .depth_first::<ink_syntax::Tag>()
+        .map(|tag| {
+            let text = tag_text(doc.node_text(tag));
+            Tag {
+                range: doc.lsp_range(tag.range()).into(),
+                text: text.to_string(),
+                dynamic: text.contains('{'),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,255 @@
+//! Rule IDs and severities for diagnostics.
+//!
+//! Every diagnostic we produce belongs to a [`Rule`], whose ID ends up in
+//! [`Diagnostic::code`](lsp_types::Diagnostic::code). Projects can change the
+//! level of each rule in the `[lints]` section of their `ink-tool.toml`:
+//!
+//! ``` toml
+//! [lints]
+//! unused-knot = "allow" # entry points, called from the game engine
+//! unused-temp = "error"
+//! ```
+//!
+//! Anything that isn't an error can also be silenced for a single line
+//! (`// ink-tool: allow(unused-knot)`, either at the end of that line or on
+//! a line of its own before it) or a whole file (`// ink-tool: allow-file(unused-knot)`).
+
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use derive_more::derive::{Display, Error};
+use lsp_types::{DiagnosticSeverity, NumberOrString};
+use serde::Deserialize;
+
+macro_rules! rules {
+    ( $( $(#[doc = $doc:literal])* $variant:ident = $id:literal, $level:ident; )+ ) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+        #[serde(try_from = "String")]
+        pub enum Rule {
+            $( $(#[doc = $doc])* $variant, )+
+        }
+
+        impl Rule {
+            pub const ALL: &'static [Rule] = &[ $(Rule::$variant,)+ ];
+
+            /// The stable ID, as used in config files, suppression comments and `Diagnostic::code`.
+            pub fn id(self) -> &'static str {
+                match self {
+                    $(Rule::$variant => $id,)+
+                }
+            }
+
+            /// The level this rule has unless configured otherwise.
+            pub fn default_level(self) -> LintLevel {
+                match self {
+                    $(Rule::$variant => LintLevel::$level,)+
+                }
+            }
+
+            /// One-line explanation of what this rule catches.
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(Rule::$variant => concat!($($doc),*).trim_ascii(),)+
+                }
+            }
+        }
+
+        impl FromStr for Rule {
+            type Err = UnknownRule;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($id => Ok(Rule::$variant),)+
+                    _ => Err(UnknownRule(s.to_string())),
+                }
+            }
+        }
+    };
+}
+
+rules! {
+    /// The parser couldn't make sense of the text.
+    SyntaxError = "syntax-error", Error;
+
+    /// A knot that is never diverted to.
+    UnusedKnot = "unused-knot", Warn;
+    /// A stitch that is never diverted to.
+    UnusedStitch = "unused-stitch", Warn;
+    /// A function that is never called.
+    UnusedFunction = "unused-function", Warn;
+    /// An EXTERNAL function that is never called.
+    UnusedExternal = "unused-external", Warn;
+    /// A label that is never referenced.
+    UnusedLabel = "unused-label", Warn;
+    /// A temporary variable that is never read.
+    UnusedTemp = "unused-temp", Warn;
+    /// A parameter that is never used.
+    UnusedParam = "unused-param", Warn;
+    /// A VAR that is never used.
+    UnusedVar = "unused-var", Warn;
+    /// A CONST that is never used.
+    UnusedConst = "unused-const", Warn;
+    /// A LIST that is never used.
+    UnusedList = "unused-list", Warn;
+    /// A LIST item that is never used.
+    UnusedListItem = "unused-list-item", Warn;
+
+    /// A divert, tunnel or thread to a location that doesn't exist.
+    UndefinedDivert = "undefined-divert", Error;
+    /// A call to a function that doesn't exist.
+    UndefinedFunction = "undefined-function", Error;
+    /// A name that doesn't refer to anything.
+    UndefinedName = "undefined-name", Error;
+    /// A divert to something that isn't an address.
+    IllegalDivertTarget = "illegal-divert-target", Error;
+    /// A call to something that isn't callable.
+    IllegalCallTarget = "illegal-call-target", Error;
+
+    /// An assignment to a CONST.
+    AssignmentToConst = "assignment-to-const", Error;
+    /// An assignment to a name that was never declared.
+    UndeclaredAssignment = "undeclared-assignment", Error;
+
+    /// A `->->` in a knot or stitch that is never entered as a tunnel.
+    TunnelReturnOutsideTunnel = "tunnel-return-outside-tunnel", Warn;
+    /// A tunnel that can end the story (or flow) with `-> END`/`-> DONE` instead of returning.
+    TunnelEndsStory = "tunnel-ends-story", Warn;
+    /// A thread that ends the whole story with `-> END`, rather than just itself with `-> DONE`.
+    ThreadEndsStory = "thread-ends-story", Warn;
+
+    /// Syntax that ink still accepts, but warns about, like an empty choice (`*`) that is
+    /// taken as a fallback choice.
+    DeprecatedSyntax = "deprecated-syntax", Warn;
+
+    /// A qualified LIST item that isn't part of that LIST.
+    UnknownListItem = "unknown-list-item", Error;
+    /// An unqualified LIST item whose name exists in several LISTs.
+    AmbiguousListItem = "ambiguous-list-item", Error;
+    /// `LIST_VALUE`/`LIST_RANGE` called with something that can't be a list.
+    ListFunctionArgument = "list-function-argument", Error;
+
+    /// The same global name is defined more than once.
+    DuplicateDefinition = "duplicate-definition", Error;
+    /// A VAR shares its name with another definition.
+    VarClash = "var-clash", Error;
+    /// A file is included more than once (or includes itself).
+    DuplicateImport = "duplicate-import", Error;
+    /// An INCLUDE that doesn't point to a known file.
+    UnresolvedImport = "unresolved-import", Error;
+
+    /// A `#tag` that doesn't fit the project's tag schema.
+    UnknownTag = "unknown-tag", Warn;
+
+    /// A suppression comment or config entry that names a rule we don't know.
+    UnknownRule = "unknown-rule", Warn;
+    /// Something ink-tool doesn't know how to check, which is a bug in ink-tool.
+    InternalError = "internal-error", Warn;
+}
+
+impl Rule {
+    /// For use in [`Diagnostic::code`](lsp_types::Diagnostic::code).
+    pub fn code(self) -> NumberOrString {
+        NumberOrString::String(self.id().to_string())
+    }
+
+    /// The rule a diagnostic was produced by, if any.
+    pub fn of(diagnostic: &lsp_types::Diagnostic) -> Option<Rule> {
+        match diagnostic.code.as_ref()? {
+            NumberOrString::String(id) => id.parse().ok(),
+            NumberOrString::Number(_) => None,
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = UnknownRule;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display, Error)]
+#[display("unknown rule `{_0}`")]
+pub struct UnknownRule(#[error(not(source))] pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Allow,
+    Hint,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LintLevel {
+    /// `None` means: don't report at all.
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            LintLevel::Allow => None,
+            LintLevel::Hint => Some(DiagnosticSeverity::HINT),
+            LintLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+            LintLevel::Warn => Some(DiagnosticSeverity::WARNING),
+            LintLevel::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+/// The `[lints]` section of `ink-tool.toml`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct LintConfig {
+    levels: BTreeMap<Rule, LintLevel>,
+}
+
+impl LintConfig {
+    pub fn level(&self, rule: Rule) -> LintLevel {
+        self.levels
+            .get(&rule)
+            .copied()
+            .unwrap_or_else(|| rule.default_level())
+    }
+
+    pub fn set_level(&mut self, rule: Rule, level: LintLevel) {
+        self.levels.insert(rule, level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_ids_round_trip() {
+        for rule in Rule::ALL {
+            assert_eq!(rule.id().parse::<Rule>(), Ok(*rule));
+        }
+    }
+
+    #[test]
+    fn config_overrides_default_levels() {
+        let config: LintConfig = toml::from_str(
+            r#"
+            unused-knot = "allow"
+            unused-temp = "error"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.level(Rule::UnusedKnot), LintLevel::Allow);
+        assert_eq!(config.level(Rule::UnusedTemp), LintLevel::Error);
+        assert_eq!(config.level(Rule::UnusedStitch), LintLevel::Warn);
+    }
+
+    #[test]
+    fn config_rejects_unknown_rules() {
+        let config = toml::from_str::<LintConfig>(r#"unused-nothing = "allow""#);
+        assert!(config.is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Going between file paths and the `file://` URIs that documents are known by.
+
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr as _,
+};
+
+use lsp_types::Uri;
+
+use crate::AppResult;
+
+/// The `file://` URI of the absolute `path`, percent-encoded the way editors send them.
+pub fn path_to_uri(path: &Path) -> AppResult<Uri> {
+    let path = path.to_str().ok_or("path wasn't a proper UTF-8 string")?;
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            write!(uri, "%{byte:02X}")?;
+        }
+    }
+    Ok(Uri::from_str(&uri)?)
+}
+
+/// The file path of a `file://` URI, with its percent-encoding decoded.
+pub fn uri_to_path(uri: &Uri) -> PathBuf {
+    let path = uri.path().as_estr().decode().into_string_lossy();
+    PathBuf::from(path.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{path_to_uri, uri_to_path};
+
+    #[test]
+    fn paths_survive_the_round_trip() {
+        for path in [
+            "/story/main.ink",
+            "/my story/chapter 1.ink",
+            "/histoires/été.ink",
+        ] {
+            let uri = path_to_uri(Path::new(path)).unwrap();
+            assert_eq!(uri_to_path(&uri), Path::new(path), "{}", uri.as_str());
+        }
+    }
+
+    #[test]
+    fn uris_from_editors_are_decoded() {
+        let uri = "file:///my%20story/%C3%A9t%C3%A9.ink".parse().unwrap();
+        assert_eq!(uri_to_path(&uri), Path::new("/my story/été.ink"));
+    }
+}
@@ -1,10 +1,15 @@
 use super::state::DocumentNotFound;
 use super::state::GotoLocationError;
+use super::uri_to_path;
 use super::RequestHandler;
 use super::SharedState;
+use crate::config::Config;
+use crate::fmt::{FormatConfig, IndentStyle, Rules};
+use crate::AppResult;
 use lsp_server::ResponseError;
 use lsp_types::*;
 use std::error::Error;
+use std::path::Path;
 
 impl From<DocumentNotFound> for ResponseError {
     fn from(value: DocumentNotFound) -> Self {
@@ -107,3 +112,55 @@ impl RequestHandler for request::Rename {
         Ok(edits)
     }
 }
+
+impl RequestHandler for request::CodeActionRequest {
+    fn execute(params: Self::Params, state: &SharedState) -> Response<Self::Result> {
+        let actions = state
+            .lock()?
+            .code_actions(&params.text_document.uri, params.range)?;
+        let response = match actions.len() {
+            0 => None,
+            _ => Some(
+                actions
+                    .into_iter()
+                    .map(CodeActionOrCommand::CodeAction)
+                    .collect(),
+            ),
+        };
+        Ok(response)
+    }
+}
+
+impl RequestHandler for request::Formatting {
+    fn execute(params: Self::Params, state: &SharedState) -> Response<Self::Result> {
+        let uri = params.text_document.uri;
+        let (config, rules) =
+            fmt_settings(&uri_to_path(&uri), &params.options).map_err(|err| ResponseError {
+                code: lsp_server::ErrorCode::RequestFailed as i32,
+                message: err.to_string(),
+                data: None,
+            })?;
+        let edits = state.lock()?.formatting(&uri, &config, &rules)?;
+        Ok(Some(edits))
+    }
+}
+
+/// The formatter settings for the file at `path`, the same ones `ink-tool fmt` would use.
+/// Only if there's no config file do the editor's `options` get a say, and then only whether
+/// to indent with spaces: ink-fmt's indentation is always 4 columns wide.
+fn fmt_settings(path: &Path, options: &FormattingOptions) -> AppResult<(FormatConfig, Rules)> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let config = Config::find_fmt(dir)?.unwrap_or_else(|| FormatConfig {
+        indent_style: if options.insert_spaces {
+            IndentStyle::Spaces
+        } else {
+            IndentStyle::Tabs
+        },
+        ..FormatConfig::default()
+    });
+    let rules = match &config.rules {
+        Some(path) => Rules::read(path)?,
+        None => Rules::default(),
+    };
+    Ok((config, rules))
+}
@@ -7,16 +7,22 @@ use crate::lsp::salsa::subqueries::ink_inventory::{IMap, ISet};
 pub use crate::lsp::{
     ink_visitors::{
         doc_symbols::document_symbols as get_document_symbols,
+        stats::{file_stats as get_file_stats, FileStats, SectionKind, SectionStats, Stats},
+        tags::{tags as get_tags, Tag},
         ws_symbols::from_doc as get_workspace_symbols,
     },
     location::TextRange,
     salsa::subqueries::{
         diagnostics::{DuplicateDefinitions, DuplicateImports, FileDiagnostics},
+        flow_exits::FlowExits,
+        flow_graph::{FileFlow, FlowLink, FlowTarget, LinkKind, TargetKind},
         ink_inventory::{InkInventory, Name, NameMap},
         local_resolutions::LocalResolutions,
         story_structure::StoryRoots,
+        suppressions::Suppressions,
     },
 };
+use crate::lsp::{lints::LintConfig, tag_schema::TagSchema};
 use bimap::BiHashMap;
 use composition::composite_query;
 use derive_more::derive::{AsRef, Deref, Into};
@@ -130,10 +136,20 @@ composite_query!({
 
         fn doc_ids() -> DocIds;
         fn opened_docs() -> HashSet<DocId>;
+        /// The `[lints]` section of the project config
+        pub fn lint_config() -> LintConfig;
+        /// The `[tags]` section of the project config
+        pub fn tag_schema() -> TagSchema;
 
         // === Leaf Queries ===
         fn document_symbols(id: DocId) -> Vec<DocumentSymbol>;
         fn workspace_symbols(id: DocId) -> Vec<WorkspaceSymbol>;
+        /// The `#tags` in this file
+        pub fn file_tags(docid: DocId) -> Vec<Tag>;
+        /// The (non-dynamic) tags used in the workspace that fit the tag schema, sorted
+        pub fn valid_tags() -> Vec<String>;
+        /// Word counts and such of this file and its knots and stitches
+        pub fn file_stats(docid: DocId) -> FileStats;
 
         // === Intermediate Queries ===
 
@@ -156,6 +172,10 @@ composite_query!({
         fn node_locations(docid: DocId) -> NodeLocations;
         fn node_text(docid: DocId) -> NodeText;
         fn node_flags(docid: DocId) -> NodeFlags;
+        /// `-> END`, `-> DONE` and `->->` in each knot and stitch of this file
+        pub fn flow_exits(docid: DocId) -> FlowExits;
+        /// Knots, stitches and labelled gathers in this file, and the redirects to them
+        pub fn flow_graph(docid: DocId) -> FileFlow;
         /// Which files contain mention of specific names (global or local)
         fn names_mentioned(story: StoryRoot) -> IMap<Name, Vec<DocId>>;
 
@@ -180,6 +200,8 @@ composite_query!({
         /// VARs are even more annoying: They clash with locals as well!
         pub fn var_clash(story: StoryRoot) -> DuplicateDefinitions;
         pub fn duplicate_imports(story: StoryRoot) -> DuplicateImports;
+        /// Rules silenced by comments in this file.
+        pub fn suppressions(docid: DocId) -> Suppressions;
         pub fn file_diagnostics(docid: DocId) -> FileDiagnostics;
     }
 });
@@ -188,6 +210,8 @@ composite_query!({
 subquery!(Ops, document, InkDocument);
 subquery!(Ops, doc_ids, DocIds);
 subquery!(Ops, opened_docs, HashSet<DocId>);
+subquery!(Ops, lint_config, LintConfig);
+subquery!(Ops, tag_schema, TagSchema);
 
 subquery!(Ops, common_path_prefix, String, |self, db| {
     db.doc_ids()
@@ -346,6 +370,30 @@ subquery!(Ops, document_symbols, Vec<DocumentSymbol>, |self, db| {
     get_document_symbols(&db.document(self.id))
 });
 
+subquery!(Ops, file_tags, Vec<Tag>, |self, db| {
+    get_tags(&db.document(self.docid))
+});
+
+subquery!(Ops, valid_tags, Vec<String>, |self, db| {
+    let schema = db.tag_schema();
+    db.doc_ids()
+        .iter()
+        .flat_map(|docid| {
+            db.file_tags(*docid)
+                .iter()
+                .filter(|it| !it.dynamic && schema.check(&it.text).is_ok())
+                .map(|it| it.text.clone())
+                .collect_vec()
+        })
+        .sorted()
+        .dedup()
+        .collect()
+});
+
+subquery!(Ops, file_stats, FileStats, |self, db| {
+    get_file_stats(&db.document(self.docid))
+});
+
 pub trait InkSetters: Db<Ops> {
     fn modify_opened<C: HasChanged>(&mut self, f: impl FnOnce(&mut HashSet<DocId>) -> C) -> bool {
         self.modify(opened_docs {}, f)
@@ -355,6 +403,14 @@ pub trait InkSetters: Db<Ops> {
         self.modify(doc_ids {}, f)
     }
 
+    fn modify_lint_config<C: HasChanged>(&mut self, f: impl FnOnce(&mut LintConfig) -> C) -> bool {
+        self.modify(lint_config {}, f)
+    }
+
+    fn modify_tag_schema<C: HasChanged>(&mut self, f: impl FnOnce(&mut TagSchema) -> C) -> bool {
+        self.modify(tag_schema {}, f)
+    }
+
     fn modify_document<C: HasChanged>(
         &mut self,
         id: DocId,
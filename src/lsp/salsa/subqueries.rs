@@ -1,6 +1,9 @@
 pub mod diagnostics;
+pub mod flow_exits;
+pub mod flow_graph;
 pub mod globals;
 pub mod ink_inventory;
 pub mod local_resolutions;
 pub mod node_flags;
 pub mod story_structure;
+pub mod suppressions;
@@ -1,22 +1,31 @@
 use std::{collections::HashSet, iter};
 
 use enumflags2::BitFlags;
-use ink_document::{ids::DefId, InkDocument};
+use ink_document::{
+    ids::{DefId, NodeId, UsageId},
+    InkDocument,
+};
+use itertools::Itertools as _;
 use lsp_types::{Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location};
 use mini_milc::{subquery, Db, Old, Subquery, Updated};
+use tree_traversal::TreeTraversal as _;
+use type_sitter::{Node as _, UntypedNode};
 use util::nonempty::Vec1;
 
 use crate::lsp::{
     ink_visitors::parse_errors::parse_errors,
+    lints::Rule,
     location::FileTextRange,
     salsa::{
         duplicate_globals, duplicate_imports, file_diagnostics,
         subqueries::{
+            flow_exits::Ending,
             ink_inventory::{IMap, NameSet},
             node_flags::{match_flags, NodeFlag},
         },
-        var_clash, DocId, InkGetters as _, Name, NodeFlags, Ops,
+        var_clash, Def, DocId, InkGetters as _, Name, NodeFlags, Ops,
     },
+    tag_schema::TagMismatch,
 };
 
 pub type FileDiagnostics = Vec<Diagnostic>;
@@ -29,12 +38,82 @@ subquery!(Ops, file_diagnostics, FileDiagnostics, |self, db| {
     let mut errors = parse_errors(&doc);
     add_unused(&mut errors, db, &doc, self.docid, &flags);
     add_illegal_targets(&mut errors, db, self.docid, &flags);
+    add_illegal_assignments(&mut errors, db, self.docid, &flags);
+    add_flow_errors(&mut errors, db, self.docid);
+    add_list_errors(&mut errors, db, &doc, self.docid, &flags);
+    add_deprecated_syntax(&mut errors, &doc);
     add_duplicate_definitions(&mut errors, db, self.docid);
     add_duplicate_imports(&mut errors, db, self.docid);
     add_unresolved_imports(&mut errors, db, self.docid);
+    add_unknown_tags(&mut errors, db, self.docid);
+    add_unknown_rules(&mut errors, db, self.docid);
+    apply_lint_levels(&mut errors, db, self.docid);
     errors
 });
 
+/// Set each diagnostic's severity from the project config, dropping the ones that are
+/// `allow`ed (there or by a suppression comment).
+///
+/// Errors can't be suppressed by comments; they have to be allowed in the config.
+fn apply_lint_levels(diags: &mut FileDiagnostics, db: &impl Db<Ops>, docid: DocId) {
+    let config = db.lint_config();
+    let suppressions = db.suppressions(docid);
+    diags.retain_mut(|diag| {
+        let Some(rule) = Rule::of(diag) else {
+            return true;
+        };
+        let Some(severity) = config.level(rule).severity() else {
+            return false;
+        };
+        diag.severity = Some(severity);
+        severity == DiagnosticSeverity::ERROR
+            || !suppressions.suppresses(rule, diag.range.start.line)
+    });
+}
+
+fn add_unknown_tags(diags: &mut FileDiagnostics, db: &impl Db<Ops>, docid: DocId) {
+    let schema = db.tag_schema();
+    if schema.is_empty() {
+        return;
+    }
+
+    for tag in db.file_tags(docid).iter().filter(|it| !it.dynamic) {
+        let text = &tag.text;
+        let message = match schema.check(text) {
+            Ok(()) => continue,
+            Err(TagMismatch::Unknown) => format!("Unknown tag `#{text}`"),
+            Err(TagMismatch::Shape { expected }) => {
+                format!("Tag `#{text}` should look like `#{}`", expected.as_str())
+            }
+            Err(TagMismatch::Value {
+                pattern,
+                placeholder,
+                value,
+            }) => format!(
+                "`{value}` is not a known <{placeholder}> (in tag `#{}`)",
+                pattern.as_str()
+            ),
+        };
+        diags.push(Diagnostic {
+            range: tag.range.into(),
+            code: Some(Rule::UnknownTag.code()),
+            message,
+            ..Default::default()
+        });
+    }
+}
+
+fn add_unknown_rules(diags: &mut FileDiagnostics, db: &impl Db<Ops>, docid: DocId) {
+    for (range, name) in db.suppressions(docid).unknown.iter() {
+        diags.push(Diagnostic {
+            range: (*range).into(),
+            code: Some(Rule::UnknownRule.code()),
+            message: format!("Unknown rule `{name}`"),
+            ..Default::default()
+        });
+    }
+}
+
 impl Subquery<Ops, DuplicateDefinitions> for duplicate_globals {
     fn value(
         &self,
@@ -155,28 +234,31 @@ fn add_unused(
     for (defid, flags) in defs {
         if db.usages(docid, defid).len() <= 1 {
             use NodeFlag::*;
-            let kind = match_flags!(match (flags) {
+            let (kind, rule) = match_flags!(match (flags) {
                 // We don't consider external parameters unused, because EXTERNALs have no body anyway.
                 External | Param => continue,
-                External | Function => "external function",
-                Function => "function",
-                Knot => "knot",
-                Stitch => "stitch",
-                Label => "label",
-                Temp => "temporary variable",
-                Param => "parameter",
-                Var => "variable",
-                Const => "constant",
-                List => "list",
-                ListItem => "list item",
-                _ => "unknown kind of definition (This is likely a bug in ink-tool.)",
+                External | Function => ("external function", Rule::UnusedExternal),
+                Function => ("function", Rule::UnusedFunction),
+                Knot => ("knot", Rule::UnusedKnot),
+                Stitch => ("stitch", Rule::UnusedStitch),
+                Label => ("label", Rule::UnusedLabel),
+                Temp => ("temporary variable", Rule::UnusedTemp),
+                Param => ("parameter", Rule::UnusedParam),
+                Var => ("variable", Rule::UnusedVar),
+                Const => ("constant", Rule::UnusedConst),
+                List => ("list", Rule::UnusedList),
+                ListItem => ("list item", Rule::UnusedListItem),
+                _ => (
+                    "unknown kind of definition (This is likely a bug in ink-tool.)",
+                    Rule::InternalError
+                ),
             });
             let locs = db.node_locations(docid);
             let range = locs[defid].into();
             let name = doc.lsp_text(range);
             diags.push(Diagnostic {
                 range,
-                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(rule.code()),
                 message: format!(r#"Unused {kind} "{name}""#),
                 ..Default::default()
             });
@@ -204,14 +286,20 @@ fn add_illegal_targets(
         let text = node_text[usage.as_ref()];
 
         if definition.is_empty() {
-            let kind = match_flags!(match (flags) {
-                Redirect => "location",
-                Call => "function",
-                _ => "name",
+            if flags.contains(Assignment) {
+                continue; // reported by `add_illegal_assignments`
+            }
+            if list_of_qualifier(db, docid, text.as_str()).is_some() {
+                continue; // reported by `add_list_errors`
+            }
+            let (kind, rule) = match_flags!(match (flags) {
+                Redirect => ("location", Rule::UndefinedDivert),
+                Call => ("function", Rule::UndefinedFunction),
+                _ => ("name", Rule::UndefinedName),
             });
             diags.push(Diagnostic {
                 range: locs[usage].into(),
-                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(rule.code()),
                 message: format!(r#"Undefined {kind} "{text}""#),
                 ..Default::default()
             });
@@ -262,7 +350,10 @@ fn add_illegal_targets(
             if illegal_targets.len() != 0 {
                 diags.push(Diagnostic {
                     range: locs[usage].into(),
-                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(match_flags!(match (flags) {
+                        Redirect => Rule::IllegalDivertTarget.code(),
+                        _ => Rule::IllegalCallTarget.code(),
+                    })),
                     message: match_flags!(match (flags) {
                         Redirect => format!(r#"Can not redirect to {text}"#),
                         Call => format!(r#"Can not call {text}"#),
@@ -279,6 +370,442 @@ fn add_illegal_targets(
     }
 }
 
+fn add_illegal_assignments(
+    diags: &mut FileDiagnostics,
+    db: &impl Db<Ops>,
+    docid: DocId,
+    flags: &NodeFlags,
+) {
+    use NodeFlag::*;
+
+    let assignments = flags
+        .iter_flags()
+        .filter(|(_, flags)| flags.contains(Usage | Assignment) && !flags.intersects(Definition));
+
+    let node_text = db.node_text(docid);
+    let locs = db.node_locations(docid);
+
+    for (usage, _) in assignments {
+        let definition = db.definition(docid, usage);
+        let text = node_text[usage.as_ref()];
+
+        if definition.is_empty() {
+            let related_information = nearest_var(db, docid, text.as_str()).map(|(doc, def)| {
+                let locs = db.node_locations(doc);
+                vec![DiagnosticRelatedInformation {
+                    location: Location::new(doc.into(), locs[def].into()),
+                    message: "did you mean this variable?".to_string(),
+                }]
+            });
+            diags.push(Diagnostic {
+                range: locs[usage].into(),
+                code: Some(Rule::UndeclaredAssignment.code()),
+                message: format!(r#"Assignment to undeclared variable "{text}""#),
+                related_information,
+                ..Default::default()
+            });
+        } else {
+            let consts = definition
+                .iter()
+                .copied()
+                .filter(|(def_doc, def_id)| db.node_flags(*def_doc)[def_id].contains(Const))
+                .map(|(def_doc, def_id)| DiagnosticRelatedInformation {
+                    location: Location::new(
+                        def_doc.into(),
+                        db.node_locations(def_doc)[def_id].into(),
+                    ),
+                    message: "declared as CONST here".to_string(),
+                })
+                .collect::<Vec<_>>();
+
+            if !consts.is_empty() {
+                diags.push(Diagnostic {
+                    range: locs[usage].into(),
+                    code: Some(Rule::AssignmentToConst.code()),
+                    message: format!(r#"Can not assign to constant "{text}""#),
+                    related_information: Some(consts),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+/// Tunnels have to come back (`->->`), and threads should only end themselves (`-> DONE`).
+///
+/// We only look at how a knot or stitch is entered *directly*; if a tunnel diverts onwards,
+/// we lose track of it.
+fn add_flow_errors(diags: &mut FileDiagnostics, db: &impl Db<Ops>, docid: DocId) {
+    use NodeFlag::*;
+
+    let node_text = db.node_text(docid);
+    // Every place that diverts, tunnels or threads into `def`
+    let entries = |def: DefId| {
+        db.usages(docid, def)
+            .iter()
+            .copied()
+            .map(|(doc, usage)| (doc, usage, db.node_flags(doc)[usage]))
+            .filter(|(_, _, flags)| flags.contains(Redirect) && !flags.contains(Definition))
+            .collect_vec()
+    };
+    let related = |entries: &[(DocId, UsageId, BitFlags<NodeFlag>)], message: &str| {
+        entries
+            .iter()
+            .map(|(doc, usage, _)| DiagnosticRelatedInformation {
+                location: Location::new((*doc).into(), db.node_locations(*doc)[*usage].into()),
+                message: message.to_string(),
+            })
+            .collect_vec()
+    };
+    let mut reported = Vec::new();
+
+    for section in db.flow_exits(docid).iter() {
+        let name = node_text[section.def.as_ref()];
+        let entered = entries(section.def);
+        let tunnels = entered
+            .iter()
+            .copied()
+            .filter(|it| it.2.contains(Tunnel))
+            .collect_vec();
+        let threads = entered
+            .iter()
+            .copied()
+            .filter(|it| it.2.contains(Thread))
+            .collect_vec();
+
+        // A `->->` in a stitch is fine if the knot is the tunnel.
+        let mut enclosing = entered.clone();
+        enclosing.extend(section.parent.map(&entries).unwrap_or_default());
+        if !enclosing.is_empty() && !enclosing.iter().any(|it| it.2.contains(Tunnel)) {
+            for range in &section.tunnel_returns {
+                diags.push(Diagnostic {
+                    range: (*range).into(),
+                    code: Some(Rule::TunnelReturnOutsideTunnel.code()),
+                    message: format!(
+                        r#"Tunnel return in "{name}", which is never entered as a tunnel"#
+                    ),
+                    related_information: Some(related(&enclosing, "entered without a tunnel here")),
+                    ..Default::default()
+                });
+            }
+        }
+
+        for (range, ending) in &section.endings {
+            let ending_name = ending.as_str();
+            if !tunnels.is_empty() && !reported.contains(&(*range, Rule::TunnelEndsStory)) {
+                reported.push((*range, Rule::TunnelEndsStory));
+                let what = match ending {
+                    Ending::End => "the story",
+                    Ending::Done => "the current flow",
+                };
+                diags.push(Diagnostic {
+                    range: (*range).into(),
+                    code: Some(Rule::TunnelEndsStory.code()),
+                    message: format!(
+                        r#"-> {ending_name} ends {what} instead of returning from tunnel "{name}""#
+                    ),
+                    related_information: Some(related(&tunnels, "entered as a tunnel here")),
+                    ..Default::default()
+                });
+            }
+            if *ending == Ending::End
+                && !threads.is_empty()
+                && !reported.contains(&(*range, Rule::ThreadEndsStory))
+            {
+                reported.push((*range, Rule::ThreadEndsStory));
+                diags.push(Diagnostic {
+                    range: (*range).into(),
+                    code: Some(Rule::ThreadEndsStory.code()),
+                    message: format!(
+                        r#"-> END ends the whole story, not just thread "{name}". Use -> DONE instead."#
+                    ),
+                    related_information: Some(related(&threads, "started as a thread here")),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+}
+
+/// The VAR whose name is closest to `name`, if any is close enough to be a likely typo.
+fn nearest_var(db: &impl Db<Ops>, docid: DocId, name: &str) -> Option<Def> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut nearest: Option<(usize, Def)> = None;
+
+    for story in db.stories_of(docid).iter().copied() {
+        let globals = db.globals(story);
+        for (var_name, defs) in globals.iter() {
+            let distance = edit_distance(name, var_name.as_str());
+            if distance > max_distance || nearest.is_some_and(|(best, _)| best <= distance) {
+                continue;
+            }
+            let var = defs
+                .iter()
+                .copied()
+                .find(|(doc, def)| db.node_flags(*doc)[def].contains(NodeFlag::Var));
+            if let Some(var) = var {
+                nearest = Some((distance, var));
+            }
+        }
+    }
+
+    nearest.map(|(_, var)| var)
+}
+
+/// Levenshtein distance, counted in `char`s.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn add_list_errors(
+    diags: &mut FileDiagnostics,
+    db: &impl Db<Ops>,
+    doc: &InkDocument,
+    docid: DocId,
+    flags: &NodeFlags,
+) {
+    use NodeFlag::*;
+
+    let usages = flags
+        .iter_flags()
+        .filter(|(_, flags)| flags.intersects(Usage) && !flags.intersects(Definition | Builtin));
+
+    let node_text = db.node_text(docid);
+    let locs = db.node_locations(docid);
+
+    for (usage, _) in usages {
+        let text = node_text[usage.as_ref()].as_str();
+        let definition = db.definition(docid, usage);
+
+        if definition.is_empty() {
+            // `Colours.purple`, where `Colours` is a list, but has no `purple`.
+            let Some((list_doc, list_def)) = list_of_qualifier(db, docid, text) else {
+                continue;
+            };
+            let (list_name, item) = text.rsplit_once('.').expect("qualified name");
+            diags.push(Diagnostic {
+                range: locs[usage].into(),
+                code: Some(Rule::UnknownListItem.code()),
+                message: format!("`{item}` is not an item of LIST `{list_name}`"),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(
+                        list_doc.into(),
+                        db.node_locations(list_doc)[list_def].into(),
+                    ),
+                    message: format!("LIST `{list_name}` defined here"),
+                }]),
+                ..Default::default()
+            });
+        } else if !text.contains('.') {
+            // `bar`, where both `foo.bar` and `pass.bar` exist.
+            let mut lists: Vec<(Name, Def)> = Vec::new();
+            for (def_doc, def_id) in definition.iter().copied() {
+                if !db.node_flags(def_doc)[def_id].contains(ListItem) {
+                    continue;
+                }
+                let inventory = db.ink_inventory(def_doc);
+                let list = inventory.lists.iter().find(|list| {
+                    list.items
+                        .values()
+                        .any(|defs| defs.iter().any(|it| *it == def_id))
+                });
+                if let Some(list) = list {
+                    if !lists.iter().any(|(name, _)| *name == list.name) {
+                        lists.push((list.name, (def_doc, def_id)));
+                    }
+                }
+            }
+
+            if lists.len() > 1 {
+                let qualified = lists
+                    .iter()
+                    .map(|(list, _)| format!("`{list}.{text}`"))
+                    .join(" or ");
+                diags.push(Diagnostic {
+                    range: locs[usage].into(),
+                    code: Some(Rule::AmbiguousListItem.code()),
+                    message: format!("Ambiguous list item `{text}`. Qualify it as {qualified}."),
+                    related_information: Some(
+                        lists
+                            .iter()
+                            .map(
+                                |(list, (item_doc, item_def))| DiagnosticRelatedInformation {
+                                    location: Location::new(
+                                        item_doc.into(),
+                                        db.node_locations(*item_doc)[*item_def].into(),
+                                    ),
+                                    message: format!("item of LIST `{list}`"),
+                                },
+                            )
+                            .collect(),
+                    ),
+                    // For the quick fixes, which qualify the item with one of these.
+                    data: Some(serde_json::json!({
+                        "lists": lists.iter().map(|(list, _)| list.to_string()).collect_vec(),
+                    })),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    add_list_function_errors(diags, db, doc, docid, flags);
+}
+
+/// `LIST_VALUE(…)` and `LIST_RANGE(…)` need a list (value) as their first argument.
+///
+/// Ink is dynamically typed, so we only flag arguments that are *obviously* wrong: literals
+/// and names that refer to things that can never hold a list.
+fn add_list_function_errors(
+    diags: &mut FileDiagnostics,
+    db: &impl Db<Ops>,
+    doc: &InkDocument,
+    docid: DocId,
+    flags: &NodeFlags,
+) {
+    use NodeFlag::*;
+
+    for call in doc.root().depth_first::<ink_syntax::Call>() {
+        let raw = call.raw();
+        let mut cursor = raw.walk();
+        let mut children = raw.named_children(&mut cursor);
+        let Some(function) = children.next() else {
+            continue;
+        };
+        let function = doc.text(function.byte_range());
+        if !matches!(function, "LIST_VALUE" | "LIST_RANGE") {
+            continue;
+        }
+        let Some(args) = children.find(|it| it.kind() == "args") else {
+            continue;
+        };
+        let mut cursor = args.walk();
+        let Some(arg) = args.named_children(&mut cursor).next() else {
+            continue;
+        };
+
+        let range = doc.lsp_range(arg.range());
+        let not_a_list = |kind: &str| Diagnostic {
+            range,
+            code: Some(Rule::ListFunctionArgument.code()),
+            message: format!("{function} expects a list, but this is a {kind}"),
+            ..Default::default()
+        };
+
+        match arg.kind() {
+            "number" => diags.push(not_a_list("number")),
+            "string" => diags.push(not_a_list("string")),
+            "boolean" => diags.push(not_a_list("boolean")),
+            "identifier" | "qualified_name" => {
+                // For qualified names, the last identifier stands for the whole name.
+                let ident = if arg.kind() == "qualified_name" {
+                    let mut cursor = arg.walk();
+                    let last = arg.named_children(&mut cursor).last();
+                    match last {
+                        Some(it) => it,
+                        None => continue,
+                    }
+                } else {
+                    arg
+                };
+                let id = NodeId::new(UntypedNode::new(ident));
+                if !flags.get(&id).is_some_and(|it| it.contains(Usage)) {
+                    continue;
+                }
+                let usage = UsageId::pinkie_promise_from_node_id(id);
+                let definition = db.definition(docid, usage);
+                let mut kinds = Vec::new();
+                let related = definition
+                    .iter()
+                    .copied()
+                    .filter_map(|(def_doc, def_id)| {
+                        let def_flags = db.node_flags(def_doc)[def_id];
+                        if !def_flags.intersects(Knot | Stitch | Label | Function) {
+                            return None;
+                        }
+                        let kind = flag_to_kind(def_flags).unwrap_or("thing");
+                        if !kinds.contains(&kind) {
+                            kinds.push(kind);
+                        }
+                        Some(DiagnosticRelatedInformation {
+                            location: Location::new(
+                                def_doc.into(),
+                                db.node_locations(def_doc)[def_id].into(),
+                            ),
+                            message: format!("a {kind} is not a list"),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                // Only complain if *every* candidate is wrong; the name might still hold a list otherwise.
+                if !related.is_empty() && related.len() == definition.len() {
+                    let name = doc.text(arg.byte_range());
+                    diags.push(Diagnostic {
+                        range,
+                        code: Some(Rule::ListFunctionArgument.code()),
+                        message: format!("`{name}` is a {}, not a list", kinds.join(" or ")),
+                        related_information: Some(related),
+                        ..Default::default()
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Choices with nothing but their marks, which ink takes as fallback choices (with a
+/// warning). `* ->` is the way to write those. The replacement goes into the diagnostic's
+/// data, for the quick fix.
+fn add_deprecated_syntax(diags: &mut FileDiagnostics, doc: &InkDocument) {
+    let text = doc.full_text();
+    for choice in doc.root().depth_first::<ink_syntax::Choice>() {
+        let Ok(marks) = choice.marks() else {
+            continue;
+        };
+        let rest = text[marks.end_byte()..].lines().next().unwrap_or_default();
+        if !rest.trim().is_empty() {
+            continue;
+        }
+        let replacement = format!("{} ->", doc.text(marks.byte_range()));
+        diags.push(Diagnostic {
+            range: doc.lsp_range(marks.range()),
+            code: Some(Rule::DeprecatedSyntax.code()),
+            message: format!("An empty choice is a fallback choice. Say so with `{replacement}`"),
+            data: Some(serde_json::json!({ "replacement": replacement })),
+            ..Default::default()
+        });
+    }
+}
+
+/// If `name` looks like `List.item` and `List` is a LIST, return the LIST's definition.
+fn list_of_qualifier(db: &impl Db<Ops>, docid: DocId, name: &str) -> Option<Def> {
+    let (qualifier, _) = name.rsplit_once('.')?;
+    let qualifier = Name::from(qualifier);
+    for story in db.stories_of(docid).iter().copied() {
+        let globals = db.globals(story);
+        let list = globals.get(&qualifier).and_then(|defs| {
+            defs.iter()
+                .copied()
+                .find(|(doc, def)| db.node_flags(*doc)[def].contains(NodeFlag::List))
+        });
+        if list.is_some() {
+            return list;
+        }
+    }
+    None
+}
+
 fn add_duplicate_definitions(diags: &mut FileDiagnostics, db: &impl Db<Ops>, docid: DocId) {
     use NodeFlag::*;
 
@@ -295,16 +822,22 @@ fn add_duplicate_definitions(diags: &mut FileDiagnostics, db: &impl Db<Ops>, doc
 
         let duplicate_globals = db.duplicate_globals(story);
         let var_clash = db.var_clash(story);
-        let duplicates = duplicate_globals.iter().chain(var_clash.iter());
-
-        for (name, dups) in duplicates {
+        let duplicates = std::iter::empty()
+            .chain(
+                duplicate_globals
+                    .iter()
+                    .map(|it| (it, Rule::DuplicateDefinition)),
+            )
+            .chain(var_clash.iter().map(|it| (it, Rule::VarClash)));
+
+        for ((name, dups), rule) in duplicates {
             for (this_file, this_def, this_flags) in
                 dups.iter().filter(|(file, _, _)| *file == docid)
             {
                 let locs = db.node_locations(*this_file);
                 diags.push(Diagnostic {
                     range: locs[*this_def].into(),
-                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(rule.code()),
                     message: format!("Multiple definitions of `{name}`{story_suffix}."),
                     related_information: Some(
                         dups.iter()
@@ -365,7 +898,7 @@ fn add_unresolved_imports(diags: &mut FileDiagnostics, db: &impl Db<Ops>, docid:
                 diags.push(Diagnostic {
                     range: range.into(),
                     message: format!("Import not found relative to story root {story_path}"),
-                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(Rule::UnresolvedImport.code()),
                     related_information: Some(vec![DiagnosticRelatedInformation {
                         location: Location {
                             uri: story.into(),
@@ -403,7 +936,7 @@ fn add_duplicate_imports(diags: &mut FileDiagnostics, db: &impl Db<Ops>, this_do
                 diags.push(Diagnostic {
                     range: import.range.into(),
                     message: format!("Duplicate or cyclic import{story_suffix}"),
-                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(Rule::DuplicateImport.code()),
                     related_information: Some(
                         iter::once(DiagnosticRelatedInformation {
                             location: Location::new(story.into(), lsp_types::Range::default()),
@@ -415,6 +948,8 @@ fn add_duplicate_imports(diags: &mut FileDiagnostics, db: &impl Db<Ops>, this_do
                         }))
                         .collect(),
                     ),
+                    // The first import is the one the quick fix keeps.
+                    data: Some(serde_json::json!({ "first": import == *dups.first() })),
                     ..Default::default()
                 });
             }
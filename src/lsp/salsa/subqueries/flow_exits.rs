@@ -0,0 +1,167 @@
+use std::hint::unreachable_unchecked;
+
+use ink_document::{
+    ids::{DefId, UsageId},
+    InkDocument,
+};
+use mini_milc::subquery;
+use tree_traversal::{VisitInstruction, Visitor};
+use type_sitter::Node as _;
+
+use crate::lsp::{
+    location::TextRange,
+    salsa::{flow_exits, InkGetters as _, NodeFlag, NodeFlags, Ops},
+};
+
+/// The ways flow can leave the knots and stitches of a file, other than by falling off
+/// their end or diverting somewhere else.
+pub type FlowExits = Vec<SectionExits>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionExits {
+    /// The name of the knot or stitch
+    pub def: DefId,
+    /// The knot a stitch belongs to
+    pub parent: Option<DefId>,
+    /// `-> END` and `-> DONE`. A knot also gets the ones of its stitches.
+    pub endings: Vec<(TextRange, Ending)>,
+    /// `->->`
+    pub tunnel_returns: Vec<TextRange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    End,
+    Done,
+}
+
+impl Ending {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Ending::End => "END",
+            Ending::Done => "DONE",
+        }
+    }
+}
+
+subquery!(Ops, flow_exits, FlowExits, |self, db| {
+    let doc = db.document(self.docid);
+    let flags = db.node_flags(self.docid);
+    Vstr::new(&doc, &flags).traverse(doc.root())
+});
+
+struct Vstr<'a> {
+    doc: &'a InkDocument,
+    flags: &'a NodeFlags,
+    /// Index of the current knot’s section
+    knot: Option<usize>,
+    /// Index of the current stitch’s section
+    stitch: Option<usize>,
+}
+
+impl<'a> Vstr<'a> {
+    fn new(doc: &'a InkDocument, flags: &'a NodeFlags) -> Self {
+        Self {
+            doc,
+            flags,
+            knot: None,
+            stitch: None,
+        }
+    }
+
+    fn range(&self, node: impl type_sitter::Node<'a>) -> TextRange {
+        self.doc.lsp_range(node.range()).into()
+    }
+}
+
+impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
+    type State = FlowExits;
+
+    fn visit(
+        &mut self,
+        node: ink_syntax::AllNamed<'a>,
+        state: &mut Self::State,
+    ) -> VisitInstruction<Self::State> {
+        use ink_syntax::AllNamed::*;
+        use VisitInstruction::{Descend, Ignore};
+
+        match node {
+            Knot(knot) => {
+                state.push(SectionExits {
+                    def: DefId::from(knot),
+                    parent: None,
+                    endings: Vec::new(),
+                    tunnel_returns: Vec::new(),
+                });
+                self.knot = Some(state.len() - 1);
+                Ignore
+            }
+            Stitch(stitch) => {
+                state.push(SectionExits {
+                    def: DefId::from(stitch),
+                    parent: self.knot.map(|it| state[it].def),
+                    endings: Vec::new(),
+                    tunnel_returns: Vec::new(),
+                });
+                self.stitch = Some(state.len() - 1);
+                Ignore
+            }
+
+            Divert(_) | Tunnel(_) if self.doc.node_text(node).trim_start().starts_with("->->") => {
+                let text = self.doc.node_text(node);
+                let start = node.start_byte() + (text.len() - text.trim_start().len());
+                let range = self
+                    .doc
+                    .lsp_range_from_bytes(start, start + "->->".len())
+                    .into();
+                if let Some(section) = self.stitch.or(self.knot) {
+                    // Nested redirect nodes may start at the same `->->`.
+                    let returns = &mut state[section].tunnel_returns;
+                    if returns.last() != Some(&range) {
+                        returns.push(range);
+                    }
+                }
+                Descend
+            }
+
+            Identifier(identifier) | Expr(ink_syntax::Expr::Identifier(identifier)) => {
+                let usgid = UsageId::from(identifier);
+                let is_builtin_address = self
+                    .flags
+                    .get(usgid.as_ref())
+                    .is_some_and(|it| it.contains(NodeFlag::Redirect | NodeFlag::Builtin));
+                let ending = match self.doc.node_text(identifier) {
+                    "END" => Ending::End,
+                    "DONE" => Ending::Done,
+                    _ => return Ignore,
+                };
+                if is_builtin_address {
+                    let range = self.range(identifier);
+                    for section in [self.stitch, self.knot].into_iter().flatten() {
+                        state[section].endings.push((range, ending));
+                    }
+                }
+                Ignore
+            }
+
+            _ => Descend,
+        }
+    }
+
+    fn leave(&mut self, node: ink_syntax::AllNamed<'a>, _: &mut Self::State) {
+        use ink_syntax::AllNamed::*;
+        match node {
+            KnotBlock(_) => {
+                self.knot = None;
+                self.stitch = None;
+            }
+            StitchBlock(_) => self.stitch = None,
+            _ => {}
+        }
+    }
+
+    fn combine(_: &mut Self::State, _: Self::State) {
+        // SAFETY: We never DescendWith, therefore we never combine.
+        unsafe { unreachable_unchecked() }
+    }
+}
@@ -0,0 +1,275 @@
+use std::hint::unreachable_unchecked;
+
+use ink_document::{
+    ids::{DefId, UsageId},
+    InkDocument,
+};
+use itertools::Itertools as _;
+use mini_milc::subquery;
+use tree_traversal::{VisitInstruction, Visitor};
+use type_sitter::Node as _;
+
+use crate::lsp::{
+    location::TextRange,
+    salsa::{flow_graph, InkGetters as _, Name, NodeFlag, NodeFlags, Ops},
+};
+
+/// The places in a file that flow can be sent to, and the redirects that send it there.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileFlow {
+    pub targets: Vec<FlowTarget>,
+    pub links: Vec<FlowLink>,
+}
+
+/// A knot, stitch or labelled gather.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowTarget {
+    /// The name (of the label, for gathers)
+    pub def: DefId,
+    pub kind: TargetKind,
+    /// The full address, like `knot.stitch.label`
+    pub path: Name,
+    pub range: TextRange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Knot,
+    Stitch,
+    Gather,
+}
+
+impl TargetKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TargetKind::Knot => "knot",
+            TargetKind::Stitch => "stitch",
+            TargetKind::Gather => "gather",
+        }
+    }
+}
+
+/// A divert, tunnel or thread, from wherever it is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowLink {
+    /// The innermost target that the redirect is in. `None` for the top of the file.
+    pub from: Option<DefId>,
+    /// The (last part of the) name being redirected to
+    pub to: UsageId,
+    pub kind: LinkKind,
+    /// The text of the choice the redirect is under, if any
+    pub choice: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Divert,
+    Tunnel,
+    Thread,
+    /// A divert that only happens if the player picks a choice
+    Choice,
+}
+
+impl LinkKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Divert => "divert",
+            LinkKind::Tunnel => "tunnel",
+            LinkKind::Thread => "thread",
+            LinkKind::Choice => "choice",
+        }
+    }
+}
+
+subquery!(Ops, flow_graph, FileFlow, |self, db| {
+    let doc = db.document(self.docid);
+    let flags = db.node_flags(self.docid);
+    Vstr::new(&doc, &flags).traverse(doc.root())
+});
+
+/// A target that a block defines, with its name and where that is.
+type Target<'a> = (DefId, TargetKind, &'a str, TextRange);
+
+struct Vstr<'a> {
+    doc: &'a InkDocument,
+    flags: &'a NodeFlags,
+    /// One entry for each knot, stitch and gather block we're in; the target it defines, if any.
+    blocks: Vec<Option<(DefId, TargetKind, &'a str)>>,
+    /// The texts of the choices we're in.
+    choices: Vec<String>,
+    qname: Option<ink_syntax::QualifiedName<'a>>,
+}
+
+impl<'a> Vstr<'a> {
+    fn new(doc: &'a InkDocument, flags: &'a NodeFlags) -> Self {
+        Self {
+            doc,
+            flags,
+            blocks: Vec::new(),
+            choices: Vec::new(),
+            qname: None,
+        }
+    }
+
+    fn target(&self, def: DefId, kind: TargetKind, name: impl type_sitter::Node<'a>) -> Target<'a> {
+        let range = self.doc.lsp_range(name.range()).into();
+        (def, kind, self.doc.node_text(name), range)
+    }
+
+    fn enter(&mut self, state: &mut FileFlow, target: Option<Target<'a>>) {
+        let Some((def, kind, name, range)) = target else {
+            self.blocks.push(None);
+            return;
+        };
+        // Labels are addressed through their knot and stitch, but not through other labels.
+        let path = self
+            .blocks
+            .iter()
+            .flatten()
+            .filter(|(_, kind, _)| *kind != TargetKind::Gather)
+            .map(|(_, _, name)| *name)
+            .chain([name])
+            .join(".");
+        state.targets.push(FlowTarget {
+            def,
+            kind,
+            path: Name::from(path),
+            range,
+        });
+        self.blocks.push(Some((def, kind, name)));
+    }
+
+    fn current(&self) -> Option<DefId> {
+        self.blocks.iter().rev().flatten().next().map(|it| it.0)
+    }
+
+    /// What the player sees of a choice (roughly): no marks, label, diverts or tags.
+    fn choice_text(&self, choice: ink_syntax::Choice<'a>) -> String {
+        let start = match (choice.label(), choice.marks()) {
+            (Some(Ok(label)), _) => label.end_byte(),
+            (_, Ok(marks)) => marks.end_byte(),
+            _ => choice.start_byte(),
+        };
+        let text = self.doc.text(start..choice.end_byte());
+        let text = text.split(['#', '\n']).next().unwrap_or_default();
+        let text = text.split("->").next().unwrap_or_default();
+        text.replace(['[', ']'], "").split_whitespace().join(" ")
+    }
+}
+
+impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
+    type State = FileFlow;
+
+    fn visit(
+        &mut self,
+        node: ink_syntax::AllNamed<'a>,
+        state: &mut Self::State,
+    ) -> VisitInstruction<Self::State> {
+        use ink_syntax::AllNamed::*;
+        use VisitInstruction::{Descend, Ignore};
+
+        match node {
+            KnotBlock(block) => {
+                // Functions aren't places to go to.
+                let target = block
+                    .header()
+                    .ok()
+                    .filter(|it| it.function().is_none())
+                    .map(|it| self.target(DefId::from(it), TargetKind::Knot, it.name()));
+                self.enter(state, target);
+                Descend
+            }
+            StitchBlock(block) => {
+                let target = block
+                    .header()
+                    .ok()
+                    .map(|it| self.target(DefId::from(it), TargetKind::Stitch, it.name()));
+                self.enter(state, target);
+                Descend
+            }
+            GatherBlock(block) => {
+                let target = block
+                    .header()
+                    .ok()
+                    .and_then(|it| it.label()?.ok())
+                    .map(|it| self.target(DefId::from(it), TargetKind::Gather, it.name()));
+                self.enter(state, target);
+                Descend
+            }
+            ChoiceBlock(block) => {
+                let text = match block.header() {
+                    Ok(choice) => self.choice_text(choice),
+                    Err(_) => String::new(),
+                };
+                self.choices.push(text);
+                Descend
+            }
+
+            // Nothing in here can redirect.
+            Knot(_) | Stitch(_) | Params(_) | Global(_) | List(_) | External(_) | Include(_)
+            | Label(_) | Text(_) | LineComment(_) | BlockComment(_) | TodoComment(_) => Ignore,
+
+            QualifiedName(qname) | Expr(ink_syntax::Expr::QualifiedName(qname)) => {
+                self.qname = Some(qname);
+                Descend
+            }
+
+            Identifier(identifier) | Expr(ink_syntax::Expr::Identifier(identifier)) => {
+                // `knot.stitch` is one target, named by its last part.
+                if self
+                    .qname
+                    .is_some_and(|it| it.end_byte() != identifier.end_byte())
+                {
+                    return Ignore;
+                }
+                let usage = UsageId::from(identifier);
+                let Some(flags) = self.flags.get(usage.as_ref()).copied() else {
+                    return Ignore;
+                };
+                if !flags.contains(NodeFlag::Redirect)
+                    || flags.intersects(NodeFlag::Builtin | NodeFlag::Definition)
+                {
+                    return Ignore;
+                }
+                let choice = self.choices.last().cloned();
+                let kind = if flags.contains(NodeFlag::Tunnel) {
+                    LinkKind::Tunnel
+                } else if flags.contains(NodeFlag::Thread) {
+                    LinkKind::Thread
+                } else if choice.is_some() {
+                    LinkKind::Choice
+                } else {
+                    LinkKind::Divert
+                };
+                state.links.push(FlowLink {
+                    from: self.current(),
+                    to: usage,
+                    kind,
+                    choice,
+                });
+                Ignore
+            }
+
+            _ => Descend,
+        }
+    }
+
+    fn leave(&mut self, node: ink_syntax::AllNamed<'a>, _: &mut Self::State) {
+        use ink_syntax::AllNamed::*;
+        match node {
+            KnotBlock(_) | StitchBlock(_) | GatherBlock(_) => {
+                self.blocks.pop();
+            }
+            ChoiceBlock(_) => {
+                self.choices.pop();
+            }
+            QualifiedName(_) | Expr(ink_syntax::Expr::QualifiedName(_)) => self.qname = None,
+            _ => {}
+        }
+    }
+
+    fn combine(_: &mut Self::State, _: Self::State) {
+        // SAFETY: We never DescendWith, therefore we never combine.
+        unsafe { unreachable_unchecked() }
+    }
+}
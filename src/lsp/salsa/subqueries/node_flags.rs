@@ -149,7 +149,13 @@ pub enum NodeFlag {
     /// Usages
     Usage,
     Redirect,
+    /// What kind of redirect (always together with `Redirect`)
+    Divert,
+    Tunnel,
+    Thread,
     Call,
+    /// The usage is written to (`~ x = …`, `~ x++`)
+    Assignment,
     /// Other information
     HasParams,
     Builtin,
@@ -163,7 +169,10 @@ struct Vstr<'a> {
     qname: Option<ink_syntax::QualifiedName<'a>>,
     list: Option<(TextRange, &'a str)>,
     call: bool,
-    redirect: bool,
+    /// `Divert`, `Tunnel` or `Thread`, if we're in one
+    redirect: Option<NodeFlag>,
+    /// is the next name the target of an assignment (`~ name = …`, `~ name++`)
+    assignment: bool,
     /// is the current usage a listvalues query (`list_name ? (item.name)`)
     listvalues: bool,
     external: bool,
@@ -178,7 +187,8 @@ impl<'a> Vstr<'a> {
             qname: None,
             list: Default::default(),
             call: false,
-            redirect: false,
+            redirect: None,
+            assignment: false,
             listvalues: false,
             external: false,
         }
@@ -287,8 +297,16 @@ impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
             }
 
             /*** Usages ***/
-            Divert(_) | Tunnel(_) | Thread(_) => {
-                self.redirect = true;
+            Divert(_) => {
+                self.redirect = Some(NodeFlag::Divert);
+                Descend
+            }
+            Tunnel(_) => {
+                self.redirect = Some(NodeFlag::Tunnel);
+                Descend
+            }
+            Thread(_) => {
+                self.redirect = Some(NodeFlag::Thread);
                 Descend
             }
             Call(_) => {
@@ -298,10 +316,15 @@ impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
 
             Args(_) => {
                 // Arguments don't inherit redirect or call flags.
-                self.redirect = false;
+                self.redirect = None;
                 self.call = false;
                 Descend
             }
+            Assignment(_) | Postfix(_) => {
+                // The first name we encounter is the one being written to.
+                self.assignment = true;
+                Descend
+            }
 
             // XXX: There is a bug(?) somewhere that causes qualified names and identifiers to be wrapped in an expr.
             // Not sure why, but we work around this here:
@@ -317,16 +340,24 @@ impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
                     .unwrap_or_else(|| identifier.byte_range());
                 let usgid = UsageId::from(identifier);
                 let text = self.doc.text(byte_range);
-                let builtin =
-                    (self.redirect && builtin_addr(text)) || (self.call && builtin_func(text));
+                let builtin = (self.redirect.is_some() && builtin_addr(text))
+                    || (self.call && builtin_func(text));
 
                 let mut kind = BitFlags::from(NodeFlag::Usage);
                 kind.set(NodeFlag::Call, self.call);
-                kind.set(NodeFlag::Redirect, self.redirect);
+                if let Some(redirect) = self.redirect {
+                    kind |= NodeFlag::Redirect | redirect;
+                }
                 kind.set(NodeFlag::ListItem, self.listvalues);
                 kind.set(NodeFlag::Builtin, builtin);
+                kind.set(NodeFlag::Assignment, self.assignment);
                 state.add_node_kind(usgid.into(), kind);
 
+                if self.qname.is_none() {
+                    // Only the target is assigned to; everything after is read.
+                    self.assignment = false;
+                }
+
                 Ignore
             }
 
@@ -379,7 +410,6 @@ impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
             /*** Unused ***/
             AltArm(_) => Descend,
             Alternatives(_) => Descend,
-            Assignment(_) => Descend,
             Binary(_) => Descend,
             BlockComment(_) => Ignore,
             Boolean(_) => Ignore,
@@ -412,7 +442,6 @@ impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
             Params(_) => Descend,
             Paren(_) => Descend,
             Path(_) => Ignore,
-            Postfix(_) => Descend,
             Return(_) => Descend,
             String(_) => Descend, // because String interpolation/evaluation
             Tag(_) => Descend,
@@ -432,9 +461,13 @@ impl<'a> Visitor<'a, ink_syntax::AllNamed<'a>> for Vstr<'a> {
             KnotBlock(_) => self.knot = None,
             StitchBlock(_) => self.stitch = None,
             List(_) => self.list = None,
-            QualifiedName(_) | Expr(ink_syntax::Expr::QualifiedName(_)) => self.qname = None,
-            Divert(_) | Tunnel(_) | Thread(_) => self.redirect = false,
+            QualifiedName(_) | Expr(ink_syntax::Expr::QualifiedName(_)) => {
+                self.qname = None;
+                self.assignment = false;
+            }
+            Divert(_) | Tunnel(_) | Thread(_) => self.redirect = None,
             Call(_) => self.call = false,
+            Assignment(_) | Postfix(_) => self.assignment = false,
             ListValues(_) => self.listvalues = false,
             External(_) => self.external = false,
 
@@ -582,6 +615,66 @@ mod tests {
             );
         }
 
+        #[test]
+        fn only_assignment_target_has_assignment_flag() {
+            let text = indoc! {r"
+                ~ health = max_health - damage
+                //|        |            ^^^^^^@
+                //|        ^^^^^^^^^^@
+                //^^^^^^@
+                ~ turns++
+                //^^^^^@
+                ~ temp x = health
+                //         ^^^^^^ health_read
+            "};
+
+            let doc = InkDocument::new(text.to_string(), None);
+            let infos = Vstr::new(&doc).traverse(doc.root());
+            let flags = scan_flags(text, infos);
+
+            softly!(
+                expect!(&flags["health"]).to_contain(Usage | Assignment),
+                expect!(&flags["turns"]).to_contain(Usage | Assignment),
+                expect!(&flags["max_health"]).not().to_contain(Assignment),
+                expect!(&flags["damage"]).not().to_contain(Assignment),
+                expect!(&flags["health_read"]).not().to_contain(Assignment)
+            );
+        }
+
+        #[test]
+        fn redirects_know_their_kind() {
+            let text = indoc! {r"
+                -> knot
+                // ^^^^ divert
+                -> tunnel ->
+                // ^^^^^^ tunnel
+                <- thread
+                // ^^^^^^ thread
+            "};
+
+            let doc = InkDocument::new(text.to_string(), None);
+            let infos = Vstr::new(&doc).traverse(doc.root());
+            let flags = scan_flags(text, infos);
+
+            softly!(
+                expect!(&flags["divert"])
+                    .to_contain(Redirect | Divert)
+                    .and()
+                    .not()
+                    .to_contain(Tunnel | Thread),
+                expect!(&flags["tunnel"])
+                    .to_contain(Redirect | Tunnel)
+                    .and()
+                    .not()
+                    .to_contain(Divert | Thread),
+                expect!(&flags["thread"])
+                    .to_contain(Redirect | Thread)
+                    .and()
+                    .not()
+                    .to_contain(Divert | Tunnel)
+            );
+        }
+
         fn scan_flags<'a>(
             text: &'a str,
             infos: NodeFlags,
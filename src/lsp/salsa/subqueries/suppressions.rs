@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use mini_milc::subquery;
+use tree_traversal::TreeTraversal as _;
+use type_sitter::Node as _;
+
+use crate::lsp::{
+    lints::Rule,
+    location::TextRange,
+    salsa::{suppressions, InkGetters as _, Ops},
+};
+
+/// Rules silenced by `// ink-tool: allow(…)` and `// ink-tool: allow-file(…)` comments.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Suppressions {
+    file: BTreeSet<Rule>,
+    /// Line number → rules allowed on that line
+    lines: BTreeMap<u32, BTreeSet<Rule>>,
+    /// Rule names we didn't recognize, and where they were mentioned.
+    pub unknown: Vec<(TextRange, String)>,
+}
+
+impl Suppressions {
+    pub fn suppresses(&self, rule: Rule, line: u32) -> bool {
+        self.file.contains(&rule) || self.lines.get(&line).is_some_and(|it| it.contains(&rule))
+    }
+}
+
+subquery!(Ops, suppressions, Suppressions, |self, db| {
+    let doc = db.document(self.docid);
+    let mut result = Suppressions::default();
+
+    for comment in doc.root().depth_first::<ink_syntax::LineComment>() {
+        let Some((file_wide, rules)) = parse_directive(doc.node_text(comment)) else {
+            continue;
+        };
+        let range = doc.lsp_range(comment.range());
+        let line_start = doc.to_byte(lsp_types::Position::new(range.start.line, 0));
+        let on_own_line = doc.text(line_start..comment.start_byte()).trim().is_empty();
+        for name in rules {
+            let rule = match name.parse::<Rule>() {
+                Ok(rule) => rule,
+                Err(_) => {
+                    result.unknown.push((range.into(), name.to_string()));
+                    continue;
+                }
+            };
+            if file_wide {
+                result.file.insert(rule);
+            } else {
+                // A trailing comment covers its own line, a comment on its own line covers the next one.
+                let line = if on_own_line {
+                    range.start.line + 1
+                } else {
+                    range.start.line
+                };
+                result.lines.entry(line).or_default().insert(rule);
+            }
+        }
+    }
+
+    result
+});
+
+/// `// ink-tool: allow(a, b)` → `Some((false, ["a", "b"]))`
+fn parse_directive(comment: &str) -> Option<(bool, impl Iterator<Item = &str>)> {
+    let directive = comment
+        .strip_prefix("//")?
+        .trim()
+        .strip_prefix("ink-tool:")?
+        .trim();
+    let (file_wide, args) = if let Some(args) = directive.strip_prefix("allow-file") {
+        (true, args)
+    } else {
+        (false, directive.strip_prefix("allow")?)
+    };
+    let args = args.trim().strip_prefix('(')?.strip_suffix(')')?;
+    let rules = args.split(',').map(str::trim).filter(|it| !it.is_empty());
+    Some((file_wide, rules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_directive;
+
+    fn parse(comment: &str) -> Option<(bool, Vec<&str>)> {
+        parse_directive(comment).map(|(file, rules)| (file, rules.collect()))
+    }
+
+    #[test]
+    fn directives() {
+        assert_eq!(
+            parse("// ink-tool: allow(unused-knot)"),
+            Some((false, vec!["unused-knot"]))
+        );
+        assert_eq!(
+            parse("//ink-tool:allow-file( unused-knot, unused-temp )"),
+            Some((true, vec!["unused-knot", "unused-temp"]))
+        );
+        assert_eq!(parse("// ink-tool allow(unused-knot)"), None);
+        assert_eq!(parse("// just a comment"), None);
+    }
+}
@@ -1,15 +1,28 @@
+use crate::config::Config;
 use crate::lsp::salsa::{self, DocId, InkGetters, InkSetters};
 use derive_more::derive::{Display, Error, From};
 use ink_document::{DocumentEdit, InkDocument};
 use line_index::WideEncoding;
-use lsp_types::{DocumentSymbol, Position, Uri, WorkspaceSymbol};
+use lsp_types::{Diagnostic, DocumentSymbol, Position, ShowMessageParams, Uri, WorkspaceSymbol};
 use mini_milc::Cached;
 use tap::Tap as _;
 
 mod completions;
+mod fixes;
+mod formatting;
 mod goto_definition;
 mod goto_references;
+mod graph;
 mod rename;
+mod stats;
+mod symbols;
+mod tags;
+
+pub use graph::{GraphEdge, GraphNode, StoryGraph, STORY_START};
+pub use rename::RenameError;
+pub use stats::StoryStats;
+pub use symbols::{SymbolDefinition, SymbolReport, SymbolUsage};
+pub use tags::TagUsage;
 
 // This is quite an abomination, but we have to deal with it.
 type DbType = mini_milc::salsa::Salsa<
@@ -24,6 +37,8 @@ type DbType = mini_milc::salsa::Salsa<
 pub struct State {
     pub db: DbType,
     pub enc: Option<WideEncoding>,
+    /// What the user should know about, but isn't part of any response.
+    messages: Vec<ShowMessageParams>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Display, Error)]
@@ -46,7 +61,27 @@ impl State {
         Self {
             db: mini_milc::salsa_hashmap(),
             enc,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Apply the project configuration.
+    pub fn configure(&mut self, config: &Config) {
+        self.db.modify_lint_config(|lints| {
+            let changed = *lints != config.lints;
+            *lints = config.lints.clone();
+            changed
+        });
+        // The IDs that `ink-tool l10n` adds are always allowed.
+        let mut schema = config.tags.clone();
+        if !schema.is_empty() {
+            schema.patterns.push(config.l10n.id_pattern().into());
         }
+        self.db.modify_tag_schema(|tags| {
+            let changed = *tags != schema;
+            *tags = schema;
+            changed
+        });
     }
 
     pub fn common_file_prefix(&self) -> String {
@@ -135,6 +170,51 @@ impl State {
         syms
     }
 
+    /// The diagnostics of every document, ordered by URI.
+    pub fn diagnostics(&self) -> Vec<(Uri, Vec<Diagnostic>)> {
+        let mut ids = self.db.doc_ids().iter().copied().collect::<Vec<_>>();
+        ids.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        ids.into_iter()
+            .map(|id| (id.into(), self.db.file_diagnostics(id).clone()))
+            .collect()
+    }
+
+    /// The files that share a story with any of `changed`, i.e. the ones whose diagnostics
+    /// can be different now. If one of them is gone, we can't tell which stories it was in,
+    /// so that is all of them.
+    pub fn affected_files(&self, changed: &[Uri]) -> Vec<Uri> {
+        let doc_ids = self.db.doc_ids();
+        let changed = changed.iter().map(DocId::new).collect::<Vec<_>>();
+        let mut ids = if changed.iter().all(|it| doc_ids.contains(it)) {
+            let stories = self.db.stories();
+            changed
+                .into_iter()
+                .flat_map(|id| self.db.stories_of(id).iter().copied().collect::<Vec<_>>())
+                .flat_map(|root| stories[&root].resolved.keys().copied().collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        } else {
+            doc_ids.iter().copied().collect()
+        };
+        ids.sort();
+        ids.dedup();
+        ids.into_iter().map(Into::into).collect()
+    }
+
+    /// The messages for the user that piled up since the last call, for `window/showMessage`.
+    pub fn take_messages(&mut self) -> Vec<ShowMessageParams> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// The byte offsets of an LSP range in a document's text.
+    pub fn byte_range(
+        &self,
+        uri: &Uri,
+        range: lsp_types::Range,
+    ) -> Result<std::ops::Range<usize>, DocumentNotFound> {
+        let (doc, _) = self.get_doc_and_id(uri)?;
+        Ok(doc.byte_range(range))
+    }
+
     #[cfg(test)]
     fn byte_range_of(&self, uri: &Uri, loc: lsp_types::Range) -> std::ops::Range<usize> {
         // only used in tests, so we'll crash liberally!
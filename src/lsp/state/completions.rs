@@ -23,6 +23,10 @@ impl super::State {
     ) -> Result<Option<Vec<CompletionItem>>, DocumentNotFound> {
         let (doc, this_doc) = self.get_doc_and_id(uri)?;
 
+        if let Some(tags) = self.tag_completions(&doc, position) {
+            return Ok(Some(tags));
+        }
+
         let Some(spec) = self.what_to_search_for(&doc, position) else {
             return Ok(None);
         };
@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools as _;
+use lsp_types::{
+    CodeAction, CodeActionKind, Diagnostic, Position, Range, TextEdit, Uri, WorkspaceEdit,
+};
+
+use super::DocumentNotFound;
+use crate::lsp::{
+    lints::Rule,
+    salsa::{DocId, InkGetters as _, NodeFlag},
+};
+
+impl super::State {
+    /// The quick fixes for the diagnostics of the document at `uri` that touch `range`.
+    pub fn code_actions(
+        &self,
+        uri: &Uri,
+        range: Range,
+    ) -> Result<Vec<CodeAction>, DocumentNotFound> {
+        let id = DocId::new(uri);
+        if !self.db.doc_ids().contains(&id) {
+            return Err(DocumentNotFound(id));
+        }
+        let diagnostics = self
+            .db
+            .file_diagnostics(id)
+            .iter()
+            .filter(|it| it.range.start <= range.end && range.start <= it.range.end)
+            .cloned()
+            .collect_vec();
+        let actions = self
+            .fixes(id, &diagnostics)
+            .into_iter()
+            .map(|(_, action)| action)
+            .collect();
+        Ok(actions)
+    }
+
+    /// The fixes of the whole workspace that can be applied without a human looking at them:
+    /// the preferred quick fixes, as long as they don't overlap each other. Applying them
+    /// can make new ones possible (a temp that was only used by an unused temp, say).
+    pub fn safe_fixes(&self) -> Vec<(Rule, CodeAction)> {
+        let mut ids = self.db.doc_ids().iter().copied().collect_vec();
+        // The same order as the diagnostics.
+        ids.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        let mut result = Vec::new();
+        for id in ids {
+            let mut taken: Vec<Range> = Vec::new();
+            let diagnostics = self.db.file_diagnostics(id);
+            for (rule, action) in self.fixes(id, &diagnostics) {
+                if action.is_preferred != Some(true) {
+                    continue;
+                }
+                let ranges = action
+                    .edit
+                    .iter()
+                    .flat_map(|it| it.changes.iter().flatten())
+                    .flat_map(|(_, edits)| edits.iter().map(|it| it.range))
+                    .collect_vec();
+                let overlaps = ranges.iter().any(|a| {
+                    taken
+                        .iter()
+                        .any(|b| (a.start < b.end && b.start < a.end) || a == b)
+                });
+                if !overlaps {
+                    taken.extend(ranges);
+                    result.push((rule, action));
+                }
+            }
+        }
+        result
+    }
+
+    fn fixes(&self, docid: DocId, diagnostics: &[Diagnostic]) -> Vec<(Rule, CodeAction)> {
+        let doc = self.db.document(docid);
+        let text = doc.full_text();
+        let uri: Uri = docid.into();
+        // Only the first INCLUDE of a file stays, in every story it is part of.
+        let first_imports = diagnostics
+            .iter()
+            .filter(|it| Rule::of(it) == Some(Rule::DuplicateImport))
+            .filter(|it| data(it, "first").and_then(|it| it.as_bool()) != Some(false))
+            .map(|it| it.range)
+            .collect_vec();
+        let mut qualified = None;
+
+        let mut result = Vec::new();
+        for diag in diagnostics {
+            let Some(rule) = Rule::of(diag) else {
+                continue;
+            };
+            let name = &text[doc.byte_range(diag.range)];
+            let line = {
+                let start = doc.to_byte(Position::new(diag.range.start.line, 0));
+                let end = text[start..]
+                    .find('\n')
+                    .map_or(text.len(), |it| start + it + 1);
+                start..end
+            };
+            let remove_line = TextEdit::new(
+                doc.lsp_range_from_bytes(line.start, line.end),
+                String::new(),
+            );
+            let action = |title: String, edit: TextEdit, preferred: bool| CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diag.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                    ..Default::default()
+                }),
+                is_preferred: Some(preferred),
+                ..Default::default()
+            };
+
+            match rule {
+                Rule::UnusedTemp => {
+                    let Some(initializer) = temp_initializer(&text[line.clone()], name) else {
+                        continue;
+                    };
+                    // Calls might do something, so those are up to the author.
+                    let preferred = !initializer.contains('(');
+                    let title = format!("Remove unused temporary variable `{name}`");
+                    result.push((rule, action(title, remove_line, preferred)));
+                }
+                Rule::DuplicateImport => {
+                    if first_imports.contains(&diag.range)
+                        || !text[line.clone()].trim_start().starts_with("INCLUDE")
+                    {
+                        continue;
+                    }
+                    let title = "Remove duplicate INCLUDE".to_string();
+                    result.push((rule, action(title, remove_line, true)));
+                }
+                Rule::AmbiguousListItem => {
+                    let lists = data(diag, "lists")
+                        .and_then(|it| it.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|it| it.as_str())
+                        .collect_vec();
+                    // The one the project already writes out, if there is exactly one.
+                    let qualified = qualified.get_or_insert_with(|| self.qualified_usages());
+                    let used = lists
+                        .iter()
+                        .filter(|list| qualified.contains(&format!("{list}.{name}")))
+                        .collect_vec();
+                    for list in &lists {
+                        let new_text = format!("{list}.{name}");
+                        let title = format!("Qualify as `{new_text}`");
+                        let preferred = used == [list];
+                        let edit = TextEdit::new(diag.range, new_text);
+                        result.push((rule, action(title, edit, preferred)));
+                    }
+                }
+                Rule::DeprecatedSyntax => {
+                    let Some(replacement) = data(diag, "replacement").and_then(|it| it.as_str())
+                    else {
+                        continue;
+                    };
+                    let title = format!("Replace with `{replacement}`");
+                    let edit = TextEdit::new(diag.range, replacement.to_string());
+                    result.push((rule, action(title, edit, true)));
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+
+    /// The text of every qualified name that is used somewhere (`list.item`, `knot.stitch`).
+    fn qualified_usages(&self) -> HashSet<String> {
+        let mut result = HashSet::new();
+        for docid in self.db.doc_ids().iter().copied() {
+            let names = self.db.node_text(docid);
+            let usages = self
+                .db
+                .node_flags(docid)
+                .iter_flags()
+                .filter(|(_, flags)| flags.contains(NodeFlag::Usage))
+                .filter_map(|(usage, _)| names.get(usage.as_ref()))
+                .filter(|it| it.as_str().contains('.'))
+                .map(|it| it.to_string())
+                .collect_vec();
+            result.extend(usages);
+        }
+        result
+    }
+}
+
+fn data<'a>(diag: &'a Diagnostic, key: &str) -> Option<&'a serde_json::Value> {
+    diag.data.as_ref()?.get(key)
+}
+
+/// The value of a line that only declares the temp `name` (`~ temp name = value`), or
+/// `None` if the line does anything else.
+fn temp_initializer<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let declaration = line
+        .trim()
+        .strip_prefix('~')?
+        .trim_start()
+        .strip_prefix("temp")?;
+    if !declaration.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = declaration.trim_start().strip_prefix(name)?.trim_start();
+    if rest.is_empty() {
+        return Some(rest);
+    }
+    rest.strip_prefix('=').map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::{
+        lints::Rule,
+        state::tests::{new_state, uri},
+        State,
+    };
+    use indoc::indoc;
+
+    /// The text of `main.ink` after applying all safe fixes once.
+    fn fixed(state: &State) -> String {
+        let main = uri("main.ink");
+        let mut text = state.text(&main).unwrap();
+        let mut edits = state
+            .safe_fixes()
+            .into_iter()
+            .flat_map(|(_, action)| action.edit.unwrap().changes.unwrap())
+            .filter(|(uri, _)| *uri == main)
+            .flat_map(|(_, edits)| edits)
+            .collect::<Vec<_>>();
+        edits.sort_by_key(|it| std::cmp::Reverse(it.range.start));
+        for edit in edits {
+            text.replace_range(state.byte_range(&main, edit.range).unwrap(), &edit.new_text);
+        }
+        text
+    }
+
+    #[test]
+    fn unused_temps_are_removed_unless_they_call_something() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            === function f() ===
+            ~ return 1
+            === knot ===
+            ~ temp a = 1
+            ~ temp b = f()
+            ~ temp c = 2
+            Hello {c}
+            -> DONE
+        "});
+        assert_eq!(
+            fixed(&state),
+            indoc! {"
+                === function f() ===
+                ~ return 1
+                === knot ===
+                ~ temp b = f()
+                ~ temp c = 2
+                Hello {c}
+                -> DONE
+            "}
+        );
+        let rules = state
+            .safe_fixes()
+            .into_iter()
+            .map(|it| it.0)
+            .collect::<Vec<_>>();
+        assert_eq!(rules, [Rule::UnusedTemp]);
+    }
+
+    #[test]
+    fn duplicate_includes_are_dropped() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            INCLUDE other.ink
+            Hello
+            INCLUDE other.ink
+            // file: other.ink
+            VAR x = 1
+        "});
+        assert_eq!(fixed(&state), "INCLUDE other.ink\nHello\n");
+    }
+
+    #[test]
+    fn ambiguous_list_items_are_qualified_the_way_they_already_are() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            LIST doors = open, closed
+            LIST shops = open, shut
+            VAR door = doors.closed
+            ~ door = doors.open
+            ~ door = open
+        "});
+        assert!(fixed(&state).ends_with("~ door = doors.open\n~ door = doors.open\n"));
+    }
+
+    #[test]
+    fn code_actions_offer_every_list_and_prefer_the_one_in_use() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            LIST doors = open, closed
+            LIST shops = open, shut
+            VAR door = doors.closed
+            ~ door = doors.open
+            ~ door = open
+        "});
+        let item = lsp_types::Range::new(
+            lsp_types::Position::new(4, 9),
+            lsp_types::Position::new(4, 13),
+        );
+        let mut actions = state
+            .code_actions(&uri("main.ink"), item)
+            .unwrap()
+            .into_iter()
+            .map(|it| (it.title, it.is_preferred))
+            .collect::<Vec<_>>();
+        actions.sort();
+        assert_eq!(
+            actions,
+            [
+                ("Qualify as `doors.open`".to_string(), Some(true)),
+                ("Qualify as `shops.open`".to_string(), Some(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_choices_get_a_divert_arrow() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            === knot ===
+            * [Leave] -> DONE
+            *
+            You stay.
+            -> DONE
+        "});
+        assert_eq!(
+            fixed(&state),
+            "=== knot ===\n* [Leave] -> DONE\n* ->\nYou stay.\n-> DONE\n"
+        );
+    }
+}
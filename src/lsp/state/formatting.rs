@@ -0,0 +1,88 @@
+use lsp_types::{MessageType, ShowMessageParams, TextEdit, Uri};
+
+use super::{DocumentNotFound, State};
+use crate::fmt::{self, FormatConfig, Rules};
+use crate::lsp::salsa::{DocId, InkGetters as _};
+
+impl State {
+    /// The edits that format the document at `uri`. They only touch what actually changes,
+    /// and all refer to the current text (as LSP wants them to).
+    /// If parts of the document can't be formatted, that's left in a message for the user.
+    pub fn formatting(
+        &mut self,
+        uri: &Uri,
+        config: &FormatConfig,
+        rules: &Rules,
+    ) -> Result<Vec<TextEdit>, DocumentNotFound> {
+        let id = DocId::new(uri);
+        if !self.db.doc_ids().contains(&id) {
+            return Err(DocumentNotFound(id));
+        }
+        let doc = self.db.document(id);
+        let source = doc.full_text();
+        let formatted = fmt::format_with_rules(source.clone(), config, rules);
+        if !formatted.unformatted.is_empty() {
+            let lines = formatted
+                .unformatted
+                .iter()
+                .map(|it| fmt::line_number(&source, it.start).to_string())
+                .collect::<Vec<_>>();
+            self.messages.push(ShowMessageParams {
+                typ: MessageType::WARNING,
+                message: format!(
+                    "{}: Syntax errors kept some parts from being formatted (at {} {})",
+                    self.db.short_path(id).as_str(),
+                    if lines.len() == 1 { "line" } else { "lines" },
+                    lines.join(", ")
+                ),
+            });
+        }
+        let edits = fmt::edits(&source, &formatted.text)
+            .into_iter()
+            .map(|it| {
+                let range = doc.lsp_range_from_bytes(it.range.start, it.range.end);
+                TextEdit::new(range, it.text)
+            })
+            .collect();
+        Ok(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fmt::{format, FormatConfig, Rules};
+    use crate::lsp::state::tests::{new_state, uri};
+
+    #[test]
+    fn formatting_edits_give_the_formatted_text() {
+        let source = "VAR x=1\n*choice\nmore\n  - (label)gathered\n";
+        let uri = uri("main.ink");
+        let mut state = new_state();
+        state.edit(uri.clone(), source.to_string());
+
+        let edits = state
+            .formatting(&uri, &FormatConfig::default(), &Rules::default())
+            .unwrap();
+        assert!(edits.len() > 1, "{edits:?}");
+        // They refer to the original text, so the later ones have to go first.
+        for edit in edits.into_iter().rev() {
+            state.edit(uri.clone(), edit);
+        }
+        assert_eq!(state.text(&uri).unwrap(), format(source.to_string()));
+    }
+
+    #[test]
+    fn parts_that_cant_be_formatted_are_reported() {
+        let uri = uri("main.ink");
+        let mut state = new_state();
+        state.edit(uri.clone(), "Fine.\n* choice {a\n".to_string());
+
+        state
+            .formatting(&uri, &FormatConfig::default(), &Rules::default())
+            .unwrap();
+        let messages = state.take_messages();
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        assert!(messages[0].message.contains("line 2"), "{messages:?}");
+        assert!(state.take_messages().is_empty());
+    }
+}
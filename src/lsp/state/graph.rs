@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use itertools::Itertools as _;
+use serde_json::json;
+
+use crate::lsp::salsa::{Def, DocId, InkGetters as _, LinkKind, TargetKind};
+
+/// The name of the node for the top of the story, where it starts. It can't clash with any
+/// knot, because it isn't a valid ink name.
+pub const STORY_START: &str = "(start)";
+
+/// How flow moves between the knots, stitches and labelled gathers of all the stories.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoryGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    /// The address of the node, like `knot.stitch`, or [`STORY_START`], with the story's root
+    /// file in front if there is more than one story (see [`super::State::story_graph`])
+    pub id: String,
+    /// `None` for the start of a story
+    pub kind: Option<TargetKind>,
+    /// The file (relative to the common directory of all files) and 1-based line
+    pub location: Option<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphEdge {
+    /// Index into [`StoryGraph::nodes`]
+    pub from: usize,
+    /// Index into [`StoryGraph::nodes`]
+    pub to: usize,
+    pub kind: LinkKind,
+    /// The text of the choice that leads here
+    pub label: Option<String>,
+}
+
+impl super::State {
+    /// The flow graph of the whole workspace. Redirects to anything else than knots, stitches
+    /// and labelled gathers (like variables holding divert targets) are left out.
+    ///
+    /// Each story gets its own nodes. If there is more than one, their ids start with the
+    /// root file of their story, like `main.ink:knot`, so that files shared by several
+    /// stories don't mix them up.
+    pub fn story_graph(&self) -> StoryGraph {
+        let prefix = self.common_file_prefix();
+        let dir = &prefix[..prefix.rfind('/').map_or(0, |it| it + 1)];
+        let file_of = |docid: DocId| {
+            let path = docid.as_str();
+            path.strip_prefix(dir).unwrap_or(path).to_string()
+        };
+
+        let stories = self.db.stories();
+        let mut graph = StoryGraph::default();
+        for (root, imports) in stories.iter().sorted_by_key(|(root, _)| **root) {
+            let qualifier = match stories.len() {
+                1 => String::new(),
+                _ => format!("{}:", file_of(DocId::from(*root))),
+            };
+            let docids = imports.resolved.keys().copied().sorted().collect_vec();
+            let story = self.single_story_graph(&docids, &qualifier, &file_of);
+            let offset = graph.nodes.len();
+            graph.nodes.extend(story.nodes);
+            graph
+                .edges
+                .extend(story.edges.into_iter().map(|it| GraphEdge {
+                    from: it.from + offset,
+                    to: it.to + offset,
+                    ..it
+                }));
+        }
+        graph
+    }
+
+    /// The graph of the story made of `docids`, with `qualifier` in front of every id.
+    fn single_story_graph(
+        &self,
+        docids: &[DocId],
+        qualifier: &str,
+        file_of: impl Fn(DocId) -> String,
+    ) -> StoryGraph {
+        let start = GraphNode {
+            id: format!("{qualifier}{STORY_START}"),
+            kind: None,
+            location: None,
+        };
+        let mut graph = StoryGraph {
+            nodes: vec![start],
+            edges: Vec::new(),
+        };
+        let mut index = HashMap::<Def, usize>::new();
+        for docid in docids.iter().copied() {
+            let file = file_of(docid);
+            for target in self.db.flow_graph(docid).targets.iter() {
+                index.insert((docid, target.def), graph.nodes.len());
+                graph.nodes.push(GraphNode {
+                    id: format!("{qualifier}{}", target.path),
+                    kind: Some(target.kind),
+                    location: Some((file.clone(), target.range.start.line + 1)),
+                });
+            }
+        }
+
+        for docid in docids.iter().copied() {
+            for link in self.db.flow_graph(docid).links.iter() {
+                let from = match link.from {
+                    Some(def) => index.get(&(docid, def)).copied(),
+                    None => Some(0),
+                };
+                let Some(from) = from else {
+                    continue;
+                };
+                for def in self.db.definition(docid, link.to).iter() {
+                    let Some(to) = index.get(def).copied() else {
+                        continue;
+                    };
+                    let edge = GraphEdge {
+                        from,
+                        to,
+                        kind: link.kind,
+                        label: link.choice.clone().filter(|it| !it.is_empty()),
+                    };
+                    if !graph.edges.contains(&edge) {
+                        graph.edges.push(edge);
+                    }
+                }
+            }
+        }
+
+        // A story that starts with a knot has nothing to show for its start.
+        if graph.edges.iter().all(|it| it.from != 0) {
+            graph.nodes.remove(0);
+            for edge in &mut graph.edges {
+                edge.from -= 1;
+                edge.to -= 1;
+            }
+        }
+        graph
+    }
+}
+
+impl StoryGraph {
+    /// For Graphviz (`dot -Tsvg`). Tunnels are dashed, threads dotted.
+    pub fn to_dot(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = String::from("digraph story {\n");
+        for node in &self.nodes {
+            let shape = match node.kind {
+                None => "shape=circle",
+                Some(TargetKind::Knot) => "shape=box",
+                Some(TargetKind::Stitch) => "shape=box, style=rounded",
+                Some(TargetKind::Gather) => "shape=ellipse",
+            };
+            writeln!(out, "    {} [{shape}];", quote(&node.id)).unwrap();
+        }
+        for edge in &self.edges {
+            let mut attributes = Vec::new();
+            match edge.kind {
+                LinkKind::Tunnel => attributes.push("style=dashed".to_string()),
+                LinkKind::Thread => attributes.push("style=dotted".to_string()),
+                LinkKind::Divert | LinkKind::Choice => {}
+            }
+            if let Some(label) = &edge.label {
+                attributes.push(format!("label={}", quote(label)));
+            }
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attributes.join(", "))
+            };
+            writeln!(
+                out,
+                "    {} -> {}{attributes};",
+                quote(&self.nodes[edge.from].id),
+                quote(&self.nodes[edge.to].id)
+            )
+            .unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// For Mermaid flowcharts (in Markdown files, for example). Tunnels are thick, threads dotted.
+    pub fn to_mermaid(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+        let mut out = String::from("flowchart TD\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let text = quote(&node.id);
+            let shape = match node.kind {
+                None => format!("(({text}))"),
+                Some(TargetKind::Knot) => format!("[{text}]"),
+                Some(TargetKind::Stitch) => format!("({text})"),
+                Some(TargetKind::Gather) => format!("([{text}])"),
+            };
+            writeln!(out, "    n{index}{shape}").unwrap();
+        }
+        for edge in &self.edges {
+            let arrow = match edge.kind {
+                LinkKind::Divert | LinkKind::Choice => "-->",
+                LinkKind::Tunnel => "==>",
+                LinkKind::Thread => "-.->",
+            };
+            let label = match &edge.label {
+                Some(label) => format!("|{}|", quote(label)),
+                None => String::new(),
+            };
+            writeln!(out, "    n{} {arrow}{label} n{}", edge.from, edge.to).unwrap();
+        }
+        out
+    }
+
+    /// Nodes (with `id`, `kind`, `file` and `line`) and edges (with `from`, `to`, `kind`
+    /// and `label`), where edges refer to nodes by their `id`.
+    pub fn to_json(&self) -> serde_json::Value {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let (file, line) = node.location.clone().unzip();
+                json!({
+                    "id": node.id,
+                    "kind": node.kind.map_or("start", TargetKind::as_str),
+                    "file": file,
+                    "line": line,
+                })
+            })
+            .collect_vec();
+        let edges = self
+            .edges
+            .iter()
+            .map(|edge| {
+                json!({
+                    "from": self.nodes[edge.from].id,
+                    "to": self.nodes[edge.to].id,
+                    "kind": edge.kind.as_str(),
+                    "label": edge.label,
+                })
+            })
+            .collect_vec();
+        json!({ "nodes": nodes, "edges": edges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StoryGraph;
+    use crate::lsp::state::tests::new_state;
+    use indoc::indoc;
+
+    const STORY: &str = indoc! {"
+        -> start
+        === start ===
+        Hello. -> intro ->
+        * [Go \"left\"] -> left
+        * [Go right] -> right.door
+        - (wait) Waiting. <- chatter
+        -> END
+        === intro ===
+        ->->
+        === left ===
+        -> END
+        === right ===
+        = door
+        -> start.wait
+        === chatter ===
+        Blah. -> DONE
+    "};
+
+    fn graph() -> StoryGraph {
+        new_state()
+            .with_comment_separated_files(STORY)
+            .story_graph()
+    }
+
+    fn edges(graph: &StoryGraph) -> Vec<String> {
+        graph
+            .edges
+            .iter()
+            .map(|it| {
+                let label = it.label.as_deref().unwrap_or_default();
+                let from = &graph.nodes[it.from].id;
+                let to = &graph.nodes[it.to].id;
+                format!("{from} -{}-> {to} {label}", it.kind.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nodes_are_knots_stitches_and_labelled_gathers() {
+        let ids: Vec<_> = graph().nodes.into_iter().map(|it| it.id).collect();
+        assert_eq!(
+            ids,
+            [
+                "(start)",
+                "start",
+                "start.wait",
+                "intro",
+                "left",
+                "right",
+                "right.door",
+                "chatter"
+            ]
+        );
+    }
+
+    #[test]
+    fn edges_are_redirects() {
+        let graph = graph();
+        let edges = edges(&graph);
+        for expected in [
+            "(start) -divert-> start ",
+            "start -tunnel-> intro ",
+            "start -choice-> left Go \"left\"",
+            "start -choice-> right.door Go right",
+            "start.wait -thread-> chatter ",
+            "right.door -divert-> start.wait ",
+        ] {
+            assert!(
+                edges.contains(&expected.to_string()),
+                "{expected}: {edges:#?}"
+            );
+        }
+        assert_eq!(edges.len(), 6, "{edges:#?}");
+    }
+
+    #[test]
+    fn formats() {
+        let graph = graph();
+        let dot = graph.to_dot();
+        assert!(
+            dot.contains(r#"    "start" -> "left" [label="Go \"left\""];"#),
+            "{dot}"
+        );
+        assert!(
+            dot.contains(r#"    "start" -> "intro" [style=dashed];"#),
+            "{dot}"
+        );
+
+        let mermaid = graph.to_mermaid();
+        assert!(
+            mermaid.starts_with("flowchart TD\n    n0((\"(start)\"))\n"),
+            "{mermaid}"
+        );
+        assert!(
+            mermaid.contains("    n1 -->|\"Go #quot;left#quot;\"| n4\n"),
+            "{mermaid}"
+        );
+
+        let json = graph.to_json();
+        assert_eq!(json["nodes"][1]["line"], 2);
+        assert_eq!(json["nodes"][1]["file"], "main.ink");
+        assert_eq!(json["edges"][0]["from"], "(start)");
+    }
+
+    #[test]
+    fn stories_get_their_own_nodes() {
+        let graph = new_state()
+            .with_comment_separated_files(indoc! {"
+                INCLUDE shared.ink
+                -> shared_knot
+                // file: other.ink
+                INCLUDE shared.ink
+                Hello. -> shared_knot
+                // file: shared.ink
+                === shared_knot ===
+                -> END
+            "})
+            .story_graph();
+        let ids: Vec<_> = graph.nodes.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "main.ink:(start)",
+                "main.ink:shared_knot",
+                "other.ink:(start)",
+                "other.ink:shared_knot",
+            ]
+        );
+        assert_eq!(
+            edges(&graph),
+            [
+                "main.ink:(start) -divert-> main.ink:shared_knot ",
+                "other.ink:(start) -divert-> other.ink:shared_knot ",
+            ]
+        );
+    }
+}
@@ -1,7 +1,11 @@
+use crate::lsp::salsa::{Def, InkGetters as _};
 use derive_more::derive::{Display, Error};
+use ink_document::ids::ScopeId;
 use itertools::Itertools;
 use lsp_types::{TextEdit, Uri, WorkspaceEdit};
 use std::collections::HashMap;
+use tree_traversal::TreeTraversal as _;
+use type_sitter::Node as _;
 
 impl From<RenameError> for lsp_server::ResponseError {
     fn from(value: RenameError) -> Self {
@@ -46,6 +50,128 @@ impl super::State {
         let edits = WorkspaceEdit::new(edits);
         Ok(Some(edits))
     }
+
+    /// Rename what is defined as `qualified_name` (see [`super::SymbolDefinition`]) to
+    /// `new_name`, for renaming without an editor.
+    ///
+    /// Fails if the name is ambiguous, or if something in the same stories is already called
+    /// `new_name` (at the same level), or something called `new_name` is visible where the
+    /// symbol is used, so that one would shadow the other.
+    pub fn rename_by_name(
+        &self,
+        qualified_name: &str,
+        new_name: &str,
+    ) -> Result<WorkspaceEdit, RenameError> {
+        let is_name = |it: &str| {
+            it.chars().all(|c| c.is_alphanumeric() || c == '_')
+                && it.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        };
+        if !is_name(new_name) {
+            return Err(RenameError::RenameFailed(format!(
+                "`{new_name}` isn't a valid name"
+            )));
+        }
+
+        let report = self.symbol_report();
+        let mut found = report
+            .definitions
+            .iter()
+            .filter(|it| it.qualified_name == qualified_name);
+        let target = found.next().ok_or_else(|| {
+            RenameError::RenameFailed(format!("Nothing named `{qualified_name}` is defined"))
+        })?;
+        if let Some(other) = found.next() {
+            return Err(RenameError::RenameFailed(format!(
+                "`{qualified_name}` is defined more than once, in {} and {}",
+                location(&target.location),
+                location(&other.location)
+            )));
+        }
+
+        let new_qualified_name = match qualified_name.rsplit_once('.') {
+            Some((prefix, _)) => format!("{prefix}.{new_name}"),
+            None => new_name.to_string(),
+        };
+        let clash = report.definitions.iter().find(|it| {
+            it.qualified_name == new_qualified_name
+                && it
+                    .stories
+                    .iter()
+                    .any(|story| target.stories.contains(story))
+        });
+        if let Some(clash) = clash {
+            return Err(RenameError::RenameFailed(format!(
+                "`{new_qualified_name}` is already defined in {}",
+                location(&clash.location)
+            )));
+        }
+
+        let edits = self
+            .rename_symbol(
+                target.location.uri.clone(),
+                target.location.range.start,
+                new_name,
+            )?
+            .unwrap_or_default();
+        // Names elsewhere can still take over renamed usages, or be hidden by them.
+        if let Some(other) = self.visible_at_edits(&edits, new_name) {
+            return Err(RenameError::RenameFailed(format!(
+                "`{new_name}` is already defined in {}, which is visible where `{qualified_name}` is used",
+                location(&other)
+            )));
+        }
+        Ok(edits)
+    }
+
+    /// Where something called `name` is defined that is visible at one of the `edits`:
+    /// a local in a surrounding knot or stitch, or a global of the story.
+    fn visible_at_edits(&self, edits: &WorkspaceEdit, name: &str) -> Option<lsp_types::Location> {
+        let location_of = |(docid, def): Def| {
+            let range = self.db.node_locations(docid)[def];
+            lsp_types::Location::new(docid.into(), range.into())
+        };
+        for (uri, edits) in edits.changes.iter().flatten() {
+            let Ok((doc, docid)) = self.get_doc_and_id(uri) else {
+                continue;
+            };
+            let locals = self.db.local_resolutions(docid);
+            let file_scope = self.db.ink_inventory(docid).scope_id;
+            for edit in edits {
+                let byte = doc.to_byte(edit.range.start);
+                let scopes = doc
+                    .root()
+                    .depth_first::<ink_syntax::ScopeBlock>()
+                    .filter(|it| (it.start_byte()..it.end_byte()).contains(&byte))
+                    .map(ScopeId::from)
+                    .chain([file_scope]);
+                for scope in scopes {
+                    let local = locals
+                        .in_scope
+                        .get(&scope)
+                        .into_iter()
+                        .flatten()
+                        .find(|(it, _)| it.as_str() == name);
+                    if let Some((_, def)) = local {
+                        return Some(location_of((docid, *def)));
+                    }
+                }
+            }
+            for story in self.db.stories_of(docid).iter() {
+                let globals = self.db.globals(*story);
+                let global = globals.iter().find(|(it, _)| it.as_str() == name);
+                if let Some((_, defs)) = global {
+                    return Some(location_of(*defs.first()));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn location(location: &lsp_types::Location) -> String {
+    let start = location.range.start;
+    let path = location.uri.path().as_str();
+    format!("{path}:{}:{}", start.line + 1, start.character + 1)
 }
 
 #[cfg(test)]
@@ -532,4 +658,74 @@ mod tests {
         {huh.huh} {huh}
         ",
     ];
+
+    mod by_name {
+        use crate::lsp::state::{rename::RenameError, tests::new_state, State};
+        use indoc::indoc;
+
+        fn state() -> State {
+            new_state().with_comment_separated_files(indoc! {"
+                VAR hp = 1
+                VAR mp = 1
+                -> knot
+                === knot ===
+                ~ temp hp_left = hp
+                {hp} {hp_left}
+            "})
+        }
+
+        fn edit_count(state: &State, name: &str, new_name: &str) -> usize {
+            let edits = state.rename_by_name(name, new_name).unwrap();
+            edits.changes.unwrap().values().map(Vec::len).sum()
+        }
+
+        fn failure(name: &str, new_name: &str) -> String {
+            match state().rename_by_name(name, new_name) {
+                Err(RenameError::RenameFailed(message)) => message,
+                other => panic!("{other:?}"),
+            }
+        }
+
+        #[test]
+        fn renames_globals_and_locals() {
+            let state = state();
+            assert_eq!(edit_count(&state, "hp", "health"), 3);
+            assert_eq!(edit_count(&state, "knot.hp_left", "rest"), 2);
+        }
+
+        #[test]
+        fn refuses_clashes() {
+            assert_eq!(
+                failure("hp", "mp"),
+                "`mp` is already defined in /main.ink:2:5"
+            );
+        }
+
+        #[test]
+        fn refuses_names_that_would_be_shadowed() {
+            // `{hp}` in the knot would refer to the temp.
+            assert_eq!(
+                failure("hp", "hp_left"),
+                "`hp_left` is already defined in /main.ink:5:8, which is visible where `hp` is used"
+            );
+        }
+
+        #[test]
+        fn refuses_names_that_would_shadow() {
+            // The temp would hide the global `mp` in the knot.
+            assert_eq!(
+                failure("knot.hp_left", "mp"),
+                "`mp` is already defined in /main.ink:2:5, which is visible where `knot.hp_left` is used"
+            );
+        }
+
+        #[test]
+        fn refuses_unknown_and_invalid_names() {
+            assert_eq!(
+                failure("hp_left", "x"),
+                "Nothing named `hp_left` is defined"
+            );
+            assert_eq!(failure("hp", "1up"), "`1up` isn't a valid name");
+        }
+    }
 }
@@ -0,0 +1,203 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use itertools::Itertools as _;
+use lsp_types::Uri;
+
+use crate::lsp::{
+    location::TextRange,
+    salsa::{Def, DocId, FileStats, InkGetters as _, SectionKind, Stats, TargetKind},
+};
+
+/// The numbers of a story and the files in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoryStats {
+    /// The root file of the story
+    pub root: Uri,
+    /// All files added up. The minimum is the estimated minimum playthrough, see
+    /// [`super::State::story_stats`].
+    pub total: Stats,
+    /// The root file first, then the others by name
+    pub files: Vec<(Uri, FileStats)>,
+}
+
+impl super::State {
+    /// The numbers of every story in the workspace, by root file.
+    ///
+    /// The minimum playthrough of a story is the cheapest way from its start to a knot or
+    /// stitch that ends it (with `-> END`, `-> DONE`, or by not going anywhere else),
+    /// counting the minimum words of every knot and stitch along the way. Which choice
+    /// leads where isn't taken into account, so this is an estimate.
+    pub fn story_stats(&self) -> Vec<StoryStats> {
+        let stories = self.db.stories();
+        let mut result = Vec::new();
+        for (root, imports) in stories.iter().sorted_by_key(|(root, _)| **root) {
+            let root = DocId::from(*root);
+            let docids = imports
+                .resolved
+                .keys()
+                .copied()
+                .sorted_by_key(|it| (*it != root, *it))
+                .collect_vec();
+            let files = docids
+                .iter()
+                .map(|it| ((*it).into(), self.db.file_stats(*it).clone()))
+                .collect_vec();
+            let mut total = Stats::default();
+            for (_, stats) in &files {
+                total.add(&stats.total());
+            }
+            total.min_words = self.min_playthrough(&docids);
+            result.push(StoryStats {
+                root: root.into(),
+                total,
+                files,
+            });
+        }
+        result
+    }
+
+    fn min_playthrough(&self, docids: &[DocId]) -> usize {
+        // Node 0 is the start: the tops of all files, since INCLUDE pulls them in.
+        let mut costs = vec![0];
+        let mut ends = vec![false];
+        let mut index = HashMap::<Def, usize>::new();
+        let mut ranges = Vec::<(DocId, TextRange, usize)>::new();
+        let mut first_stitch = HashMap::<usize, usize>::new();
+        for docid in docids.iter().copied() {
+            let stats = self.db.file_stats(docid);
+            costs[0] += stats.top.min_words;
+            let exits = self.db.flow_exits(docid);
+            let mut knot = None;
+            for section in &stats.sections {
+                if section.kind == SectionKind::Function {
+                    continue;
+                }
+                let node = costs.len();
+                costs.push(section.stats.min_words);
+                index.insert((docid, section.def), node);
+                ranges.push((docid, section.range, node));
+                match section.kind {
+                    SectionKind::Stitch => {
+                        if let Some(knot) = knot {
+                            first_stitch.entry(knot).or_insert(node);
+                        }
+                    }
+                    _ => knot = Some(node),
+                }
+                // A knot's exits include those of its stitches.
+                let is_end = exits
+                    .iter()
+                    .filter(|it| it.def == section.def)
+                    .flat_map(|it| it.endings.iter())
+                    .any(|(range, _)| {
+                        !stats.sections.iter().any(|it| {
+                            it.kind == SectionKind::Stitch
+                                && it.def != section.def
+                                && section.range.contains(&it.range)
+                                && it.range.contains(range)
+                        })
+                    });
+                ends.push(is_end);
+            }
+        }
+        // Labelled gathers are part of their knot or stitch.
+        for docid in docids.iter().copied() {
+            for target in self.db.flow_graph(docid).targets.iter() {
+                if target.kind != TargetKind::Gather {
+                    continue;
+                }
+                let section = ranges
+                    .iter()
+                    .filter(|(file, range, _)| *file == docid && range.contains(&target.range))
+                    .map(|(_, _, node)| *node)
+                    .last();
+                if let Some(node) = section {
+                    index.insert((docid, target.def), node);
+                }
+            }
+        }
+
+        let mut edges = vec![Vec::new(); costs.len()];
+        for docid in docids.iter().copied() {
+            for link in self.db.flow_graph(docid).links.iter() {
+                let from = match link.from {
+                    Some(def) => index.get(&(docid, def)).copied(),
+                    None => Some(0),
+                };
+                let Some(from) = from else {
+                    continue;
+                };
+                for def in self.db.definition(docid, link.to).iter() {
+                    if let Some(to) = index.get(def).copied() {
+                        edges[from].push(to);
+                    }
+                }
+            }
+        }
+        // A knot that doesn't go anywhere by itself starts with its first stitch.
+        for (knot, stitch) in first_stitch {
+            if edges[knot].is_empty() && !ends[knot] {
+                edges[knot].push(stitch);
+            }
+        }
+
+        let mut best = vec![usize::MAX; costs.len()];
+        let mut queue = BinaryHeap::from([Reverse((costs[0], 0))]);
+        best[0] = costs[0];
+        while let Some(Reverse((cost, node))) = queue.pop() {
+            if cost > best[node] {
+                continue;
+            }
+            if ends[node] || edges[node].is_empty() {
+                return cost;
+            }
+            for next in edges[node].iter().copied() {
+                let cost = cost + costs[next];
+                if cost < best[next] {
+                    best[next] = cost;
+                    queue.push(Reverse((cost, next)));
+                }
+            }
+        }
+        // Every way goes round in circles.
+        costs[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::state::tests::{new_state, uri};
+    use indoc::indoc;
+
+    #[test]
+    fn the_minimum_playthrough_takes_the_shortest_way() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            INCLUDE other.ink
+            You wake up.
+            * Get up -> long_day
+            * Stay in bed -> short_day
+            // file: other.ink
+            === long_day ===
+            You go to work and have a very long day.
+            -> END
+            === short_day ===
+            You sleep.
+            -> sleep_more
+            = sleep_more
+            And sleep some more.
+            -> DONE
+        "});
+        let stories = state.story_stats();
+        assert_eq!(stories.len(), 1);
+        let story = &stories[0];
+        assert_eq!(story.root, uri("main.ink"));
+        assert_eq!(story.files.len(), 2);
+        assert_eq!(story.total.words, 3 + 2 + 3 + 10 + 2 + 4);
+        assert_eq!(story.total.choices, 2);
+        // "You wake up. Get up" and the long day are 15 words, sleeping in is 11.
+        assert_eq!(story.total.min_words, 3 + 2 + 2 + 4);
+    }
+}
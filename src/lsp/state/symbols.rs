@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use enumflags2::BitFlags;
+use itertools::Itertools as _;
+use lsp_types::{Location, Uri};
+
+use crate::lsp::{
+    location::TextRange,
+    salsa::{match_flags, Def, DocId, InkGetters as _, NodeFlag},
+};
+
+/// Every definition in the workspace, and every usage with what it resolves to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolReport {
+    pub definitions: Vec<SymbolDefinition>,
+    pub usages: Vec<SymbolUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolDefinition {
+    /// Including the knot and stitch it is in, if it is local to them (`knot.stitch.label`,
+    /// `knot.temp_var`)
+    pub qualified_name: String,
+    /// `knot`, `stitch`, `var` and so on
+    pub kind: &'static str,
+    pub location: Location,
+    /// The knot (or `knot.stitch`) this is defined in
+    pub parent: Option<String>,
+    /// The root files of the stories this is part of
+    pub stories: Vec<Uri>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolUsage {
+    /// As written, like `knot.stitch`
+    pub name: String,
+    /// `read`, `assignment`, `call`, `divert`, `tunnel` or `thread`
+    pub kind: &'static str,
+    pub location: Location,
+    /// Indexes into [`SymbolReport::definitions`]. Empty if the name couldn't be resolved.
+    pub resolves_to: Vec<usize>,
+}
+
+impl super::State {
+    /// All the names that are defined and used, in file order. Built-ins (like `END` or
+    /// `CHOICE_COUNT`) aren't included.
+    pub fn symbol_report(&self) -> SymbolReport {
+        let mut docids = self.db.doc_ids().iter().copied().collect_vec();
+        docids.sort();
+
+        let mut report = SymbolReport::default();
+        let mut index = HashMap::<Def, usize>::new();
+        for docid in docids.iter().copied() {
+            let flags = self.db.node_flags(docid);
+            let names = self.db.node_text(docid);
+            let locations = self.db.node_locations(docid);
+            let sections = self.sections(docid);
+            let stories = self
+                .db
+                .stories_of(docid)
+                .iter()
+                .map(|it| DocId::from(*it).into())
+                .collect_vec();
+            let definitions = flags
+                .iter_definitions()
+                .filter(|(_, flags)| !flags.contains(NodeFlag::Builtin))
+                .filter_map(|(def, flags)| {
+                    Some((def, flags, *locations.get_by_left(def.as_ref())?))
+                })
+                .sorted_by_key(|(_, _, range)| range.start);
+            for (def, flags, range) in definitions {
+                let name = names.get(def.as_ref()).map_or("", |it| it.as_str());
+                // A knot or stitch contains its own name, but isn't its own parent.
+                let parent = sections
+                    .iter()
+                    .filter(|(_, block, name_range)| block.contains(&range) && *name_range != range)
+                    .map(|(name, _, _)| name.clone())
+                    .last();
+                let qualified_name = match &parent {
+                    Some(parent) if flags.contains(NodeFlag::Local) => format!("{parent}.{name}"),
+                    _ => name.to_string(),
+                };
+                index.insert((docid, def), report.definitions.len());
+                report.definitions.push(SymbolDefinition {
+                    qualified_name,
+                    kind: definition_kind(flags),
+                    location: Location::new(docid.into(), range.into()),
+                    parent,
+                    stories: stories.clone(),
+                });
+            }
+        }
+
+        for docid in docids.iter().copied() {
+            let flags = self.db.node_flags(docid);
+            let names = self.db.node_text(docid);
+            let locations = self.db.node_locations(docid);
+            let usages = flags
+                .iter_flags()
+                .filter(|(_, flags)| flags.contains(NodeFlag::Usage))
+                .filter(|(_, flags)| !flags.intersects(NodeFlag::Definition | NodeFlag::Builtin))
+                .filter_map(|(usage, flags)| {
+                    Some((usage, flags, *locations.get_by_left(usage.as_ref())?))
+                })
+                .sorted_by_key(|(_, _, range)| range.start);
+            for (usage, flags, range) in usages {
+                let resolves_to = self
+                    .db
+                    .definition(docid, usage)
+                    .iter()
+                    .filter_map(|def| index.get(def).copied())
+                    .collect();
+                report.usages.push(SymbolUsage {
+                    name: names
+                        .get(usage.as_ref())
+                        .map_or_else(String::new, |it| it.to_string()),
+                    kind: usage_kind(flags),
+                    location: Location::new(docid.into(), range.into()),
+                    resolves_to,
+                });
+            }
+        }
+        report
+    }
+
+    /// The knots and stitches of a file: their (qualified) name, the range of their block
+    /// and the range of their name.
+    fn sections(&self, docid: DocId) -> Vec<(String, TextRange, TextRange)> {
+        let inventory = self.db.ink_inventory(docid);
+        let locations = self.db.node_locations(docid);
+        let mut result = Vec::new();
+        for section in &inventory.sections {
+            let name = section.name.to_string();
+            for sub in &section.subsections {
+                result.push((
+                    format!("{name}.{}", sub.name),
+                    locations[sub.scope_id],
+                    locations[sub.name_id],
+                ));
+            }
+            result.push((
+                name,
+                locations[section.scope_id],
+                locations[section.name_id],
+            ));
+        }
+        // Outer ones first, so that the last one containing something is the innermost one.
+        result.sort_by_key(|(_, block, _)| (block.start, std::cmp::Reverse(block.end)));
+        result
+    }
+}
+
+fn definition_kind(flags: BitFlags<NodeFlag>) -> &'static str {
+    use NodeFlag::*;
+    match_flags!(match (flags) {
+        Function => "function",
+        Knot => "knot",
+        Stitch => "stitch",
+        Label => "label",
+        External => "external",
+        Param => "param",
+        Temp => "temp",
+        Const => "const",
+        Var => "var",
+        ListItem => "list item",
+        List => "list",
+        _ => "unknown",
+    })
+}
+
+fn usage_kind(flags: BitFlags<NodeFlag>) -> &'static str {
+    use NodeFlag::*;
+    match_flags!(match (flags) {
+        Tunnel => "tunnel",
+        Thread => "thread",
+        Divert => "divert",
+        Call => "call",
+        Assignment => "assignment",
+        _ => "read",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::state::tests::new_state;
+    use indoc::indoc;
+
+    #[test]
+    fn definitions_and_usages() {
+        let state = new_state().with_comment_separated_files(indoc! {"
+            VAR score = 0
+            -> start
+            === start ===
+            ~ temp bonus = 1
+            ~ score = score + bonus
+            - (loop) -> loop
+        "});
+        let report = state.symbol_report();
+
+        let definitions = report
+            .definitions
+            .iter()
+            .map(|it| (it.qualified_name.as_str(), it.kind, it.parent.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            [
+                ("score", "var", None),
+                ("start", "knot", None),
+                ("start.bonus", "temp", Some("start")),
+                ("start.loop", "label", Some("start")),
+            ]
+        );
+        assert!(report.definitions[0].stories[0]
+            .as_str()
+            .ends_with("main.ink"));
+
+        let usages = report
+            .usages
+            .iter()
+            .map(|it| {
+                let resolved = it
+                    .resolves_to
+                    .iter()
+                    .map(|it| report.definitions[*it].qualified_name.as_str())
+                    .collect::<Vec<_>>();
+                (it.name.as_str(), it.kind, resolved)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            usages,
+            [
+                ("start", "divert", vec!["start"]),
+                ("score", "assignment", vec!["score"]),
+                ("score", "read", vec!["score"]),
+                ("bonus", "read", vec!["start.bonus"]),
+                ("loop", "divert", vec!["start.loop"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools as _;
+use lsp_types::{CompletionItem, CompletionItemKind, CompletionTextEdit, Position, TextEdit};
+use type_sitter::Node as _;
+
+use super::*;
+
+/// How a tag is used across the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagUsage {
+    pub locations: Vec<lsp_types::Location>,
+    /// Whether the tag fits the project's tag schema
+    pub valid: bool,
+}
+
+impl super::State {
+    /// All the (non-dynamic) tags in the workspace, by their text.
+    pub fn tag_report(&self) -> BTreeMap<String, TagUsage> {
+        let schema = self.db.tag_schema();
+        let mut report = BTreeMap::<String, TagUsage>::new();
+        for docid in self.db.doc_ids().iter().copied() {
+            for tag in self.db.file_tags(docid).iter().filter(|it| !it.dynamic) {
+                let usage = report.entry(tag.text.clone()).or_insert_with(|| TagUsage {
+                    locations: Vec::new(),
+                    valid: schema.check(&tag.text).is_ok(),
+                });
+                usage
+                    .locations
+                    .push(lsp_types::Location::new(docid.into(), tag.range.into()));
+            }
+        }
+        report
+    }
+
+    /// Completions for the tag at the cursor. `None` if the cursor isn't in a tag, or no
+    /// tag starts with what has been typed.
+    pub(super) fn tag_completions(
+        &self,
+        doc: &InkDocument,
+        position: Position,
+    ) -> Option<Vec<CompletionItem>> {
+        let cursor = doc.to_byte(position);
+        let mut node = doc
+            .root()
+            .raw()
+            .descendant_for_byte_range(cursor.saturating_sub(1), cursor)?;
+        while node.kind() != ink_syntax::Tag::KIND {
+            node = node.parent()?;
+        }
+        let hash = node.start_byte() + doc.text(node.byte_range()).find('#')?;
+        if cursor <= hash {
+            return None;
+        }
+        let typed = doc.text(hash + 1..cursor);
+        let prefix = typed.trim_start();
+        // Past the tag, for example in a divert after it.
+        if prefix.contains(char::is_whitespace) || prefix.contains("->") {
+            return None;
+        }
+        if typed.contains(['{', '}', '[', ']']) {
+            return None;
+        }
+        let range = doc.lsp_range_from_bytes(hash + 1, cursor);
+
+        let schema = self.db.tag_schema();
+        let from_schema = schema.patterns.iter().flat_map(|pattern| {
+            pattern
+                .expansions(&schema.values)
+                .unwrap_or_else(|| vec![pattern.literal_prefix()])
+                .into_iter()
+                .map(|tag| (tag, Some(pattern.as_str().to_string())))
+        });
+        // The tag being typed is in there too, but suggesting it back is no help.
+        let editing = doc.text(hash + 1..node.end_byte()).trim();
+        let used = self.db.valid_tags();
+        let from_usage = used
+            .iter()
+            .filter(|tag| tag.as_str() != editing)
+            .map(|tag| (tag.clone(), None));
+
+        let items = from_schema
+            .chain(from_usage)
+            .filter(|(tag, _)| !tag.is_empty() && tag.starts_with(prefix))
+            .unique_by(|(tag, _)| tag.clone())
+            .map(|(tag, pattern)| CompletionItem {
+                label: tag.clone(),
+                kind: Some(CompletionItemKind::VALUE),
+                detail: pattern,
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: tag,
+                })),
+                ..Default::default()
+            })
+            .collect_vec();
+
+        (!items.is_empty()).then_some(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::state::tests::{new_state, text_with_caret, uri};
+    use indoc::indoc;
+    use itertools::Itertools as _;
+
+    fn labels(text: &str) -> Option<Vec<String>> {
+        let (text, caret) = text_with_caret(text);
+        let mut state = new_state();
+        state.edit(uri("main.ink"), text.as_str());
+        let items = state.completions(&uri("main.ink"), caret).unwrap()?;
+        Some(items.into_iter().map(|it| it.label).sorted().collect())
+    }
+
+    #[test]
+    fn completes_tags_used_elsewhere() {
+        let labels = labels(indoc! {"
+            Hello. #mood:happy
+            Bye. #mood:@
+        "});
+        assert_eq!(labels, Some(vec!["mood:happy".to_string()]));
+    }
+
+    #[test]
+    fn diverts_after_tags_complete_as_usual() {
+        let labels = labels(indoc! {"
+            === knot ===
+            * [Go] #mood:happy -> kn@
+        "})
+        .unwrap_or_default();
+        assert!(labels.contains(&"knot".to_string()), "{labels:?}");
+    }
+}
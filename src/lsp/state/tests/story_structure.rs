@@ -7,7 +7,10 @@ use indoc::indoc;
 
 use crate::lsp::{
     salsa::{DocId, InkGetters},
-    state::{tests::new_state, State},
+    state::{
+        tests::{new_state, uri},
+        State,
+    },
 };
 
 /// Some helpers to make the tests more readable.
@@ -39,6 +42,14 @@ impl State {
         }
         structure
     }
+
+    fn affected(&self, changed: &[&str]) -> BTreeSet<String> {
+        let changed = changed.iter().map(|it| uri(it)).collect::<Vec<_>>();
+        self.affected_files(&changed)
+            .iter()
+            .map(|it| self.path(DocId::new(it)).clone())
+            .collect()
+    }
 }
 
 fn structure<'a>(
@@ -319,3 +330,46 @@ fn cyclic_and_non_cyclic_imports() {
             ])
     );
 }
+
+#[test]
+fn affected_files_are_the_ones_in_the_same_story() {
+    let state = new_state().with_comment_separated_files(indoc! {"
+        // file: a.ink
+        INCLUDE shared.ink
+        INCLUDE a_only.ink
+
+        // file: b.ink
+        INCLUDE shared.ink
+
+        // file: shared.ink
+        Everybody sees me.
+
+        // file: a_only.ink
+        Only a sees me.
+    "});
+
+    check!(state.affected(&["a_only.ink"]) == set(["a.ink", "a_only.ink", "shared.ink"]));
+    check!(state.affected(&["b.ink"]) == set(["b.ink", "shared.ink"]));
+    check!(state.affected(&["shared.ink"]) == set(["a.ink", "a_only.ink", "b.ink", "shared.ink"]));
+}
+
+#[test]
+fn any_file_is_affected_by_a_deleted_one() {
+    let mut state = new_state().with_comment_separated_files(indoc! {"
+        // file: a.ink
+        INCLUDE gone.ink
+
+        // file: b.ink
+        Nothing to see here.
+
+        // file: gone.ink
+        Soon I won't be.
+    "});
+    state.forget(uri("gone.ink")).unwrap();
+
+    check!(state.affected(&["gone.ink"]) == set(["a.ink", "b.ink"]));
+}
+
+fn set<'a>(them: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+    them.into_iter().map(ToString::to_string).collect()
+}
@@ -0,0 +1,246 @@
+//! Describes which `#tags` a project uses.
+//!
+//! Configured in the `[tags]` section of `ink-tool.toml`:
+//!
+//! ``` toml
+//! [tags]
+//! patterns = ["speaker:<name>", "sfx:<file>", "portrait:<speaker>:<mood>"]
+//!
+//! [tags.values] # optional: restrict what a placeholder may be
+//! mood = ["happy", "sad", "angry"]
+//! ```
+//!
+//! A tag is split into segments at `:`. Literal segments must match exactly, a
+//! `<placeholder>` segment matches any non-empty text (or one of its listed values).
+//! Without any patterns, every tag is allowed.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use itertools::Itertools as _;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagSchema {
+    pub patterns: Vec<TagPattern>,
+    /// Placeholder name → allowed values
+    pub values: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub struct TagPattern {
+    text: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+impl From<String> for TagPattern {
+    fn from(text: String) -> Self {
+        let segments = tag_segments(&text)
+            .map(|segment| {
+                match segment
+                    .strip_prefix('<')
+                    .and_then(|it| it.strip_suffix('>'))
+                {
+                    Some(name) => Segment::Placeholder(name.to_string()),
+                    None => Segment::Literal(segment.to_string()),
+                }
+            })
+            .collect();
+        Self { text, segments }
+    }
+}
+
+impl TagPattern {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// The part of the pattern before the first placeholder (e.g. `portrait:` for
+    /// `portrait:<speaker>:<mood>`).
+    pub fn literal_prefix(&self) -> String {
+        let literals = self
+            .segments
+            .iter()
+            .map_while(|it| match it {
+                Segment::Literal(literal) => Some(literal.as_str()),
+                Segment::Placeholder(_) => None,
+            })
+            .collect_vec();
+        if literals.len() == self.segments.len() {
+            literals.join(":")
+        } else {
+            literals.iter().map(|it| format!("{it}:")).join("")
+        }
+    }
+
+    /// All the tags this pattern can produce, if every placeholder has a list of values.
+    pub fn expansions(&self, values: &BTreeMap<String, BTreeSet<String>>) -> Option<Vec<String>> {
+        let choices = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => Some(vec![literal.as_str()]),
+                Segment::Placeholder(name) => values
+                    .get(name)
+                    .map(|it| it.iter().map(String::as_str).collect()),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(
+            choices
+                .into_iter()
+                .multi_cartesian_product()
+                .map(|it| it.join(":"))
+                .collect(),
+        )
+    }
+}
+
+/// Why a tag doesn't fit the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagMismatch<'a> {
+    /// No pattern looks remotely like it.
+    Unknown,
+    /// A pattern starts like this tag, but has a different number of segments.
+    Shape { expected: &'a TagPattern },
+    /// The tag has the right shape, but a placeholder has a value that isn't allowed.
+    Value {
+        pattern: &'a TagPattern,
+        placeholder: &'a str,
+        value: String,
+    },
+}
+
+impl TagSchema {
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Check a tag (without the leading `#`) against the schema.
+    pub fn check(&self, tag: &str) -> Result<(), TagMismatch<'_>> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let segments = tag_segments(tag).collect_vec();
+        let mut best = TagMismatch::Unknown;
+
+        for pattern in &self.patterns {
+            let same_start = match (pattern.segments.first(), segments.first()) {
+                (Some(Segment::Literal(expected)), Some(actual)) => expected == *actual,
+                _ => false,
+            };
+
+            if pattern.segments.len() != segments.len() {
+                if same_start && best == TagMismatch::Unknown {
+                    best = TagMismatch::Shape { expected: pattern };
+                }
+                continue;
+            }
+
+            let mut bad_value = None;
+            let fits = pattern
+                .segments
+                .iter()
+                .zip(&segments)
+                .all(|(expected, actual)| match expected {
+                    Segment::Literal(literal) => literal == *actual,
+                    Segment::Placeholder(_) if actual.is_empty() => false,
+                    Segment::Placeholder(name) => {
+                        if let Some(allowed) = self.values.get(name) {
+                            if !allowed.contains(*actual) && bad_value.is_none() {
+                                bad_value = Some((name.as_str(), actual.to_string()));
+                            }
+                        }
+                        true
+                    }
+                });
+
+            match (fits, bad_value) {
+                (true, None) => return Ok(()),
+                (true, Some((placeholder, value))) => {
+                    best = TagMismatch::Value {
+                        pattern,
+                        placeholder,
+                        value,
+                    }
+                }
+                (false, _) if same_start && best == TagMismatch::Unknown => {
+                    best = TagMismatch::Shape { expected: pattern }
+                }
+                (false, _) => {}
+            }
+        }
+
+        Err(best)
+    }
+}
+
+/// The text of a tag, without the `#` and surrounding whitespace.
+pub fn tag_text(node_text: &str) -> &str {
+    let text = node_text.trim();
+    text.strip_prefix('#').unwrap_or(text).trim()
+}
+
+fn tag_segments(tag: &str) -> impl Iterator<Item = &str> {
+    tag.split(':').map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> TagSchema {
+        toml::from_str(
+            r#"
+            patterns = ["speaker:<name>", "portrait:<speaker>:<mood>", "ending"]
+            values = { mood = ["happy", "sad"] }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn matching_tags() {
+        let schema = schema();
+        assert_eq!(schema.check("speaker:alice"), Ok(()));
+        assert_eq!(schema.check("speaker: alice"), Ok(()));
+        assert_eq!(schema.check("portrait:bob:sad"), Ok(()));
+        assert_eq!(schema.check("ending"), Ok(()));
+    }
+
+    #[test]
+    fn mismatching_tags() {
+        let schema = schema();
+        assert_eq!(schema.check("sfx:bang"), Err(TagMismatch::Unknown));
+        assert!(matches!(
+            schema.check("portrait:bob"),
+            Err(TagMismatch::Shape { expected }) if expected.as_str() == "portrait:<speaker>:<mood>"
+        ));
+        assert!(matches!(
+            schema.check("portrait:bob:grumpy"),
+            Err(TagMismatch::Value { placeholder: "mood", value, .. }) if value == "grumpy"
+        ));
+        assert!(matches!(
+            schema.check("speaker:"),
+            Err(TagMismatch::Shape { .. })
+        ));
+    }
+
+    #[test]
+    fn completions_from_patterns() {
+        let schema = schema();
+        assert_eq!(schema.patterns[0].literal_prefix(), "speaker:");
+        assert_eq!(schema.patterns[2].literal_prefix(), "ending");
+        assert_eq!(
+            schema.patterns[1].expansions(&schema.values),
+            None, // `speaker` has no values
+        );
+    }
+}
@@ -13,3 +13,20 @@ fn emoji() {
     let output = ink_tool::fmt::format(input);
     pretty_assertions::assert_str_eq!(output, expected);
 }
+
+#[test]
+fn in_parallel() {
+    use ink_tool::fmt::{FormatConfig, Formatter, Rules};
+
+    let formatter = Formatter::new(&FormatConfig::default(), &Rules::default());
+    let names = vec!["the_intercept.ink", "ld41-emoji.ink"];
+    let outputs = formatter.parallel(names.clone(), |formatter, name| {
+        let input = std::fs::read_to_string(format!("examples/input/{name}")).unwrap();
+        formatter.format(input).text
+    });
+    for (name, output) in names.into_iter().zip(outputs) {
+        let output = output.unwrap();
+        let expected = std::fs::read_to_string(format!("examples/output/{name}")).unwrap();
+        pretty_assertions::assert_str_eq!(output, expected, "{name}");
+    }
+}